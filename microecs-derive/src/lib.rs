@@ -0,0 +1,218 @@
+//! Derives for the traits of `microecs`, re-exported by it with the `derive` feature.
+//!
+//! The macros parse their input with `proc_macro` alone and support structs without where
+//! clauses, which covers the component and bundle types they are meant for.
+
+mod parse;
+
+use parse::{parse, Fields, Input};
+use proc_macro::TokenStream;
+
+/// Implements `Component` with the storage given by `#[component(storage = Type)]`, the type
+/// then registered through `ChunkBuilder::with_custom_storage`.
+#[proc_macro_derive(Component, attributes(component))]
+pub fn derive_component(input: TokenStream) -> TokenStream {
+    expand(input, |input| {
+        no_generics(input, "Component")?;
+        let storage = input
+            .attributes
+            .iter()
+            .filter(|(name, _)| name == "component")
+            .find_map(|(_, arguments)| storage_argument(arguments.clone()))
+            .ok_or("missing `#[component(storage = Type)]`")?;
+        Ok(format!(
+            "impl ::microecs::prelude::Component for {} {{ type Storage = {storage}; }}",
+            input.name
+        ))
+    })
+}
+
+/// Implements `Bundle` for a struct, inserting every field as a component.
+#[proc_macro_derive(Bundle)]
+pub fn derive_bundle(input: TokenStream) -> TokenStream {
+    expand(input, |input| {
+        no_generics(input, "Bundle")?;
+        let types = input.fields.types();
+        if types.is_empty() {
+            return Err("a bundle needs at least one field".into());
+        }
+        let tuple = format!("({},)", types.join(", "));
+        let values = format!("({},)", input.fields.accessors("self").join(", "));
+        Ok(format!(
+            "impl ::microecs::prelude::Bundle for {name} {{
+                type Columns<'__a> = <{tuple} as ::microecs::prelude::Bundle>::Columns<'__a>;
+
+                fn columns(
+                    chunk: &::microecs::prelude::Chunk,
+                ) -> ::core::result::Result<Self::Columns<'_>, ::microecs::Error> {{
+                    <{tuple} as ::microecs::prelude::Bundle>::columns(chunk)
+                }}
+
+                #[inline]
+                fn write(self, columns: &mut Self::Columns<'_>, index: usize) {{
+                    ::microecs::prelude::Bundle::write({values}, columns, index)
+                }}
+            }}",
+            name = input.name
+        ))
+    })
+}
+
+/// Implements `SystemParam` for a struct whose fields are all system parameters, e.g.
+/// `struct Physics<'a> { positions: ComponentsMut<'a, Position>, time: ResourceRef<'a, Time> }`.
+/// The struct may have a single lifetime parameter and no other generics.
+#[proc_macro_derive(SystemParam)]
+pub fn derive_system_param(input: TokenStream) -> TokenStream {
+    expand(input, |input| {
+        let lifetime = input.generics.replace(' ', "");
+        if !(lifetime.is_empty() || lifetime.starts_with('\'') && !lifetime.contains([',', ':'])) {
+            return Err(
+                "`SystemParam` can only be derived for structs with a single lifetime".into(),
+            );
+        }
+        let (generics, param) = match lifetime.is_empty() {
+            true => (String::new(), input.name.clone()),
+            false => (format!("<{lifetime}>"), format!("{}<'__p>", input.name)),
+        };
+        let types = input.fields.types();
+        let access = types
+            .iter()
+            .map(|ty| format!("<{ty} as ::microecs::prelude::SystemParam>::access(access);"))
+            .collect::<String>();
+        let states = types
+            .iter()
+            .map(|ty| format!("<{ty} as ::microecs::prelude::SystemParam>::State,"))
+            .collect::<String>();
+        let values = types.iter().enumerate().map(|(i, ty)| {
+            format!(
+                "<{ty} as ::microecs::prelude::SystemParam>::get_param(\
+                    &mut state.{i}, chunk, resources, command_queue)?"
+            )
+        });
+        let value = match &input.fields {
+            Fields::Named(fields) => {
+                let fields = fields
+                    .iter()
+                    .zip(values)
+                    .map(|((name, _), value)| format!("{name}: {value},"))
+                    .collect::<String>();
+                format!("{} {{ {fields} }}", input.name)
+            }
+            Fields::Unnamed(_) => {
+                format!("{}({})", input.name, values.collect::<Vec<_>>().join(", "))
+            }
+            Fields::Unit => input.name.clone(),
+        };
+        Ok(format!(
+            "impl{generics} ::microecs::prelude::SystemParam for {name}{generics} {{
+                type Param<'__p> = {param};
+                type State = ({states});
+
+                #[allow(unused_variables)]
+                fn access(access: &mut ::microecs::prelude::Access) {{
+                    {access}
+                }}
+
+                #[allow(unused_variables)]
+                fn get_param<'__p>(
+                    state: &mut Self::State,
+                    chunk: &'__p ::microecs::prelude::Chunk,
+                    resources: &'__p ::microecs::prelude::Resources,
+                    command_queue: &'__p ::microecs::prelude::CommandQueue,
+                ) -> ::core::result::Result<Self::Param<'__p>, ::microecs::Error> {{
+                    ::core::result::Result::Ok({value})
+                }}
+            }}",
+            name = input.name
+        ))
+    })
+}
+
+fn expand(input: TokenStream, f: impl FnOnce(&Input) -> Result<String, String>) -> TokenStream {
+    let code = parse(input)
+        .and_then(|input| f(&input))
+        .unwrap_or_else(|error| format!("::core::compile_error!({error:?});"));
+    code.parse().unwrap()
+}
+
+fn no_generics(input: &Input, derive: &str) -> Result<(), String> {
+    match input.generics.is_empty() {
+        true => Ok(()),
+        false => Err(format!("`{derive}` cannot be derived for generic types")),
+    }
+}
+
+/// Returns the type in `storage = Type`.
+fn storage_argument(arguments: TokenStream) -> Option<String> {
+    let arguments = arguments.to_string();
+    let (key, value) = arguments.split_once('=')?;
+    (key.trim() == "storage").then(|| value.trim().to_string())
+}
+
+/// The inputs the derives reject with an error, e.g. enums:
+///
+/// ```compile_fail
+/// #[derive(microecs::prelude::Component)]
+/// #[component(storage = std::collections::BTreeMap<usize, Self>)]
+/// enum Direction {
+///     Left,
+///     Right,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// #[derive(microecs::prelude::Bundle)]
+/// enum Loot {
+///     Gold(u32),
+/// }
+/// ```
+///
+/// Generic components and bundles:
+///
+/// ```compile_fail
+/// #[derive(microecs::prelude::Component)]
+/// #[component(storage = std::collections::BTreeMap<usize, Self>)]
+/// struct Wrapper<T>(T);
+/// ```
+///
+/// ```compile_fail
+/// #[derive(microecs::prelude::Bundle)]
+/// struct Pair<T: 'static> {
+///     first: T,
+///     second: u32,
+/// }
+/// ```
+///
+/// Components without a storage and bundles without fields:
+///
+/// ```compile_fail
+/// #[derive(microecs::prelude::Component)]
+/// struct Health(u32);
+/// ```
+///
+/// ```compile_fail
+/// #[derive(microecs::prelude::Bundle)]
+/// struct Empty;
+/// ```
+///
+/// System params with type parameters or where clauses:
+///
+/// ```compile_fail
+/// use microecs::prelude::*;
+///
+/// #[derive(SystemParam)]
+/// struct Values<'a, T: 'static> {
+///     values: ComponentsRef<'a, T>,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// use microecs::prelude::*;
+///
+/// #[derive(SystemParam)]
+/// struct Values<'a>(ComponentsRef<'a, u32>)
+/// where
+///     u32: Copy;
+/// ```
+#[cfg(doctest)]
+struct Rejected;
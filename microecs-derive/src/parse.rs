@@ -0,0 +1,195 @@
+use proc_macro::{Delimiter, Group, Spacing, TokenStream, TokenTree};
+
+/// The parts of a struct definition the derives need. Types and generics are kept as source
+/// text, since the generated code is assembled as a string.
+pub struct Input {
+    pub name: String,
+    /// The generic parameters without the angle brackets, empty if there are none.
+    pub generics: String,
+    pub fields: Fields,
+    /// The contents of every `#[name(...)]` attribute of the struct, keyed by `name`.
+    pub attributes: Vec<(String, TokenStream)>,
+}
+
+pub enum Fields {
+    Named(Vec<(String, String)>),
+    Unnamed(Vec<String>),
+    Unit,
+}
+
+impl Fields {
+    pub fn types(&self) -> Vec<&str> {
+        match self {
+            Fields::Named(fields) => fields.iter().map(|(_, ty)| ty.as_str()).collect(),
+            Fields::Unnamed(fields) => fields.iter().map(String::as_str).collect(),
+            Fields::Unit => Vec::new(),
+        }
+    }
+
+    /// The expressions reading every field of `value`.
+    pub fn accessors(&self, value: &str) -> Vec<String> {
+        match self {
+            Fields::Named(fields) => fields
+                .iter()
+                .map(|(name, _)| format!("{value}.{name}"))
+                .collect(),
+            Fields::Unnamed(fields) => (0..fields.len()).map(|i| format!("{value}.{i}")).collect(),
+            Fields::Unit => Vec::new(),
+        }
+    }
+}
+
+pub fn parse(input: TokenStream) -> Result<Input, String> {
+    let mut tokens = input.into_iter().peekable();
+    let mut attributes = Vec::new();
+    loop {
+        match tokens.next() {
+            Some(TokenTree::Punct(punct)) if punct.as_char() == '#' => {
+                if let Some(TokenTree::Group(group)) = tokens.next() {
+                    attributes.extend(attribute(&group));
+                }
+            }
+            Some(TokenTree::Ident(ident)) if ident.to_string() == "pub" => {
+                if let Some(TokenTree::Group(group)) = tokens.peek() {
+                    if group.delimiter() == Delimiter::Parenthesis {
+                        tokens.next();
+                    }
+                }
+            }
+            Some(TokenTree::Ident(ident)) if ident.to_string() == "struct" => break,
+            _ => return Err("only structs are supported".into()),
+        }
+    }
+    let name = match tokens.next() {
+        Some(TokenTree::Ident(ident)) => ident.to_string(),
+        _ => return Err("expected the struct name".into()),
+    };
+    let mut generics = TokenStream::new();
+    if matches!(tokens.peek(), Some(TokenTree::Punct(punct)) if punct.as_char() == '<') {
+        tokens.next();
+        let mut depth = 1;
+        for token in tokens.by_ref() {
+            depth += angle_depth(&token);
+            if depth == 0 {
+                break;
+            }
+            generics.extend([token]);
+        }
+    }
+    let fields = match tokens.next() {
+        Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace => {
+            Fields::Named(named_fields(&group)?)
+        }
+        Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Parenthesis => {
+            Fields::Unnamed(split_fields(&group).into_iter().map(field_type).collect())
+        }
+        Some(TokenTree::Punct(punct)) if punct.as_char() == ';' => Fields::Unit,
+        Some(TokenTree::Ident(ident)) if ident.to_string() == "where" => {
+            return Err("where clauses are not supported".into())
+        }
+        _ => return Err("expected the struct fields".into()),
+    };
+    if matches!(tokens.next(), Some(TokenTree::Ident(ident)) if ident.to_string() == "where") {
+        return Err("where clauses are not supported".into());
+    }
+    Ok(Input {
+        name,
+        generics: generics.to_string(),
+        fields,
+        attributes,
+    })
+}
+
+/// Returns the name and arguments of an attribute written as `name(arguments)`.
+fn attribute(group: &Group) -> Option<(String, TokenStream)> {
+    let mut tokens = group.stream().into_iter();
+    let name = match tokens.next()? {
+        TokenTree::Ident(ident) => ident.to_string(),
+        _ => return None,
+    };
+    match tokens.next()? {
+        TokenTree::Group(group) if group.delimiter() == Delimiter::Parenthesis => {
+            Some((name, group.stream()))
+        }
+        _ => None,
+    }
+}
+
+fn named_fields(group: &Group) -> Result<Vec<(String, String)>, String> {
+    split_fields(group)
+        .into_iter()
+        .map(|field| {
+            let mut tokens = skip_attributes_and_visibility(field).into_iter();
+            let name = match tokens.next() {
+                Some(TokenTree::Ident(ident)) => ident.to_string(),
+                _ => return Err("expected a field name".into()),
+            };
+            match tokens.next() {
+                Some(TokenTree::Punct(punct)) if punct.as_char() == ':' => {}
+                _ => return Err(format!("expected the type of field `{name}`")),
+            }
+            Ok((name, tokens.collect::<TokenStream>().to_string()))
+        })
+        .collect()
+}
+
+fn field_type(field: Vec<TokenTree>) -> String {
+    skip_attributes_and_visibility(field)
+        .into_iter()
+        .collect::<TokenStream>()
+        .to_string()
+}
+
+fn skip_attributes_and_visibility(field: Vec<TokenTree>) -> Vec<TokenTree> {
+    let mut start = 0;
+    loop {
+        match &field[start..] {
+            [TokenTree::Punct(punct), TokenTree::Group(_), ..] if punct.as_char() == '#' => {
+                start += 2
+            }
+            [TokenTree::Ident(ident), TokenTree::Group(group), ..]
+                if ident.to_string() == "pub" && group.delimiter() == Delimiter::Parenthesis =>
+            {
+                start += 2
+            }
+            [TokenTree::Ident(ident), ..] if ident.to_string() == "pub" => start += 1,
+            _ => break,
+        }
+    }
+    field[start..].to_vec()
+}
+
+/// Splits the contents of a struct body at the commas that are not nested in a type.
+fn split_fields(group: &Group) -> Vec<Vec<TokenTree>> {
+    let mut fields = Vec::new();
+    let mut field = Vec::new();
+    let mut depth = 0;
+    let mut after_dash = false;
+    for token in group.stream() {
+        let is_dash = matches!(&token, TokenTree::Punct(punct) if punct.as_char() == '-' && punct.spacing() == Spacing::Joint);
+        match &token {
+            TokenTree::Punct(punct) if punct.as_char() == ',' && depth == 0 => {
+                fields.push(core::mem::take(&mut field));
+            }
+            // the `>` of `->` in a function pointer type
+            TokenTree::Punct(punct) if punct.as_char() == '>' && after_dash => field.push(token),
+            _ => {
+                depth += angle_depth(&token);
+                field.push(token);
+            }
+        }
+        after_dash = is_dash;
+    }
+    if !field.is_empty() {
+        fields.push(field);
+    }
+    fields
+}
+
+fn angle_depth(token: &TokenTree) -> i32 {
+    match token {
+        TokenTree::Punct(punct) if punct.as_char() == '<' => 1,
+        TokenTree::Punct(punct) if punct.as_char() == '>' => -1,
+        _ => 0,
+    }
+}
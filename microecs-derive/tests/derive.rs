@@ -0,0 +1,155 @@
+use microecs::prelude::*;
+use std::any::TypeId;
+use std::collections::BTreeMap;
+
+pub struct Health(u32);
+
+#[derive(Component)]
+#[component(storage = BTreeMap<usize, Self>)]
+struct Sparse(u32);
+
+#[derive(Component)]
+#[component(storage = std::collections::BTreeMap<usize, Marker>)]
+struct Marker;
+
+#[derive(Bundle)]
+pub struct Goblin {
+    health: Health,
+    pub(crate) name: &'static str,
+}
+
+#[derive(Bundle)]
+struct Pair(Health, pub u16);
+
+fn chunk() -> Chunk {
+    ChunkBuilder::default()
+        .with_component::<Health>()
+        .with_component::<&'static str>()
+        .with_component::<u16>()
+        .with_custom_storage::<Sparse>()
+        .with_custom_storage::<Marker>()
+        .build()
+}
+
+#[test]
+fn components_use_their_storage() {
+    let mut chunk = chunk();
+    let entities = [(); 2].map(|_| chunk.spawn().unwrap());
+    chunk.add_component(entities[1], Sparse(3)).unwrap();
+    chunk.add_component(entities[1], Marker).unwrap();
+    let sparse = chunk.components_ref::<Sparse>().unwrap();
+    assert_eq!(sparse.get(entities[1]).map(|s| s.0), Some(3));
+    assert!(sparse.get(entities[0]).is_none());
+    assert!(chunk
+        .components_ref::<Marker>()
+        .unwrap()
+        .contains(entities[1]));
+}
+
+#[test]
+fn bundles_round_trip_through_spawn_batch() {
+    let mut chunk = chunk();
+    let goblins = [
+        Goblin {
+            health: Health(5),
+            name: "grik",
+        },
+        Goblin {
+            health: Health(6),
+            name: "snag",
+        },
+    ];
+    let goblins = chunk.spawn_batch(goblins).unwrap();
+    let pairs = chunk.spawn_batch([Pair(Health(1), 9)]).unwrap();
+
+    let health = chunk.components_ref::<Health>().unwrap();
+    let names = chunk.components_ref::<&'static str>().unwrap();
+    let levels = chunk.components_ref::<u16>().unwrap();
+    let goblins = goblins
+        .iter()
+        .map(|&e| (health.get(e).unwrap().0, *names.get(e).unwrap()));
+    assert_eq!(goblins.collect::<Vec<_>>(), [(5, "grik"), (6, "snag")]);
+    assert_eq!(health.get(pairs[0]).map(|h| h.0), Some(1));
+    assert_eq!(levels.get(pairs[0]), Some(&9));
+    assert!(names.get(pairs[0]).is_none());
+}
+
+struct Position(i32);
+
+struct Velocity(i32);
+
+struct Time(i32);
+
+#[derive(SystemParam)]
+struct Movement<'a> {
+    positions: ComponentsMut<'a, Position>,
+    velocities: ComponentsRef<'a, Velocity>,
+    time: ResourceRef<'a, Time>,
+}
+
+#[derive(SystemParam)]
+struct Frame<'w>(Movement<'w>, ResourceMut<'w, Vec<i32>>);
+
+fn step(frame: Frame) {
+    let Frame(mut movement, mut log) = frame;
+    let dt = movement.time.get().0;
+    for (position, velocity) in (&mut movement.positions, &movement.velocities).query() {
+        position.0 += velocity.0 * dt;
+        log.get_mut().push(position.0);
+    }
+}
+
+fn pause(mut time: ResourceMut<Time>) {
+    time.get_mut().0 = 0;
+}
+
+#[test]
+fn derived_params_fetch_their_fields() {
+    let mut chunk = ChunkBuilder::default()
+        .with_component::<Position>()
+        .with_component::<Velocity>()
+        .build();
+    chunk
+        .spawn_batch([(Position(1), Velocity(2)), (Position(0), Velocity(-1))])
+        .unwrap();
+    let mut resources = ResourcesBuilder::default()
+        .with_resource(Time(3))
+        .with_resource(Vec::<i32>::new())
+        .build();
+    chunk.with(&mut resources).run(step).unwrap();
+    assert_eq!(
+        *resources.resource_ref::<Vec<i32>>().unwrap().get(),
+        [7, -3]
+    );
+}
+
+#[test]
+fn derived_params_report_the_access_of_their_fields() {
+    let mut schedule = Schedule::new();
+    schedule.add_system(step);
+    schedule.add_system(pause);
+    let access = schedule.systems().next().unwrap().access();
+    let reads = access.reads().map(|(target, _)| target).collect::<Vec<_>>();
+    let writes = access
+        .writes()
+        .map(|(target, _)| target)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        reads,
+        [
+            AccessTarget::Component(TypeId::of::<Velocity>()),
+            AccessTarget::Resource(TypeId::of::<Time>()),
+        ]
+    );
+    assert_eq!(
+        writes,
+        [
+            AccessTarget::Component(TypeId::of::<Position>()),
+            AccessTarget::Resource(TypeId::of::<Vec<i32>>()),
+        ]
+    );
+    // `pause` writes the time that `step` reads
+    let conflicts = schedule.conflicts();
+    assert_eq!(conflicts.len(), 1);
+    assert!(conflicts[0].type_name.ends_with("Time"));
+}
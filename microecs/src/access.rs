@@ -0,0 +1,86 @@
+use alloc::vec::Vec;
+use core::any::{type_name, TypeId};
+
+/// What a system param borrows, used to order or parallelize systems that would otherwise fail
+/// with an `AlreadyBorrowedMutably` error.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AccessTarget {
+    Component(TypeId),
+    Resource(TypeId),
+    /// A resource stored on the chunk, see `ChunkBuilder::with_item`.
+    Item(TypeId),
+    NonSend(TypeId),
+}
+
+/// The borrows made by the params of a system, see `SystemParam::access`.
+#[derive(Clone, Debug, Default)]
+pub struct Access {
+    reads: Vec<(AccessTarget, &'static str)>,
+    writes: Vec<(AccessTarget, &'static str)>,
+    /// The borrows of each system of a pipe, see `add_stage`.
+    stages: Vec<Access>,
+}
+
+impl Access {
+    pub fn read<T: 'static>(&mut self, target: fn(TypeId) -> AccessTarget) {
+        self.reads
+            .push((target(TypeId::of::<T>()), type_name::<T>()));
+    }
+
+    pub fn write<T: 'static>(&mut self, target: fn(TypeId) -> AccessTarget) {
+        self.writes
+            .push((target(TypeId::of::<T>()), type_name::<T>()));
+    }
+
+    /// Adds the borrows of a system whose params are only fetched once those of the previous
+    /// stages are dropped, as with `System::pipe`. `self_conflict` then checks each stage on its
+    /// own.
+    pub fn add_stage(&mut self, stage: Access) {
+        self.reads.extend(stage.reads());
+        self.writes.extend(stage.writes());
+        self.stages.push(stage);
+    }
+
+    /// The targets borrowed immutably, along with their type names.
+    pub fn reads(&self) -> impl Iterator<Item = (AccessTarget, &'static str)> + use<'_> {
+        self.reads.iter().copied()
+    }
+
+    /// The targets borrowed mutably, along with their type names.
+    pub fn writes(&self) -> impl Iterator<Item = (AccessTarget, &'static str)> + use<'_> {
+        self.writes.iter().copied()
+    }
+
+    /// Returns the type name of a target that one of `self` and `other` writes while the other
+    /// one reads or writes it.
+    pub fn conflict_with(&self, other: &Access) -> Option<&'static str> {
+        let conflict = |writes: &[(AccessTarget, &'static str)], other: &Access| {
+            writes
+                .iter()
+                .find(|(target, _)| {
+                    other
+                        .reads()
+                        .chain(other.writes())
+                        .any(|(t, _)| t == *target)
+                })
+                .map(|(_, name)| *name)
+        };
+        conflict(&self.writes, other).or_else(|| conflict(&other.writes, self))
+    }
+
+    /// Returns the type name of a target written by one param and borrowed by another one of
+    /// the same system, which makes fetching the params fail.
+    pub fn self_conflict(&self) -> Option<&'static str> {
+        if !self.stages.is_empty() {
+            return self.stages.iter().find_map(Access::self_conflict);
+        }
+        self.writes
+            .iter()
+            .enumerate()
+            .find(|(index, (target, _))| {
+                self.reads().any(|(t, _)| t == *target)
+                    || self.writes[index + 1..].iter().any(|(t, _)| t == target)
+            })
+            .map(|(_, (_, name))| *name)
+    }
+}
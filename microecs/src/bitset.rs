@@ -0,0 +1,120 @@
+use alloc::vec::Vec;
+
+const BITS: usize = u64::BITS as usize;
+
+/// One bit per row of a column, set if the row holds a value.
+#[derive(Clone, Debug, Default)]
+pub struct Bitset {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl Bitset {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of rows, set or not.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of set rows.
+    pub fn count(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    #[inline]
+    pub fn contains(&self, index: usize) -> bool {
+        index < self.len && self.words[index / BITS] & (1 << (index % BITS)) != 0
+    }
+
+    #[inline]
+    pub(crate) fn set(&mut self, index: usize, value: bool) {
+        assert!(index < self.len, "row {index} out of bounds");
+        let bit = 1 << (index % BITS);
+        if value {
+            self.words[index / BITS] |= bit;
+        } else {
+            self.words[index / BITS] &= !bit;
+        }
+    }
+
+    #[inline]
+    pub(crate) fn push(&mut self, value: bool) {
+        if self.len.is_multiple_of(BITS) {
+            self.words.push(0);
+        }
+        self.len += 1;
+        self.set(self.len - 1, value);
+    }
+
+    pub(crate) fn swap_remove(&mut self, index: usize) {
+        let last = self.contains(self.len - 1);
+        self.set(index, last);
+        self.truncate(self.len - 1);
+    }
+
+    pub(crate) fn extend_unset(&mut self, additional: usize) {
+        self.len += additional;
+        self.words.resize(self.len.div_ceil(BITS), 0);
+    }
+
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        let words = (self.len + additional).div_ceil(BITS);
+        self.words.reserve(words - self.words.len());
+    }
+
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.words.shrink_to_fit();
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.words.clear();
+        self.len = 0;
+    }
+
+    /// Keeps the rows whose entry in `keep` is true, preserving their order.
+    pub(crate) fn retain_rows(&mut self, keep: &[bool]) {
+        let old = core::mem::take(self);
+        for (index, _) in keep.iter().enumerate().filter(|(_, keep)| **keep) {
+            self.push(old.contains(index));
+        }
+    }
+
+    pub(crate) fn append(&mut self, other: &mut Bitset) {
+        for index in 0..other.len {
+            self.push(other.contains(index));
+        }
+        other.clear();
+    }
+
+    /// Keeps only the rows also set in `other`, a word at a time.
+    pub(crate) fn intersect(&mut self, other: &Bitset) {
+        for (word, other) in self.words.iter_mut().zip(other.words.iter()) {
+            *word &= other;
+        }
+    }
+
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.words.capacity() * size_of::<u64>()
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.len = len;
+        self.words.truncate(len.div_ceil(BITS));
+        if !len.is_multiple_of(BITS) {
+            // keep the bits past the end unset, so `intersect` and `push` can ignore them
+            *self.words.last_mut().unwrap() &= (1 << (len % BITS)) - 1;
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use crate::components::ComponentsMut;
+use crate::{Chunk, Error};
+
+/// A tuple of component values that are inserted together, e.g. by `Chunk::spawn_batch`.
+pub trait Bundle: Sized + 'static {
+    type Columns<'a>;
+
+    fn columns(chunk: &Chunk) -> Result<Self::Columns<'_>, Error>;
+
+    fn write(self, columns: &mut Self::Columns<'_>, index: usize);
+}
+
+macro_rules! impl_bundle_for_tuple {
+    ( $(($T:ident, $i:tt)),+ ) => {
+        impl<$($T: 'static),+> Bundle for ($($T,)+) {
+            type Columns<'a> = ($(ComponentsMut<'a, $T>,)+);
+
+            fn columns(chunk: &Chunk) -> Result<Self::Columns<'_>, Error> {
+                Ok(($(chunk.components_mut::<$T>()?,)+))
+            }
+
+            #[inline]
+            fn write(self, columns: &mut Self::Columns<'_>, index: usize) {
+                $(columns.$i.values.set(index, Some(self.$i));)+
+            }
+        }
+    };
+}
+
+impl_bundle_for_tuple!((C1, 0));
+impl_bundle_for_tuple!((C1, 0), (C2, 1));
+impl_bundle_for_tuple!((C1, 0), (C2, 1), (C3, 2));
+impl_bundle_for_tuple!((C1, 0), (C2, 1), (C3, 2), (C4, 3));
+impl_bundle_for_tuple!((C1, 0), (C2, 1), (C3, 2), (C4, 3), (C5, 4));
+impl_bundle_for_tuple!((C1, 0), (C2, 1), (C3, 2), (C4, 3), (C5, 4), (C6, 5));
+impl_bundle_for_tuple!(
+    (C1, 0),
+    (C2, 1),
+    (C3, 2),
+    (C4, 3),
+    (C5, 4),
+    (C6, 5),
+    (C7, 6)
+);
+#[rustfmt::skip]
+impl_bundle_for_tuple!((C1, 0), (C2, 1), (C3, 2), (C4, 3), (C5, 4), (C6, 5), (C7, 6), (C8, 7));
+#[rustfmt::skip]
+impl_bundle_for_tuple!((C1, 0), (C2, 1), (C3, 2), (C4, 3), (C5, 4), (C6, 5), (C7, 6), (C8, 7), (C9, 8));
+#[rustfmt::skip]
+impl_bundle_for_tuple!((C1, 0), (C2, 1), (C3, 2), (C4, 3), (C5, 4), (C6, 5), (C7, 6), (C8, 7), (C9, 8), (C10, 9));
+#[rustfmt::skip]
+impl_bundle_for_tuple!((C1, 0), (C2, 1), (C3, 2), (C4, 3), (C5, 4), (C6, 5), (C7, 6), (C8, 7), (C9, 8), (C10, 9), (C11, 10));
+#[rustfmt::skip]
+impl_bundle_for_tuple!((C1, 0), (C2, 1), (C3, 2), (C4, 3), (C5, 4), (C6, 5), (C7, 6), (C8, 7), (C9, 8), (C10, 9), (C11, 10), (C12, 11));
@@ -0,0 +1,795 @@
+use crate::entities::{ChunkEntities, Entity};
+use crate::resources::{Resource, Resources};
+use crate::{Chunk, Error};
+use alloc::{boxed::Box, collections::vec_deque::VecDeque, vec::Vec};
+use core::mem::{align_of, size_of, ManuallyDrop, MaybeUninit};
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+use spin::Mutex;
+
+/// A multi-producer queue of deferred commands.
+///
+/// Every `Commands` records into its own buffer, which is pushed into a lock-free stack of
+/// segments when it is dropped, so any number of systems can hold `Commands` at the same time.
+/// Within a segment the commands keep their order; segments are applied in the order in which
+/// they were submitted.
+///
+/// Segments are recycled once flushed, so after the first few frames recording commands does not
+/// allocate (besides `defer` closures and components too large to be stored inline).
+#[derive(Default)]
+pub struct CommandQueue {
+    segments: AtomicPtr<Segment>,
+    // every command with the system that queued it
+    pending: VecDeque<(Option<&'static str>, Command)>,
+    // boxed because the segments are moved in and out of `segments` as raw pointers
+    #[allow(clippy::vec_box)]
+    spare_segments: Mutex<Vec<Box<Segment>>>,
+    policy: FlushPolicy,
+}
+
+/// What `CommandQueue::flush` does when a command fails.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// Stops at the failing command and returns its error, keeping the remaining commands for the
+    /// next flush.
+    #[default]
+    Abort,
+    /// Applies every other command and returns the failures as `Error::CommandsFailed`.
+    Collect,
+    /// Panics on the first failure in debug builds and behaves like `Collect` otherwise.
+    PanicInDebug,
+}
+
+impl FlushPolicy {
+    /// Reports `error` according to the policy, returning it if the flush should stop.
+    pub(crate) fn handle(self, error: Error, failures: &mut Vec<Error>) -> Result<(), Error> {
+        match self {
+            FlushPolicy::Abort => Err(error),
+            FlushPolicy::PanicInDebug if cfg!(debug_assertions) => panic!("{error}"),
+            FlushPolicy::Collect | FlushPolicy::PanicInDebug => {
+                failures.push(error);
+                Ok(())
+            }
+        }
+    }
+}
+
+struct Segment {
+    commands: Vec<Command>,
+    system: Option<&'static str>,
+    next: *mut Segment,
+}
+
+// SAFETY: `next` is only followed by the owner of the whole stack, see `collect_segments`.
+unsafe impl Send for Segment {}
+
+impl Default for Segment {
+    fn default() -> Self {
+        Self {
+            commands: Vec::new(),
+            system: None,
+            next: ptr::null_mut(),
+        }
+    }
+}
+
+impl CommandQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    #[inline]
+    pub fn flush_policy(&self) -> FlushPolicy {
+        self.policy
+    }
+
+    pub fn set_flush_policy(&mut self, policy: FlushPolicy) {
+        self.policy = policy;
+    }
+
+    /// Reserves room for at least `additional` commands in the segment the next `Commands`
+    /// fetched from this queue records into.
+    pub fn reserve(&mut self, additional: usize) {
+        let spare_segments = self.spare_segments.get_mut();
+        match spare_segments.last_mut() {
+            Some(segment) => segment.commands.reserve(additional),
+            None => spare_segments.push(Box::new(Segment {
+                commands: Vec::with_capacity(additional),
+                ..Segment::default()
+            })),
+        }
+    }
+
+    /// Applies every command in the order in which they were submitted. A failing command is
+    /// reported as `Error::CommandFailed` and handled according to the `FlushPolicy`.
+    pub fn flush(&mut self, chunk: &mut Chunk, resources: &mut Resources) -> Result<(), Error> {
+        self.collect_segments();
+        let mut failures = Vec::new();
+        let mut index = 0;
+        while let Some((system, command)) = self.pending.pop_front() {
+            let name = command.name();
+            if let Err(error) = command.apply(chunk, resources, system) {
+                let error = Error::command_failed(system, index, name, error);
+                self.policy.handle(error, &mut failures)?;
+            }
+            index += 1;
+        }
+        chunk.run_due_commands(resources, self.policy, &mut failures)?;
+        chunk.flush_hook_commands(resources)?;
+        match failures.is_empty() {
+            true => Ok(()),
+            false => Err(Error::CommandsFailed(failures)),
+        }
+    }
+
+    /// Drops every command waiting to be flushed.
+    pub fn clear(&mut self) {
+        self.collect_segments();
+        self.pending.clear();
+    }
+
+    /// Returns true if nothing is waiting to be flushed.
+    pub fn is_empty(&mut self) -> bool {
+        self.pending.is_empty() && self.segments.get_mut().is_null()
+    }
+
+    pub(crate) fn deferred_commands<'a>(&'a self, entities: &'a ChunkEntities) -> Commands<'a> {
+        Commands {
+            queue: self,
+            entities,
+            segment: None,
+        }
+    }
+
+    fn take_segment(&self) -> Box<Segment> {
+        // never wait on other producers, allocating a new segment is cheaper than spinning
+        self.spare_segments
+            .try_lock()
+            .and_then(|mut spare| spare.pop())
+            .unwrap_or_default()
+    }
+
+    fn push_segment(&self, segment: Box<Segment>) {
+        let segment = Box::into_raw(segment);
+        let mut head = self.segments.load(Ordering::Relaxed);
+        loop {
+            // SAFETY: the segment is not shared until the exchange below succeeds.
+            unsafe { (*segment).next = head };
+            match self.segments.compare_exchange_weak(
+                head,
+                segment,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    /// Records `system` as the origin of the segments submitted since the last call.
+    pub(crate) fn label_segments(&mut self, system: &'static str) {
+        let mut segment = *self.segments.get_mut();
+        // SAFETY: as in `collect_segments`, `&mut self` makes this the only reader of the stack.
+        while let Some(current) = unsafe { segment.as_mut() } {
+            if current.system.is_some() {
+                break;
+            }
+            current.system = Some(system);
+            segment = current.next;
+        }
+    }
+
+    /// Drops the segments submitted since the last `label_segments`, e.g. by a failed system.
+    pub(crate) fn discard_unlabeled(&mut self) {
+        let head = self.segments.get_mut();
+        let spare_segments = self.spare_segments.get_mut();
+        // SAFETY: as in `collect_segments`, `&mut self` makes this the only reader of the stack.
+        while !head.is_null() && unsafe { (**head).system.is_none() } {
+            let mut segment = unsafe { Box::from_raw(*head) };
+            *head = core::mem::replace(&mut segment.next, ptr::null_mut());
+            segment.commands.clear();
+            spare_segments.push(segment);
+        }
+    }
+
+    /// Moves every submitted segment into `pending`, oldest first.
+    fn collect_segments(&mut self) {
+        let mut head = core::mem::replace(self.segments.get_mut(), ptr::null_mut());
+        let spare_segments = self.spare_segments.get_mut();
+        let first_collected = spare_segments.len();
+        while !head.is_null() {
+            // SAFETY: segments are only ever created by `push_segment` and, thanks to `&mut self`,
+            // this is the only place that can be reading them.
+            let mut segment = unsafe { Box::from_raw(head) };
+            head = core::mem::replace(&mut segment.next, ptr::null_mut());
+            spare_segments.push(segment);
+        }
+        for segment in spare_segments[first_collected..].iter_mut().rev() {
+            let system = segment.system.take();
+            self.pending
+                .extend(segment.commands.drain(..).map(|command| (system, command)));
+        }
+    }
+}
+
+impl Drop for CommandQueue {
+    fn drop(&mut self) {
+        self.collect_segments();
+    }
+}
+
+type CustomCommand = Box<dyn FnOnce(&mut Chunk, &mut Resources) -> Result<(), Error> + Send + Sync>;
+
+/// Deferred structural changes. The common operations are stored as plain data so that only
+/// `defer` and large component values of `insert` need to be boxed.
+pub(crate) enum Command {
+    /// The entity and the `ChunkEntities::owner` it was reserved from.
+    Spawn(Entity, u64),
+    Despawn(Entity),
+    Insert(
+        Entity,
+        SmallValue,
+        unsafe fn(&mut Chunk, Entity, SmallValue) -> Result<(), Error>,
+    ),
+    Remove(Entity, fn(&mut Chunk, Entity) -> Result<(), Error>),
+    InsertResource(SmallValue, unsafe fn(&mut Resources, SmallValue)),
+    RemoveResource(fn(&mut Resources)),
+    Custom(CustomCommand),
+    Delayed(u32, CustomCommand),
+}
+
+impl Command {
+    fn name(&self) -> &'static str {
+        match self {
+            Command::Spawn(..) => "spawn",
+            Command::Despawn(_) => "despawn",
+            Command::Insert(..) => "insert",
+            Command::Remove(..) => "remove",
+            Command::InsertResource(..) => "insert_resource",
+            Command::RemoveResource(_) => "remove_resource",
+            Command::Custom(_) => "defer",
+            Command::Delayed(..) => "defer_after",
+        }
+    }
+
+    fn apply(
+        self,
+        chunk: &mut Chunk,
+        resources: &mut Resources,
+        system: Option<&'static str>,
+    ) -> Result<(), Error> {
+        match self {
+            Command::Spawn(entity, owner) if owner != chunk.entities.owner() => {
+                Err(Error::ForeignEntity(entity))
+            }
+            Command::Spawn(entity, _) => {
+                chunk.entities.spawn_reserved(&mut chunk.components, entity)
+            }
+            Command::Despawn(entity) => chunk.destroy(entity),
+            // SAFETY: `Commands::insert` always pairs the value with its own `insert_value::<T>`.
+            Command::Insert(entity, value, insert) => unsafe { insert(chunk, entity, value) },
+            Command::Remove(entity, remove) => remove(chunk, entity),
+            // SAFETY: `Commands::insert_resource` always pairs the value with its own
+            // `insert_resource::<T>`.
+            Command::InsertResource(value, insert) => {
+                unsafe { insert(resources, value) };
+                Ok(())
+            }
+            Command::RemoveResource(remove) => {
+                remove(resources);
+                Ok(())
+            }
+            Command::Custom(command) => command(chunk, resources),
+            Command::Delayed(ticks, command) => {
+                let due = chunk.tick().wrapping_add(ticks);
+                chunk.delayed_commands.push(due, system, command);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The commands queued by `Commands::defer_after`, waiting for their tick.
+#[derive(Default)]
+pub(crate) struct DelayedCommands {
+    commands: Vec<(u32, Option<&'static str>, CustomCommand)>,
+}
+
+type DueCommand = (Option<&'static str>, CustomCommand);
+
+impl DelayedCommands {
+    pub(crate) fn push(&mut self, due: u32, system: Option<&'static str>, command: CustomCommand) {
+        self.commands.push((due, system, command));
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.commands.clear();
+    }
+
+    /// Removes the commands due at or before `tick`, in the order in which they were queued.
+    pub(crate) fn take_due(&mut self, tick: u32) -> Vec<DueCommand> {
+        // wrapping difference, so that ticks keep working after `u32::MAX`
+        self.commands
+            .extract_if(.., |(due, _, _)| (tick.wrapping_sub(*due) as i32) >= 0)
+            .map(|(_, system, command)| (system, command))
+            .collect()
+    }
+}
+
+pub struct Commands<'a> {
+    queue: &'a CommandQueue,
+    entities: &'a ChunkEntities,
+    segment: Option<Box<Segment>>,
+}
+
+impl Commands<'_> {
+    pub fn defer(
+        &mut self,
+        command: impl FnOnce(&mut Chunk, &mut Resources) -> Result<(), Error> + Send + Sync + 'static,
+    ) {
+        self.push(Command::Custom(Box::new(command)));
+    }
+
+    /// Like `defer`, but runs `command` during the first flush after `ticks` calls to
+    /// `Chunk::advance_tick`, e.g. to remove a corpse once its death animation is over. With 0
+    /// ticks it runs during the flush applying this call.
+    pub fn defer_after(
+        &mut self,
+        ticks: u32,
+        command: impl FnOnce(&mut Chunk, &mut Resources) -> Result<(), Error> + Send + Sync + 'static,
+    ) {
+        self.push(Command::Delayed(ticks, Box::new(command)));
+    }
+
+    /// Reserves an entity and defers the creation of its row. The returned entity can already be
+    /// used by the commands queued after this one. The id belongs to the chunk these commands were
+    /// fetched from, flushing the spawn into another chunk fails with `Error::ForeignEntity`.
+    pub fn spawn(&mut self) -> Entity {
+        let entity = self.entities.reserve();
+        self.spawn_reserved(entity);
+        entity
+    }
+
+    /// Defers the creation of the row of `entity`, an id handed out by `EntitySpawner::reserve`.
+    pub fn spawn_reserved(&mut self, entity: Entity) {
+        self.push(Command::Spawn(entity, self.entities.owner()));
+    }
+
+    pub fn despawn(&mut self, entity: Entity) {
+        self.push(Command::Despawn(entity));
+    }
+
+    pub fn insert<T: Send + Sync + 'static>(&mut self, entity: Entity, value: T) {
+        self.push(Command::Insert(
+            entity,
+            SmallValue::new(value),
+            insert_value::<T>,
+        ));
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity: Entity) {
+        self.push(Command::Remove(entity, Chunk::remove_component::<T>));
+    }
+
+    pub fn insert_resource<T: Resource + Send + Sync>(&mut self, value: T) {
+        self.push(Command::InsertResource(
+            SmallValue::new(value),
+            insert_resource::<T>,
+        ));
+    }
+
+    pub fn remove_resource<T: 'static>(&mut self) {
+        self.push(Command::RemoveResource(Resources::remove_resource::<T>));
+    }
+
+    /// Reserves room for at least `additional` more commands in this buffer.
+    pub fn reserve(&mut self, additional: usize) {
+        self.segment().commands.reserve(additional);
+    }
+
+    #[inline]
+    fn push(&mut self, command: Command) {
+        self.segment().commands.push(command);
+    }
+
+    #[inline]
+    fn segment(&mut self) -> &mut Segment {
+        let queue = self.queue;
+        self.segment.get_or_insert_with(|| queue.take_segment())
+    }
+}
+
+impl Drop for Commands<'_> {
+    fn drop(&mut self) {
+        let Some(segment) = self.segment.take() else {
+            return;
+        };
+        if segment.commands.is_empty() {
+            if let Some(mut spare) = self.queue.spare_segments.try_lock() {
+                spare.push(segment);
+            }
+        } else {
+            self.queue.push_segment(segment);
+        }
+    }
+}
+
+/// # Safety
+///
+/// `value` must have been created from a `T`.
+unsafe fn insert_value<T: 'static>(
+    chunk: &mut Chunk,
+    entity: Entity,
+    value: SmallValue,
+) -> Result<(), Error> {
+    chunk.add_component(entity, value.take::<T>())
+}
+
+/// # Safety
+///
+/// `value` must have been created from a `T`.
+unsafe fn insert_resource<T: Resource>(resources: &mut Resources, value: SmallValue) {
+    resources.add_resource(value.take::<T>());
+}
+
+const INLINE_WORDS: usize = 3;
+
+type InlineStorage = MaybeUninit<[usize; INLINE_WORDS]>;
+
+/// A type-erased value stored in place when it fits in `INLINE_WORDS` words, boxed otherwise.
+pub(crate) struct SmallValue {
+    data: InlineStorage,
+    drop: unsafe fn(&mut InlineStorage),
+}
+
+impl SmallValue {
+    fn new<T: Send + Sync + 'static>(value: T) -> Self {
+        let mut data = InlineStorage::uninit();
+        // SAFETY: `fits_inline` guarantees `data` is large and aligned enough for a `T`, and it is
+        // always large enough for a `Box<T>`.
+        unsafe {
+            if fits_inline::<T>() {
+                data.as_mut_ptr().cast::<T>().write(value);
+            } else {
+                data.as_mut_ptr().cast::<Box<T>>().write(Box::new(value));
+            }
+        }
+        Self {
+            data,
+            drop: drop_value::<T>,
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The value must have been created from a `T`.
+    unsafe fn take<T>(self) -> T {
+        let this = ManuallyDrop::new(self);
+        if fits_inline::<T>() {
+            this.data.as_ptr().cast::<T>().read()
+        } else {
+            *this.data.as_ptr().cast::<Box<T>>().read()
+        }
+    }
+}
+
+impl Drop for SmallValue {
+    fn drop(&mut self) {
+        // SAFETY: `drop` was chosen for the type this value was created from.
+        unsafe { (self.drop)(&mut self.data) }
+    }
+}
+
+#[inline]
+const fn fits_inline<T>() -> bool {
+    size_of::<T>() <= size_of::<InlineStorage>() && align_of::<T>() <= align_of::<InlineStorage>()
+}
+
+unsafe fn drop_value<T>(data: &mut InlineStorage) {
+    if fits_inline::<T>() {
+        data.as_mut_ptr().cast::<T>().drop_in_place();
+    } else {
+        data.as_mut_ptr().cast::<Box<T>>().drop_in_place();
+    }
+}
+
+/// A system parameter handing out entity ids right away and deferring the creation of their
+/// rows, for systems that spawn entities but queue no other command.
+pub struct EntitySpawner<'a> {
+    commands: Commands<'a>,
+}
+
+impl<'a> EntitySpawner<'a> {
+    pub(crate) fn new(commands: Commands<'a>) -> Self {
+        Self { commands }
+    }
+
+    /// Reserves an id without queuing anything, e.g. to store a reference to an entity that is
+    /// spawned later through `spawn_reserved`.
+    #[inline]
+    pub fn reserve(&self) -> Entity {
+        self.commands.entities.reserve()
+    }
+
+    #[inline]
+    pub fn spawn_reserved(&mut self, entity: Entity) {
+        self.commands.spawn_reserved(entity);
+    }
+
+    /// See `Commands::spawn`.
+    #[inline]
+    pub fn spawn(&mut self) -> Entity {
+        self.commands.spawn()
+    }
+
+    #[inline]
+    pub fn insert<T: Send + Sync + 'static>(&mut self, entity: Entity, value: T) {
+        self.commands.insert(entity, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChunkBuilder;
+    use alloc::string::ToString;
+    use alloc::vec;
+    use core::sync::atomic::AtomicUsize;
+
+    fn log(commands: &mut Commands, value: u32) {
+        commands.defer(move |_, resources| {
+            resources.resource_mut::<Vec<u32>>()?.get_mut().push(value);
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn segments_flush_in_submission_order() {
+        let mut chunk = ChunkBuilder::default().build();
+        let mut resources = Resources::default();
+        resources.add_resource(Vec::<u32>::new());
+        let mut queue = CommandQueue::new();
+        {
+            let mut first = queue.deferred_commands(&chunk.entities);
+            let mut second = queue.deferred_commands(&chunk.entities);
+            log(&mut first, 1);
+            log(&mut second, 3);
+            log(&mut first, 2);
+            log(&mut second, 4);
+            drop(second);
+            drop(first);
+        }
+        log(&mut queue.deferred_commands(&chunk.entities), 5);
+        queue.flush(&mut chunk, &mut resources).unwrap();
+        let log = resources.resource_ref::<Vec<u32>>().unwrap();
+        assert_eq!(*log.get(), vec![3, 4, 1, 2, 5]);
+    }
+
+    #[test]
+    fn flushed_segments_are_reused() {
+        let mut chunk = ChunkBuilder::default().build();
+        let mut resources = Resources::default();
+        resources.add_resource(Vec::<u32>::new());
+        let mut queue = CommandQueue::new();
+        for value in 0..3 {
+            let mut commands = queue.deferred_commands(&chunk.entities);
+            for _ in 0..100 {
+                log(&mut commands, value);
+            }
+            drop(commands);
+            queue.flush(&mut chunk, &mut resources).unwrap();
+            assert_eq!(queue.spare_segments.get_mut().len(), 1);
+        }
+        let capacity = queue.spare_segments.get_mut()[0].commands.capacity();
+        assert!(capacity >= 100);
+        // a `Commands` that recorded nothing hands its segment back without submitting it
+        queue.deferred_commands(&chunk.entities).segment();
+        assert_eq!(queue.spare_segments.get_mut().len(), 1);
+        let mut commands = queue.deferred_commands(&chunk.entities);
+        log(&mut commands, 3);
+        assert_eq!(commands.segment().commands.capacity(), capacity);
+        drop(commands);
+        assert!(!queue.is_empty());
+        assert_eq!(queue.spare_segments.get_mut().len(), 0);
+    }
+
+    #[test]
+    fn reserve_grows_the_next_recording_segment() {
+        let chunk = ChunkBuilder::default().build();
+        let mut queue = CommandQueue::new();
+        queue.reserve(64);
+        let mut commands = queue.deferred_commands(&chunk.entities);
+        commands.spawn();
+        assert!(commands.segment().commands.capacity() >= 64);
+    }
+
+    fn log_after(commands: &mut Commands, ticks: u32, value: u32) {
+        commands.defer_after(ticks, move |_, resources| {
+            resources.resource_mut::<Vec<u32>>()?.get_mut().push(value);
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn delayed_commands_run_once_their_ticks_have_passed() {
+        let mut chunk = ChunkBuilder::default().build();
+        let mut resources = Resources::default();
+        resources.add_resource(Vec::<u32>::new());
+        let mut queue = CommandQueue::new();
+        log_after(&mut queue.deferred_commands(&chunk.entities), 2, 2);
+        log_after(&mut queue.deferred_commands(&chunk.entities), 0, 0);
+        let mut logged = Vec::new();
+        for _ in 0..4 {
+            queue.flush(&mut chunk, &mut resources).unwrap();
+            logged.push(resources.resource_ref::<Vec<u32>>().unwrap().get().clone());
+            chunk.advance_tick();
+        }
+        assert_eq!(logged, vec![vec![0], vec![0], vec![0, 2], vec![0, 2]]);
+    }
+
+    #[test]
+    fn clearing_the_chunk_drops_delayed_commands() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        let mut chunk = ChunkBuilder::default().build();
+        let mut resources = Resources::default();
+        resources.add_resource(Vec::<u32>::new());
+        let mut queue = CommandQueue::new();
+        log_after(&mut queue.deferred_commands(&chunk.entities), 1, 1);
+        let small = Small(&DROPS);
+        queue
+            .deferred_commands(&chunk.entities)
+            .defer_after(1, move |_, _| {
+                drop(small);
+                Ok(())
+            });
+        queue.flush(&mut chunk, &mut resources).unwrap();
+        chunk.clear();
+        assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+        chunk.advance_tick();
+        queue.flush(&mut chunk, &mut resources).unwrap();
+        assert!(resources
+            .resource_ref::<Vec<u32>>()
+            .unwrap()
+            .get()
+            .is_empty());
+    }
+
+    fn context(error: &Error) -> (Option<&'static str>, usize, &'static str) {
+        match error {
+            Error::CommandFailed(context, _) => (context.system, context.index, context.command),
+            error => panic!("{error} has no command context"),
+        }
+    }
+
+    #[test]
+    fn abort_stops_at_the_failing_command() {
+        let mut chunk = ChunkBuilder::default().build();
+        let mut resources = Resources::default();
+        resources.add_resource(Vec::<u32>::new());
+        let destroyed = chunk.spawn().unwrap();
+        chunk.destroy(destroyed).unwrap();
+        let mut queue = CommandQueue::new();
+        {
+            let mut commands = queue.deferred_commands(&chunk.entities);
+            log(&mut commands, 1);
+            commands.despawn(destroyed);
+            log(&mut commands, 2);
+        }
+        let error = queue.flush(&mut chunk, &mut resources).unwrap_err();
+        assert_eq!(context(&error), (None, 1, "despawn"));
+        assert!(matches!(error.root(), Error::InvalidEntity(e) if *e == destroyed));
+        assert_eq!(
+            *resources.resource_ref::<Vec<u32>>().unwrap().get(),
+            vec![1]
+        );
+        // the commands after the failing one are kept for the next flush
+        queue.flush(&mut chunk, &mut resources).unwrap();
+        assert_eq!(
+            *resources.resource_ref::<Vec<u32>>().unwrap().get(),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    fn collect_returns_the_failures_in_order() {
+        let mut chunk = ChunkBuilder::default().build();
+        let mut resources = Resources::default();
+        resources.add_resource(Vec::<u32>::new());
+        let destroyed = chunk.spawn().unwrap();
+        chunk.destroy(destroyed).unwrap();
+        let mut queue = CommandQueue::new().with_flush_policy(FlushPolicy::Collect);
+        {
+            let mut commands = queue.deferred_commands(&chunk.entities);
+            commands.despawn(destroyed);
+            log(&mut commands, 1);
+            commands.insert(destroyed, 0u32);
+            log(&mut commands, 2);
+        }
+        let Err(Error::CommandsFailed(failures)) = queue.flush(&mut chunk, &mut resources) else {
+            panic!("the failures were not collected");
+        };
+        let contexts = failures.iter().map(context).collect::<Vec<_>>();
+        assert_eq!(contexts, vec![(None, 0, "despawn"), (None, 2, "insert")]);
+        assert_eq!(
+            *resources.resource_ref::<Vec<u32>>().unwrap().get(),
+            vec![1, 2]
+        );
+    }
+
+    fn despawn_twice(mut commands: Commands) {
+        let entity = commands.spawn();
+        commands.despawn(entity);
+        commands.despawn(entity);
+    }
+
+    #[test]
+    fn failures_name_the_system_that_queued_them() {
+        let mut chunk = ChunkBuilder::default().build();
+        let mut resources = Resources::default();
+        let error = chunk.with(&mut resources).run(despawn_twice).err().unwrap();
+        let (system, index, command) = context(&error);
+        assert!(system.unwrap().ends_with("despawn_twice"));
+        assert_eq!((index, command), (2, "despawn"));
+        assert!(error.to_string().contains("queued by"));
+    }
+
+    struct Small(&'static AtomicUsize);
+
+    struct Large(&'static AtomicUsize, [usize; INLINE_WORDS]);
+
+    impl Drop for Small {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    impl Drop for Large {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn small_values_drop_once_inline_and_boxed() {
+        static SMALL: AtomicUsize = AtomicUsize::new(0);
+        static LARGE: AtomicUsize = AtomicUsize::new(0);
+        assert!(fits_inline::<Small>());
+        assert!(!fits_inline::<Large>());
+
+        drop(SmallValue::new(Small(&SMALL)));
+        drop(SmallValue::new(Large(&LARGE, [7; INLINE_WORDS])));
+        assert_eq!(SMALL.load(Ordering::Relaxed), 1);
+        assert_eq!(LARGE.load(Ordering::Relaxed), 1);
+
+        // SAFETY: both values are taken as the types they were created from.
+        let small = unsafe { SmallValue::new(Small(&SMALL)).take::<Small>() };
+        let large = unsafe { SmallValue::new(Large(&LARGE, [7; INLINE_WORDS])).take::<Large>() };
+        assert_eq!(large.1, [7; INLINE_WORDS]);
+        assert_eq!(SMALL.load(Ordering::Relaxed), 1);
+        assert_eq!(LARGE.load(Ordering::Relaxed), 1);
+        drop((small, large));
+        assert_eq!(SMALL.load(Ordering::Relaxed), 2);
+        assert_eq!(LARGE.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn queued_inserts_are_dropped_with_the_queue() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        let chunk = ChunkBuilder::default().with_component::<Small>().build();
+        let queue = CommandQueue::new();
+        {
+            let mut commands = queue.deferred_commands(&chunk.entities);
+            let entity = commands.spawn();
+            commands.insert(entity, Small(&DROPS));
+            commands.insert(entity, Large(&DROPS, [0; INLINE_WORDS]));
+        }
+        drop(queue);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 2);
+    }
+}
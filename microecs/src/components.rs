@@ -1,18 +1,26 @@
-use crate::entities::{ChunkEntities, Entity};
-use crate::Error;
-use alloc::{boxed::Box, vec::Vec};
+use crate::bitset::Bitset;
+use crate::diagnostics::ColumnDiagnostics;
+use crate::entities::{ChunkEntities, Entity, EntityMap, MapEntities};
+use crate::lock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use crate::storage::{Component, Storage};
+use crate::{ChunkBuilder, Commands, Error};
+use alloc::{boxed::Box, collections::BTreeMap, string::String, vec::Vec};
 use core::any::type_name;
 use core::any::{Any, TypeId};
+use core::fmt;
+use core::iter::Peekable;
+use core::mem::{needs_drop, size_of};
+use core::ops::Deref;
+use core::ptr::NonNull;
 use hashbrown::HashMap;
-use spin::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 pub struct ComponentsRef<'a, T> {
-    entities: &'a ChunkEntities,
+    pub(crate) entities: &'a ChunkEntities,
     pub(crate) values: RwLockReadGuard<'a, ComponentsImpl<T>>,
 }
 
 pub struct ComponentsMut<'a, T> {
-    entities: &'a ChunkEntities,
+    pub(crate) entities: &'a ChunkEntities,
     pub(crate) values: RwLockWriteGuard<'a, ComponentsImpl<T>>,
 }
 
@@ -21,6 +29,62 @@ impl<'a, T> ComponentsRef<'a, T> {
         let index = self.entities.index(entity)?;
         self.values.get(index)
     }
+
+    #[inline]
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.get(entity).is_some()
+    }
+
+    /// The tick at which the `T` of `entity` was last borrowed mutably, see `Chunk::tick`.
+    /// Returns `None` if the entity has no `T`.
+    #[inline]
+    pub fn last_changed(&self, entity: Entity) -> Option<u32> {
+        let index = self.entities.index(entity)?;
+        self.values.last_changed(index)
+    }
+
+    /// The number of entities having a `T`.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.values.len() == 0
+    }
+
+    /// Iterates over the entities having a `T`, in row order, along with their value.
+    pub fn iter_with_entities(&self) -> impl Iterator<Item = (Entity, &T)> + use<'_, 'a, T> {
+        self.entities
+            .iter()
+            .zip(self.values.iter())
+            .filter_map(|(entity, value)| Some((entity, value?)))
+    }
+
+    /// Returns the value at row `index`, as given by `Query::index_of`, without any check.
+    ///
+    /// # Safety
+    ///
+    /// Row `index` must exist and hold a value.
+    #[inline]
+    pub unsafe fn get_unchecked(&self, index: usize) -> &T {
+        // SAFETY: guaranteed by the caller.
+        unsafe { self.values.get_unchecked(index) }
+    }
+
+    /// Iterates over the values of every row, without checking whether each row holds one.
+    ///
+    /// # Safety
+    ///
+    /// Every entity of the chunk must have a `T`.
+    #[inline]
+    pub unsafe fn iter_dense_unchecked(&self) -> impl Iterator<Item = &T> + use<'_, 'a, T> {
+        // SAFETY: guaranteed by the caller.
+        self.values
+            .iter()
+            .map(|value| unsafe { value.unwrap_unchecked() })
+    }
 }
 
 impl<'a, T> ComponentsMut<'a, T> {
@@ -33,6 +97,22 @@ impl<'a, T> ComponentsMut<'a, T> {
         Ok(())
     }
 
+    /// Inserts every value into its entity. Stops at the first invalid entity, keeping the values
+    /// inserted before it.
+    pub fn insert_batch(
+        &mut self,
+        values: impl IntoIterator<Item = (Entity, T)>,
+    ) -> Result<(), Error> {
+        for (entity, value) in values {
+            let index = self
+                .entities
+                .index(entity)
+                .ok_or(Error::InvalidEntity(entity))?;
+            self.values.set(index, Some(value));
+        }
+        Ok(())
+    }
+
     pub fn remove(&mut self, entity: Entity) -> Result<(), Error> {
         let index = self
             .entities
@@ -42,89 +122,732 @@ impl<'a, T> ComponentsMut<'a, T> {
         Ok(())
     }
 
+    /// Removes the component from every entity for which `keep` returns false. Like `remove`, this
+    /// does not call component hooks.
+    pub fn retain(&mut self, mut keep: impl FnMut(Entity, &mut T) -> bool) {
+        for (index, entity) in self.entities.iter().enumerate() {
+            if self
+                .values
+                .get_mut(index)
+                .is_some_and(|value| !keep(entity, value))
+            {
+                self.values.set(index, None);
+            }
+        }
+    }
+
+    /// Removes the component from every entity, yielding the removed values in row order. The
+    /// values not yet yielded when the iterator is dropped are kept.
+    pub fn drain(&mut self) -> impl Iterator<Item = (Entity, T)> + use<'_, 'a, T> {
+        let values = &mut self.values;
+        self.entities
+            .iter()
+            .enumerate()
+            .filter_map(move |(index, entity)| Some((entity, values.remove(index)?)))
+    }
+
     pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
         let index = self.entities.index(entity)?;
         self.values.get_mut(index)
     }
 
+    /// Iterates over the entities having a `T`, in row order, along with their value.
+    pub fn iter_mut_with_entities(
+        &mut self,
+    ) -> impl Iterator<Item = (Entity, &mut T)> + use<'_, 'a, T> {
+        self.entities
+            .iter()
+            .zip(self.values.iter_mut())
+            .filter_map(|(entity, value)| Some((entity, value?)))
+    }
+
+    /// Returns the value at row `index`, as given by `Query::index_of`, without any check.
+    ///
+    /// # Safety
+    ///
+    /// Row `index` must exist and hold a value.
+    #[inline]
+    pub unsafe fn get_unchecked_mut(&mut self, index: usize) -> &mut T {
+        // SAFETY: guaranteed by the caller.
+        unsafe { self.values.get_unchecked_mut(index) }
+    }
+
+    /// Iterates over the values of every row, without checking whether each row holds one.
+    ///
+    /// # Safety
+    ///
+    /// Every entity of the chunk must have a `T`.
+    #[inline]
+    pub unsafe fn iter_dense_unchecked_mut(
+        &mut self,
+    ) -> impl Iterator<Item = &mut T> + use<'_, 'a, T> {
+        // SAFETY: guaranteed by the caller.
+        self.values
+            .iter_mut()
+            .map(|value| unsafe { value.unwrap_unchecked() })
+    }
+
+    /// Borrows the components of several entities mutably at once. Returns `None` if an entity
+    /// appears twice, is invalid, or has no `T`.
+    pub fn get_many_mut<const N: usize>(&mut self, entities: [Entity; N]) -> Option<[&mut T; N]> {
+        let mut indexes = [0; N];
+        for (index, entity) in indexes.iter_mut().zip(entities) {
+            *index = self.entities.index(entity)?;
+        }
+        self.values.get_many_mut(indexes)
+    }
+
     pub fn get(&self, entity: Entity) -> Option<&T> {
         let index = self.entities.index(entity)?;
         self.values.get(index)
     }
+
+    #[inline]
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.get(entity).is_some()
+    }
+
+    /// The tick at which the `T` of `entity` was last borrowed mutably, see `Chunk::tick`.
+    /// Returns `None` if the entity has no `T`.
+    #[inline]
+    pub fn last_changed(&self, entity: Entity) -> Option<u32> {
+        let index = self.entities.index(entity)?;
+        self.values.last_changed(index)
+    }
+
+    /// The number of entities having a `T`.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.values.len() == 0
+    }
+}
+
+/// A `ComponentsRef` that can be sent to another thread, e.g. for pathfinding running while the
+/// main thread renders. Holding it keeps writers out of the column, see
+/// `Chunk::shared_components_ref`.
+///
+/// With the `single-threaded` feature and without `sync` the column locks are not atomic, and it
+/// is neither `Send` nor `Sync`.
+pub struct SharedComponentsRef<'a, T>(ComponentsRef<'a, T>);
+
+// SAFETY: the entity table only holds plain data and an atomic, and the built-in storages only
+// hold `T`s, which are `Sync`. Columns with a custom storage, which may hold anything, are
+// refused by `ChunkComponents::shared_components_ref`.
+#[cfg(any(not(feature = "single-threaded"), feature = "sync"))]
+unsafe impl<T: Sync> Send for SharedComponentsRef<'_, T> {}
+
+// SAFETY: see `Send`.
+#[cfg(any(not(feature = "single-threaded"), feature = "sync"))]
+unsafe impl<T: Sync> Sync for SharedComponentsRef<'_, T> {}
+
+impl<'a, T> Deref for SharedComponentsRef<'a, T> {
+    type Target = ComponentsRef<'a, T>;
+
+    #[inline]
+    fn deref(&self) -> &ComponentsRef<'a, T> {
+        &self.0
+    }
+}
+
+/// A single component of a single entity, borrowed from its column.
+pub struct ComponentRef<'a, T> {
+    values: RwLockReadGuard<'a, ComponentsImpl<T>>,
+    index: usize,
+}
+
+impl<T> Deref for ComponentRef<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        // only constructed for rows holding a value
+        self.values.get(self.index).unwrap()
+    }
 }
 
+/// Keeps the columns in registration order, so that everything iterating over them, like the
+/// remove hooks of `Chunk::destroy`, runs in the same order on every run and platform.
 #[derive(Default)]
-pub(crate) struct ComponentsBuilder(HashMap<TypeId, Box<dyn ComponentStorage>>);
+pub(crate) struct ComponentsBuilder(ChunkComponents);
 
 impl ComponentsBuilder {
     pub fn with_component<T: 'static>(mut self) -> Self {
-        let vec: ComponentsImpl<T> = ComponentsImpl::new();
-        self.0.insert(TypeId::of::<T>(), Box::new(RwLock::new(vec)));
+        self.column_mut::<T>();
+        self
+    }
+
+    /// Must be called before any row is added.
+    pub fn with_storage<T: 'static>(mut self, kind: StorageKind) -> Self {
+        *self.column_mut::<T>().values.get_mut() = ComponentsImpl::new(kind, 0);
+        self
+    }
+
+    /// Must be called before any row is added.
+    pub fn with_custom_storage<T: Component>(mut self) -> Self {
+        *self.column_mut::<T>().values.get_mut() =
+            ComponentsImpl::with_storage(Box::new(T::Storage::default()));
+        self
+    }
+
+    pub fn with_clone_hook<T: Clone + 'static>(mut self) -> Self {
+        self.column_mut::<T>().hooks.clone = Some(T::clone);
+        self
+    }
+
+    pub fn with_map_entities_hook<T: MapEntities + 'static>(mut self) -> Self {
+        self.column_mut::<T>().hooks.map_entities = Some(T::map_entities);
+        self
+    }
+
+    pub fn with_debug_hook<T: fmt::Debug + 'static>(mut self) -> Self {
+        self.column_mut::<T>().hooks.debug = Some(|value, out| write!(out, "{value:?}"));
+        self
+    }
+
+    pub fn with_on_add<T: 'static>(mut self, hook: ComponentHook) -> Self {
+        self.column_mut::<T>().hooks.on_add = Some(hook);
         self
     }
 
+    pub fn with_on_remove<T: 'static>(mut self, hook: ComponentHook) -> Self {
+        self.column_mut::<T>().hooks.on_remove = Some(hook);
+        self
+    }
+
+    fn column_mut<T: 'static>(&mut self) -> &mut Column<T> {
+        self.0.register::<T>(0);
+        self.0
+            .column_mut(&TypeId::of::<T>())
+            .and_then(|column| column.as_any_mut().downcast_mut::<Column<T>>())
+            .unwrap()
+    }
+
     pub fn build(self) -> ChunkComponents {
-        ChunkComponents(self.0)
+        self.0
     }
 }
 
-pub(crate) struct ChunkComponents(HashMap<TypeId, Box<dyn ComponentStorage>>);
+/// Identifies the column of a component type within a chunk, see `Chunk::component_id`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ComponentId(usize);
+
+impl ComponentId {
+    /// The position of the column, in registration order.
+    #[inline]
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// The columns live in a `Vec` indexed by `ComponentId`, so that callers holding an id find
+/// them without hashing. `ids` is only looked up to get an id from a type.
+#[derive(Default)]
+pub(crate) struct ChunkComponents {
+    columns: Vec<(TypeId, Box<dyn ComponentStorage>)>,
+    ids: HashMap<TypeId, ComponentId>,
+    tick: u32,
+}
+
+/// The values of every column, as copied by `ChunkComponents::snapshot`.
+pub(crate) type ColumnSnapshots = Vec<(TypeId, Box<dyn Any>)>;
+
+/// The changed values of every column, as copied by `ChunkComponents::delta_since`.
+pub(crate) type ColumnDeltas = Vec<(TypeId, Box<dyn Any>)>;
 
 impl ChunkComponents {
     pub fn components_ref<'a, T: 'static>(
         &'a self,
         entities: &'a ChunkEntities,
-    ) -> Result<ComponentsRef<T>, Error> {
+    ) -> Result<ComponentsRef<'a, T>, Error> {
+        self.components_ref_by_id(entities, self.component_id::<T>()?)
+    }
+
+    pub fn components_mut<'a, T: 'static>(
+        &'a self,
+        entities: &'a ChunkEntities,
+    ) -> Result<ComponentsMut<'a, T>, Error> {
+        self.components_mut_by_id(entities, self.component_id::<T>()?)
+    }
+
+    pub fn shared_components_ref<'a, T: Sync + 'static>(
+        &'a self,
+        entities: &'a ChunkEntities,
+    ) -> Result<SharedComponentsRef<'a, T>, Error> {
+        let components = self.components_ref::<T>(entities)?;
+        if components.values.is_custom() {
+            return Err(Error::ComponentStorageNotShareable(type_name::<T>()));
+        }
+        Ok(SharedComponentsRef(components))
+    }
+
+    pub fn components_ref_by_id<'a, T: 'static>(
+        &'a self,
+        entities: &'a ChunkEntities,
+        id: ComponentId,
+    ) -> Result<ComponentsRef<'a, T>, Error> {
         let values = self
-            .components_rwlock()?
+            .components_rwlock_at(id)
+            .ok_or(Error::ComponentNotRegistered(type_name::<T>()))?
             .try_read()
             .ok_or(Error::ComponentAlreadyBorrowedMutably(type_name::<T>()))?;
         Ok(ComponentsRef { entities, values })
     }
 
-    pub fn components_mut<'a, T: 'static>(
+    pub fn components_mut_by_id<'a, T: 'static>(
         &'a self,
         entities: &'a ChunkEntities,
-    ) -> Result<ComponentsMut<T>, Error> {
+        id: ComponentId,
+    ) -> Result<ComponentsMut<'a, T>, Error> {
         let values = self
-            .components_rwlock()?
+            .components_rwlock_at(id)
+            .ok_or(Error::ComponentNotRegistered(type_name::<T>()))?
             .try_write()
             .ok_or(Error::ComponentAlreadyBorrowedMutably(type_name::<T>()))?;
         Ok(ComponentsMut { entities, values })
     }
 
+    /// Returns `None` if row `index` has no `T`, or if `T` is missing or borrowed mutably.
+    pub fn component_ref<T: 'static>(&self, index: usize) -> Option<ComponentRef<'_, T>> {
+        let values = self.components_rwlock::<T>().ok()?.try_read()?;
+        values.get(index)?;
+        Some(ComponentRef { values, index })
+    }
+
+    /// Accesses the values of `T` through `&mut self`, which needs no locking.
+    pub fn values_mut<T: 'static>(&mut self) -> Result<&mut ComponentsImpl<T>, Error> {
+        self.column_mut(&TypeId::of::<T>())
+            .ok_or(Error::ComponentNotRegistered(type_name::<T>()))?
+            .as_any_mut()
+            .downcast_mut::<Column<T>>()
+            .map(|column| column.values.get_mut())
+            .ok_or(Error::InternalStorageError(type_name::<T>()))
+    }
+
+    pub fn on_add<T: 'static>(&self) -> Option<ComponentHook> {
+        self.column::<T>().ok()?.hooks.on_add
+    }
+
+    pub fn on_remove<T: 'static>(&self) -> Option<ComponentHook> {
+        self.column::<T>().ok()?.hooks.on_remove
+    }
+
+    /// Returns the `on_add` hooks of the components of row `index`.
+    pub fn on_add_hooks(&mut self, index: usize) -> Vec<ComponentHook> {
+        self.columns
+            .iter_mut()
+            .filter_map(|(_, column)| column.has(index).then(|| column.on_add()).flatten())
+            .collect()
+    }
+
+    /// Returns the `on_remove` hooks of the components of row `index`.
+    pub fn on_remove_hooks(&mut self, index: usize) -> Vec<ComponentHook> {
+        self.columns
+            .iter_mut()
+            .filter_map(|(_, column)| column.has(index).then(|| column.on_remove()).flatten())
+            .collect()
+    }
+
+    /// Writes one line per component of row `index`, see `ComponentStorage::debug_row`.
+    pub fn debug_row(&self, index: usize, out: &mut dyn fmt::Write) -> fmt::Result {
+        let mut line = String::new();
+        for (_, column) in self.columns.iter() {
+            line.clear();
+            if column.debug_row(index, &mut line)? {
+                writeln!(out, "  {line}")?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn diagnostics(&self) -> Result<Vec<ColumnDiagnostics>, Error> {
+        self.columns
+            .iter()
+            .map(|(_, column)| column.diagnostics())
+            .collect()
+    }
+
+    /// The type names of the columns that are currently borrowed mutably.
+    pub fn borrowed_mutably(&self) -> impl Iterator<Item = &'static str> + use<'_> {
+        self.columns
+            .iter()
+            .filter(|(_, column)| column.is_borrowed_mutably())
+            .map(|(_, column)| column.type_name())
+    }
+
+    /// The type id and type name of every registered component type.
+    pub fn types(&self) -> impl Iterator<Item = (TypeId, &'static str)> + use<'_> {
+        self.columns
+            .iter()
+            .map(|(type_id, column)| (*type_id, column.type_name()))
+    }
+
+    pub(crate) fn column_dyn(&self, type_id: TypeId) -> Result<&dyn ComponentStorage, Error> {
+        let id = self
+            .ids
+            .get(&type_id)
+            .ok_or(Error::ComponentTypeNotRegistered(type_id))?;
+        Ok(self.columns[id.0].1.as_ref())
+    }
+
+    pub(crate) fn column_dyn_mut(
+        &mut self,
+        type_id: TypeId,
+    ) -> Result<&mut Box<dyn ComponentStorage>, Error> {
+        self.column_mut(&type_id)
+            .ok_or(Error::ComponentTypeNotRegistered(type_id))
+    }
+
+    /// Adds a column for `T` holding `rows` empty rows, unless `T` is already registered.
+    pub fn register<T: 'static>(&mut self, rows: usize) {
+        if !self.ids.contains_key(&TypeId::of::<T>()) {
+            self.insert(TypeId::of::<T>(), Box::new(Column::<T>::new(rows)));
+        }
+    }
+
+    /// Returns the id of the column of `T`, which stays the same for the whole life of the
+    /// chunk.
+    pub fn component_id<T: 'static>(&self) -> Result<ComponentId, Error> {
+        self.ids
+            .get(&TypeId::of::<T>())
+            .copied()
+            .ok_or(Error::ComponentNotRegistered(type_name::<T>()))
+    }
+
+    /// Returns the values of `T` if they are stored in the column `id`.
+    pub(crate) fn components_rwlock_at<T: 'static>(
+        &self,
+        id: ComponentId,
+    ) -> Option<&RwLock<ComponentsImpl<T>>> {
+        self.columns
+            .get(id.0)?
+            .1
+            .as_any()
+            .downcast_ref::<Column<T>>()
+            .map(|column| &column.values)
+    }
+
+    pub fn extend_none(&mut self, rows: usize) {
+        for (_, column) in self.columns.iter_mut() {
+            column.extend_none(rows);
+        }
+    }
+
+    #[inline]
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    pub fn advance_tick(&mut self) -> u32 {
+        self.tick = self.tick.wrapping_add(1);
+        for (_, column) in self.columns.iter_mut() {
+            column.set_tick(self.tick);
+        }
+        self.tick
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        for (_, column) in self.columns.iter_mut() {
+            column.reserve(additional);
+        }
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        for (_, column) in self.columns.iter_mut() {
+            column.shrink_to_fit();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for (_, column) in self.columns.iter_mut() {
+            column.clear();
+        }
+    }
+
+    /// Rewrites the entities held by the rows starting at `first_index`, in every column
+    /// registered with a `MapEntities` hook.
+    pub fn map_entities(&mut self, first_index: usize, map: &EntityMap) {
+        for (_, column) in self.columns.iter_mut() {
+            column.map_entities(first_index, map);
+        }
+    }
+
+    /// Copies the values of every column. Fails if a column holds values but was registered
+    /// without a clone hook.
+    pub fn snapshot(&self) -> Result<ColumnSnapshots, Error> {
+        self.columns
+            .iter()
+            .map(|(type_id, column)| Ok((*type_id, column.snapshot()?)))
+            .collect()
+    }
+
+    /// Puts back the values copied by `snapshot` and the ticks they were changed at, leaving
+    /// `rows` rows in every column and `tick` as the current tick. Columns registered after the
+    /// snapshot was taken end up empty.
+    pub fn restore(
+        &mut self,
+        rows: usize,
+        tick: u32,
+        snapshot: ColumnSnapshots,
+    ) -> Result<(), Error> {
+        let mut snapshot = snapshot.into_iter().collect::<HashMap<_, _>>();
+        self.tick = tick;
+        for (type_id, column) in self.columns.iter_mut() {
+            column.set_tick(tick);
+            column.restore(rows, snapshot.remove(type_id))?;
+        }
+        Ok(())
+    }
+
+    /// Copies the values of every column changed at or after `tick`, with `None` for the removed
+    /// ones. Fails if a changed value was registered without a clone hook.
+    pub fn delta_since(&self, entities: &ChunkEntities, tick: u32) -> Result<ColumnDeltas, Error> {
+        self.columns
+            .iter()
+            .map(|(type_id, column)| Ok((*type_id, column.delta_since(entities, tick)?)))
+            .collect()
+    }
+
+    /// Puts the values copied by `delta_since` in the rows of the same entities.
+    pub fn apply_delta(
+        &mut self,
+        entities: &ChunkEntities,
+        delta: ColumnDeltas,
+    ) -> Result<(), Error> {
+        for (type_id, delta) in delta {
+            self.column_mut(&type_id)
+                .ok_or(Error::ComponentTypeNotRegistered(type_id))?
+                .apply_delta(entities, delta)?;
+        }
+        Ok(())
+    }
+
+    pub fn retain_rows(&mut self, keep: &[bool]) {
+        for (_, column) in self.columns.iter_mut() {
+            column.retain_rows(keep);
+        }
+    }
+
     pub fn push_none(&mut self) -> Result<(), Error> {
-        for column in self.0.values_mut() {
+        for (_, column) in self.columns.iter_mut() {
             column.push_none()?;
         }
         Ok(())
     }
 
     pub fn swap_remove(&mut self, index: usize) -> Result<(), Error> {
-        for column in self.0.values_mut() {
+        for (_, column) in self.columns.iter_mut() {
             column.swap_remove(index)?;
         }
         Ok(())
     }
 
+    /// Returns the name of the first component of row `index` that has no column in `other`.
+    pub fn missing_in(&mut self, index: usize, other: &ChunkComponents) -> Option<&'static str> {
+        self.columns.iter_mut().find_map(|(type_id, column)| {
+            (column.has(index) && !other.ids.contains_key(type_id)).then(|| column.type_name())
+        })
+    }
+
+    /// Returns the name of the first column of `self` holding values but missing from `other`.
+    pub fn missing_columns_in(&mut self, other: &ChunkComponents) -> Option<&'static str> {
+        self.columns.iter_mut().find_map(|(type_id, column)| {
+            (!column.is_empty() && !other.ids.contains_key(type_id)).then(|| column.type_name())
+        })
+    }
+
+    /// Appends all the rows of `other`, which holds `rows` rows, after the rows of `self`.
+    pub fn append(&mut self, other: &mut ChunkComponents, rows: usize) -> Result<(), Error> {
+        for (type_id, column) in self.columns.iter_mut() {
+            match other.column_mut(type_id) {
+                Some(other_column) => column.append(other_column.as_mut())?,
+                None => column.extend_none(rows),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the name of the first component of row `index` without a clone hook, ignoring the
+    /// types in `skip`.
+    pub fn non_cloneable(&mut self, index: usize, skip: &[TypeId]) -> Option<&'static str> {
+        self.columns.iter_mut().find_map(|(type_id, column)| {
+            (!skip.contains(type_id) && !column.can_clone(index)).then(|| column.type_name())
+        })
+    }
+
+    /// Clones every component of row `from` into row `to`, ignoring the types in `skip`.
+    pub fn clone_row(&mut self, from: usize, to: usize, skip: &[TypeId]) -> Result<(), Error> {
+        for (type_id, column) in self.columns.iter_mut() {
+            if !skip.contains(type_id) {
+                column.clone_row(from, to)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves every component of row `index` into row `other_index` of `other`.
+    pub fn move_row(
+        &mut self,
+        index: usize,
+        other: &mut ChunkComponents,
+        other_index: usize,
+    ) -> Result<(), Error> {
+        for (type_id, column) in self.columns.iter_mut() {
+            if !column.has(index) {
+                continue;
+            }
+            let other_column = other
+                .column_mut(type_id)
+                .ok_or(Error::ComponentNotRegistered(column.type_name()))?;
+            column.move_to(index, other_column.as_mut(), other_index)?;
+        }
+        Ok(())
+    }
+
     fn components_rwlock<T: 'static>(&self) -> Result<&RwLock<ComponentsImpl<T>>, Error> {
-        Ok(self
-            .0
-            .get(&TypeId::of::<T>())
-            .ok_or(Error::ComponentNotRegistered(type_name::<T>()))?
+        self.column::<T>().map(|column| &column.values)
+    }
+
+    fn column<T: 'static>(&self) -> Result<&Column<T>, Error> {
+        self.columns[self.component_id::<T>()?.0]
+            .1
             .as_any()
-            .downcast_ref::<RwLock<ComponentsImpl<T>>>()
-            .ok_or(Error::InternalStorageError(type_name::<T>()))?)
+            .downcast_ref::<Column<T>>()
+            .ok_or(Error::InternalStorageError(type_name::<T>()))
+    }
+
+    fn insert(&mut self, type_id: TypeId, mut column: Box<dyn ComponentStorage>) {
+        column.set_tick(self.tick);
+        self.ids.insert(type_id, ComponentId(self.columns.len()));
+        self.columns.push((type_id, column));
+    }
+
+    fn column_mut(&mut self, type_id: &TypeId) -> Option<&mut Box<dyn ComponentStorage>> {
+        let id = *self.ids.get(type_id)?;
+        Some(&mut self.columns[id.0].1)
+    }
+}
+
+/// A component read through `Chunk::component_dyn`, keeping its column locked for reading.
+pub struct ComponentDynRef<'a>(Box<dyn Deref<Target = dyn Any> + 'a>);
+
+impl Deref for ComponentDynRef<'_> {
+    type Target = dyn Any;
+
+    #[inline]
+    fn deref(&self) -> &dyn Any {
+        &**self.0
+    }
+}
+
+struct DynValue<'a, T> {
+    values: RwLockReadGuard<'a, ComponentsImpl<T>>,
+    index: usize,
+}
+
+impl<T: 'static> Deref for DynValue<'_, T> {
+    type Target = dyn Any;
+
+    #[inline]
+    fn deref(&self) -> &dyn Any {
+        // checked by `get_dyn` and kept by the lock
+        self.values.get(self.index).unwrap()
     }
 }
 
 pub(crate) trait ComponentStorage {
     fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn type_name(&self) -> &'static str;
     fn swap_remove(&mut self, index: usize) -> Result<(), Error>;
     fn push_none(&mut self) -> Result<(), Error>;
+    fn has(&mut self, index: usize) -> bool;
+    /// Returns true if no row holds a value.
+    fn is_empty(&mut self) -> bool;
+    fn extend_none(&mut self, rows: usize);
+    fn set_tick(&mut self, tick: u32);
+    fn reserve(&mut self, additional: usize);
+    fn shrink_to_fit(&mut self);
+    fn clear(&mut self);
+    /// Keeps the rows whose entry in `keep` is true, preserving their order.
+    fn retain_rows(&mut self, keep: &[bool]);
+    /// Moves all the rows of `other`, which must store the same type, to the end of this column.
+    fn append(&mut self, other: &mut dyn ComponentStorage) -> Result<(), Error>;
+    /// Returns false if row `index` holds a value but no clone hook was registered.
+    fn can_clone(&mut self, index: usize) -> bool;
+    fn on_add(&self) -> Option<ComponentHook>;
+    fn on_remove(&self) -> Option<ComponentHook>;
+    /// Reads the value at `index` without marking it as changed.
+    fn get_dyn(&self, index: usize) -> Result<Option<ComponentDynRef<'_>>, Error>;
+    fn get_dyn_mut(&mut self, index: usize) -> Option<&mut dyn Any>;
+    fn diagnostics(&self) -> Result<ColumnDiagnostics, Error>;
+    fn is_borrowed_mutably(&self) -> bool;
+    /// Writes the type name of the value at `index` if there is one, followed by the value if a
+    /// debug hook was registered. Returns false if the row holds no value or the column is
+    /// borrowed mutably.
+    fn debug_row(&self, index: usize, out: &mut dyn fmt::Write) -> Result<bool, fmt::Error>;
+    /// Stores `value`, which must be of the column's type, returning the previous value.
+    fn set_dyn(
+        &mut self,
+        index: usize,
+        value: Option<Box<dyn Any>>,
+    ) -> Result<Option<Box<dyn Any>>, Error>;
+    fn map_entities(&mut self, first_index: usize, map: &EntityMap);
+    fn clone_row(&mut self, from: usize, to: usize) -> Result<(), Error>;
+    /// Clones every value through the clone hook, see `ChunkComponents::snapshot`.
+    fn snapshot(&self) -> Result<Box<dyn Any>, Error>;
+    /// Replaces the column with `rows` rows holding the values of `snapshot`, if any, changed at
+    /// the ticks they were when it was taken.
+    fn restore(&mut self, rows: usize, snapshot: Option<Box<dyn Any>>) -> Result<(), Error>;
+    /// Clones the values changed at or after `tick` through the clone hook, keyed by entity.
+    fn delta_since(&self, entities: &ChunkEntities, tick: u32) -> Result<Box<dyn Any>, Error>;
+    fn apply_delta(&mut self, entities: &ChunkEntities, delta: Box<dyn Any>) -> Result<(), Error>;
+    /// Moves the value at `index` into `other_index` of `other`, which must store the same type.
+    fn move_to(
+        &mut self,
+        index: usize,
+        other: &mut dyn ComponentStorage,
+        other_index: usize,
+    ) -> Result<(), Error>;
 }
 
-impl<T> ComponentStorage for RwLock<ComponentsImpl<T>>
+/// A registered component type: its values and the type-erased hooks available for it.
+pub(crate) struct Column<T> {
+    values: RwLock<ComponentsImpl<T>>,
+    hooks: ComponentHooks<T>,
+}
+
+pub(crate) struct ComponentHooks<T> {
+    clone: Option<fn(&T) -> T>,
+    on_add: Option<ComponentHook>,
+    on_remove: Option<ComponentHook>,
+    map_entities: Option<fn(&mut T, &EntityMap)>,
+    debug: Option<fn(&T, &mut dyn fmt::Write) -> fmt::Result>,
+}
+
+/// Called with the entity whose component was added or removed, see `ChunkBuilder::on_add`.
+pub type ComponentHook = fn(Entity, &mut Commands);
+
+impl<T: 'static> Column<T> {
+    fn new(rows: usize) -> Self {
+        Self {
+            values: RwLock::new(ComponentsImpl::new(StorageKind::Dense, rows)),
+            hooks: ComponentHooks {
+                clone: None,
+                on_add: None,
+                on_remove: None,
+                map_entities: None,
+                debug: None,
+            },
+        }
+    }
+}
+
+impl<T> ComponentStorage for Column<T>
 where
     T: 'static,
 {
@@ -132,60 +855,1025 @@ where
         self
     }
 
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn type_name(&self) -> &'static str {
+        type_name::<T>()
+    }
+
     fn swap_remove(&mut self, index: usize) -> Result<(), Error> {
-        self.try_write()
+        self.values
+            .try_write()
             .ok_or(Error::ComponentAlreadyBorrowedMutably(type_name::<T>()))?
             .swap_remove(index);
         Ok(())
     }
 
     fn push_none(&mut self) -> Result<(), Error> {
-        self.try_write()
+        self.values
+            .try_write()
             .ok_or(Error::ComponentAlreadyBorrowedMutably(type_name::<T>()))?
             .push(None);
         Ok(())
     }
+
+    fn has(&mut self, index: usize) -> bool {
+        self.values.get_mut().get(index).is_some()
+    }
+
+    fn is_empty(&mut self) -> bool {
+        self.values.get_mut().len() == 0
+    }
+
+    fn extend_none(&mut self, rows: usize) {
+        self.values.get_mut().extend_none(rows);
+    }
+
+    fn set_tick(&mut self, tick: u32) {
+        self.values.get_mut().set_tick(tick);
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.values.get_mut().reserve(additional);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.values.get_mut().shrink_to_fit();
+    }
+
+    fn clear(&mut self) {
+        self.values.get_mut().clear();
+    }
+
+    fn retain_rows(&mut self, keep: &[bool]) {
+        self.values.get_mut().retain_rows(keep);
+    }
+
+    fn append(&mut self, other: &mut dyn ComponentStorage) -> Result<(), Error> {
+        let other = other
+            .as_any_mut()
+            .downcast_mut::<Self>()
+            .ok_or(Error::InternalStorageError(type_name::<T>()))?;
+        self.values.get_mut().append(other.values.get_mut());
+        Ok(())
+    }
+
+    fn can_clone(&mut self, index: usize) -> bool {
+        self.hooks.clone.is_some() || !self.has(index)
+    }
+
+    fn on_add(&self) -> Option<ComponentHook> {
+        self.hooks.on_add
+    }
+
+    fn on_remove(&self) -> Option<ComponentHook> {
+        self.hooks.on_remove
+    }
+
+    fn debug_row(&self, index: usize, out: &mut dyn fmt::Write) -> Result<bool, fmt::Error> {
+        let Some(values) = self.values.try_read() else {
+            return Ok(false);
+        };
+        let Some(value) = values.get(index) else {
+            return Ok(false);
+        };
+        out.write_str(type_name::<T>())?;
+        if let Some(debug) = self.hooks.debug {
+            out.write_str(": ")?;
+            debug(value, out)?;
+        }
+        Ok(true)
+    }
+
+    fn is_borrowed_mutably(&self) -> bool {
+        self.values.try_read().is_none()
+    }
+
+    fn diagnostics(&self) -> Result<ColumnDiagnostics, Error> {
+        let values = self
+            .values
+            .try_read()
+            .ok_or(Error::ComponentAlreadyBorrowedMutably(type_name::<T>()))?;
+        Ok(ColumnDiagnostics {
+            type_name: type_name::<T>(),
+            rows: values.rows(),
+            values: values.len(),
+            heap_bytes: values.heap_bytes(),
+        })
+    }
+
+    fn get_dyn(&self, index: usize) -> Result<Option<ComponentDynRef<'_>>, Error> {
+        let values = self
+            .values
+            .try_read()
+            .ok_or(Error::ComponentAlreadyBorrowedMutably(type_name::<T>()))?;
+        if values.get(index).is_none() {
+            return Ok(None);
+        }
+        Ok(Some(ComponentDynRef(Box::new(DynValue { values, index }))))
+    }
+
+    fn get_dyn_mut(&mut self, index: usize) -> Option<&mut dyn Any> {
+        self.values
+            .get_mut()
+            .get_mut(index)
+            .map(|value| value as &mut dyn Any)
+    }
+
+    fn set_dyn(
+        &mut self,
+        index: usize,
+        value: Option<Box<dyn Any>>,
+    ) -> Result<Option<Box<dyn Any>>, Error> {
+        let value = match value {
+            Some(value) => Some(
+                *value
+                    .downcast::<T>()
+                    .map_err(|_| Error::ComponentTypeMismatch(type_name::<T>()))?,
+            ),
+            None => None,
+        };
+        let values = self.values.get_mut();
+        let previous = values.take(index);
+        values.set(index, value);
+        Ok(previous.map(|value| Box::new(value) as Box<dyn Any>))
+    }
+
+    fn map_entities(&mut self, first_index: usize, map: &EntityMap) {
+        let Some(map_entities) = self.hooks.map_entities else {
+            return;
+        };
+        for value in self.values.get_mut().iter_mut().skip(first_index).flatten() {
+            map_entities(value, map);
+        }
+    }
+
+    fn clone_row(&mut self, from: usize, to: usize) -> Result<(), Error> {
+        let values = self.values.get_mut();
+        let Some(value) = values.get(from) else {
+            values.set(to, None);
+            return Ok(());
+        };
+        let clone = self
+            .hooks
+            .clone
+            .ok_or(Error::ComponentNotCloneable(type_name::<T>()))?;
+        let value = clone(value);
+        values.set(to, Some(value));
+        Ok(())
+    }
+
+    fn snapshot(&self) -> Result<Box<dyn Any>, Error> {
+        let values = self
+            .values
+            .try_read()
+            .ok_or(Error::ComponentAlreadyBorrowedMutably(type_name::<T>()))?;
+        let mut snapshot = Vec::with_capacity(values.len());
+        for (index, value) in values.iter().enumerate() {
+            if let Some(value) = value {
+                let clone = self
+                    .hooks
+                    .clone
+                    .ok_or(Error::ComponentNotCloneable(type_name::<T>()))?;
+                snapshot.push((index, clone(value), values.changed[index]));
+            }
+        }
+        Ok(Box::new(snapshot))
+    }
+
+    fn restore(&mut self, rows: usize, snapshot: Option<Box<dyn Any>>) -> Result<(), Error> {
+        let snapshot = match snapshot {
+            Some(snapshot) => *snapshot
+                .downcast::<Vec<(usize, T, u32)>>()
+                .map_err(|_| Error::InternalStorageError(type_name::<T>()))?,
+            None => Vec::new(),
+        };
+        let values = self.values.get_mut();
+        values.clear();
+        values.extend_none(rows);
+        for (index, value, changed) in snapshot {
+            values.set(index, Some(value));
+            values.changed[index] = changed;
+        }
+        Ok(())
+    }
+
+    fn delta_since(&self, entities: &ChunkEntities, tick: u32) -> Result<Box<dyn Any>, Error> {
+        let values = self
+            .values
+            .try_read()
+            .ok_or(Error::ComponentAlreadyBorrowedMutably(type_name::<T>()))?;
+        let mut delta = Vec::new();
+        for (index, value) in values.iter().enumerate() {
+            if (values.changed[index].wrapping_sub(tick) as i32) < 0 {
+                continue;
+            }
+            let value = match value {
+                Some(value) => {
+                    let clone = self
+                        .hooks
+                        .clone
+                        .ok_or(Error::ComponentNotCloneable(type_name::<T>()))?;
+                    Some(clone(value))
+                }
+                None => None,
+            };
+            delta.push((entities.at(index).unwrap(), value));
+        }
+        Ok(Box::new(delta))
+    }
+
+    fn apply_delta(&mut self, entities: &ChunkEntities, delta: Box<dyn Any>) -> Result<(), Error> {
+        let delta = *delta
+            .downcast::<Vec<(Entity, Option<T>)>>()
+            .map_err(|_| Error::InternalStorageError(type_name::<T>()))?;
+        let values = self.values.get_mut();
+        for (entity, value) in delta {
+            let index = entities.index(entity).ok_or(Error::InvalidEntity(entity))?;
+            values.set(index, value);
+        }
+        Ok(())
+    }
+
+    fn move_to(
+        &mut self,
+        index: usize,
+        other: &mut dyn ComponentStorage,
+        other_index: usize,
+    ) -> Result<(), Error> {
+        let other = other
+            .as_any_mut()
+            .downcast_mut::<Self>()
+            .ok_or(Error::InternalStorageError(type_name::<T>()))?;
+        let value = self.values.get_mut().take(index);
+        other.values.get_mut().set(other_index, value);
+        Ok(())
+    }
+}
+
+/// How the values of a component type are stored.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum StorageKind {
+    /// One slot per row, fastest to iterate. Suits components most entities have.
+    #[default]
+    Dense,
+    /// Only the rows holding a value take memory. Suits components few entities have.
+    Sparse,
+}
+
+pub struct ComponentsImpl<T> {
+    rows: Rows<T>,
+    present: Bitset,
+    // number of rows holding a value
+    len: usize,
+    // the tick at which each row was last borrowed mutably, see `Chunk::tick`
+    changed: Vec<u32>,
+    tick: u32,
+    // whether the rows are kept by a `Storage` implemented outside of this crate
+    custom: bool,
 }
 
-pub struct ComponentsImpl<T>(Vec<Option<T>>);
+enum Rows<T> {
+    Dense(Vec<Option<T>>),
+    /// Values stored by row, only for the rows in `present`.
+    Keyed(Box<dyn Storage<T>>),
+    /// Zero-sized types without drop glue, whose values are entirely described by `present`.
+    Marker,
+}
+
+impl<T: 'static> ComponentsImpl<T> {
+    fn new(kind: StorageKind, row_count: usize) -> Self {
+        let rows = match kind {
+            _ if size_of::<T>() == 0 && !needs_drop::<T>() => Rows::Marker,
+            StorageKind::Dense => Rows::Dense(Vec::new()),
+            StorageKind::Sparse => Rows::Keyed(Box::new(BTreeMap::<usize, T>::new())),
+        };
+        Self::with_rows(rows, row_count)
+    }
+
+    fn with_storage(storage: Box<dyn Storage<T>>) -> Self {
+        let mut values = Self::with_rows(Rows::Keyed(storage), 0);
+        values.custom = true;
+        values
+    }
+}
 
 impl<T> ComponentsImpl<T> {
-    fn new() -> Self {
-        Self(Vec::new())
+    fn with_rows(rows: Rows<T>, row_count: usize) -> Self {
+        let mut values = Self {
+            rows,
+            present: Bitset::new(),
+            len: 0,
+            changed: Vec::new(),
+            tick: 0,
+            custom: false,
+        };
+        values.extend_none(row_count);
+        values
     }
 
     #[inline]
     pub fn iter(&self) -> impl Iterator<Item = Option<&T>> + use<'_, T> {
-        self.0.iter().map(|v| v.as_ref())
+        match &self.rows {
+            Rows::Dense(values) => RowsIter::Dense(values.iter(), Option::as_ref),
+            Rows::Keyed(values) => RowsIter::Keyed(KeyedRows::new(self.rows(), values.iter())),
+            Rows::Marker => RowsIter::Marker(MarkerRows::new(&self.present), |_| &*marker()),
+        }
     }
 
     #[inline]
     pub fn iter_mut(&mut self) -> impl Iterator<Item = Option<&mut T>> + use<'_, T> {
-        self.0.iter_mut().map(|v| v.as_mut())
+        self.changed.fill(self.tick);
+        let rows = self.rows();
+        match &mut self.rows {
+            Rows::Dense(values) => RowsIter::Dense(values.iter_mut(), Option::as_mut),
+            Rows::Keyed(values) => RowsIter::Keyed(KeyedRows::new(rows, values.iter_mut())),
+            Rows::Marker => RowsIter::Marker(MarkerRows::new(&self.present), |_| marker()),
+        }
     }
 
     #[inline]
     pub fn set(&mut self, index: usize, value: Option<T>) {
-        self.0[index] = value;
+        let had_value = self.present.contains(index);
+        self.present.set(index, value.is_some());
+        self.len += value.is_some() as usize;
+        self.len -= had_value as usize;
+        self.changed[index] = self.tick;
+        match &mut self.rows {
+            Rows::Dense(values) => values[index] = value,
+            Rows::Keyed(values) => match value {
+                Some(value) => values.insert(index, value),
+                None => drop(values.remove(index)),
+            },
+            Rows::Marker => core::mem::forget(value),
+        }
+    }
+
+    #[inline]
+    /// Like `take`, marking the row as changed for `Chunk::delta_since` to send the removal.
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        self.changed[index] = self.tick;
+        self.take(index)
+    }
+
+    pub fn take(&mut self, index: usize) -> Option<T> {
+        let had_value = self.present.contains(index);
+        self.present.set(index, false);
+        self.len -= had_value as usize;
+        match &mut self.rows {
+            Rows::Dense(values) => values[index].take(),
+            Rows::Keyed(values) => values.remove(index),
+            // SAFETY: `T` is zero-sized, so reading it reads no memory, and a value was forgotten
+            // when it was stored.
+            Rows::Marker => had_value.then(|| unsafe { NonNull::dangling().read() }),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.changed[index] = self.tick;
+        match &mut self.rows {
+            Rows::Dense(values) => values[index].as_mut(),
+            Rows::Keyed(values) => values.get_mut(index),
+            Rows::Marker => self.present.contains(index).then(marker),
+        }
     }
 
     #[inline]
-    fn get_mut(&mut self, index: usize) -> Option<&mut T> {
-        self.0[index].as_mut()
+    pub(crate) fn len(&self) -> usize {
+        self.len
     }
 
+    /// Whether the rows are kept by a `Storage` implemented outside of this crate.
     #[inline]
-    fn get(&self, index: usize) -> Option<&T> {
-        self.0[index].as_ref()
+    pub(crate) fn is_custom(&self) -> bool {
+        self.custom
+    }
+
+    /// Returns `None` if two indexes are equal or one of the rows has no value.
+    #[inline]
+    fn get_many_mut<const N: usize>(&mut self, indexes: [usize; N]) -> Option<[&mut T; N]> {
+        for (i, index) in indexes.iter().enumerate() {
+            if indexes[..i].contains(index) || !self.present.contains(*index) {
+                return None;
+            }
+        }
+        for index in indexes {
+            self.changed[index] = self.tick;
+        }
+        match &mut self.rows {
+            Rows::Dense(values) => {
+                let values = values.get_disjoint_mut(indexes).ok()?;
+                Some(values.map(|value| value.as_mut().unwrap()))
+            }
+            Rows::Keyed(values) => {
+                let mut found = [const { None }; N];
+                for (index, value) in values.iter_mut() {
+                    if let Some(i) = indexes.iter().position(|i| *i == index) {
+                        found[i] = Some(value);
+                    }
+                }
+                if found.iter().any(|value| value.is_none()) {
+                    return None;
+                }
+                Some(found.map(|value| value.unwrap()))
+            }
+            Rows::Marker => Some([(); N].map(|_| marker())),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// Row `index` must exist and hold a value.
+    #[inline]
+    pub(crate) unsafe fn get_unchecked(&self, index: usize) -> &T {
+        // SAFETY: guaranteed by the caller.
+        unsafe {
+            match &self.rows {
+                Rows::Dense(values) => values.get_unchecked(index).as_ref().unwrap_unchecked(),
+                Rows::Keyed(values) => values.get(index).unwrap_unchecked(),
+                Rows::Marker => marker(),
+            }
+        }
+    }
+
+    /// # Safety
+    ///
+    /// Row `index` must exist and hold a value.
+    #[inline]
+    pub(crate) unsafe fn get_unchecked_mut(&mut self, index: usize) -> &mut T {
+        // SAFETY: guaranteed by the caller.
+        unsafe {
+            *self.changed.get_unchecked_mut(index) = self.tick;
+            match &mut self.rows {
+                Rows::Dense(values) => values.get_unchecked_mut(index).as_mut().unwrap_unchecked(),
+                Rows::Keyed(values) => values.get_mut(index).unwrap_unchecked(),
+                Rows::Marker => marker(),
+            }
+        }
+    }
+
+    #[inline]
+    pub(crate) fn get(&self, index: usize) -> Option<&T> {
+        match &self.rows {
+            Rows::Dense(values) => values[index].as_ref(),
+            Rows::Keyed(values) => values.get(index),
+            Rows::Marker => self.present.contains(index).then(|| &*marker()),
+        }
+    }
+
+    /// The rows holding a value.
+    #[inline]
+    pub(crate) fn present(&self) -> &Bitset {
+        &self.present
+    }
+
+    /// The tick at which row `index` was last borrowed mutably, if it holds a value.
+    #[inline]
+    pub(crate) fn last_changed(&self, index: usize) -> Option<u32> {
+        self.present.contains(index).then(|| self.changed[index])
+    }
+
+    #[inline]
+    pub(crate) fn set_tick(&mut self, tick: u32) {
+        self.tick = tick;
+    }
+
+    #[inline]
+    fn rows(&self) -> usize {
+        self.present.len()
+    }
+
+    /// Returns `None` for values kept by a `Storage`, which may not be shareable.
+    #[cfg(feature = "std")]
+    pub(crate) fn shared_rows(&self) -> Option<SharedRows<'_, T>> {
+        (!matches!(self.rows, Rows::Keyed(_))).then_some(SharedRows(self))
+    }
+
+    /// Returns `None` for values kept by a `Storage`, which can only lend one of them at a time.
+    #[cfg(feature = "std")]
+    pub(crate) fn mut_rows(&mut self) -> Option<MutRows<'_, T>> {
+        let values = match &mut self.rows {
+            Rows::Dense(values) => values.as_mut_ptr(),
+            Rows::Keyed(_) => return None,
+            Rows::Marker => core::ptr::null_mut(),
+        };
+        Some(MutRows {
+            values,
+            changed: self.changed.as_mut_ptr(),
+            present: &self.present,
+            tick: self.tick,
+            _values: core::marker::PhantomData,
+        })
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    pub(crate) fn is_keyed(&self) -> bool {
+        matches!(self.rows, Rows::Keyed(_))
+    }
+
+    /// The heap memory used by the column. Custom storages are assumed to spend a `usize` key
+    /// per value, like the sparse one.
+    fn heap_bytes(&self) -> usize {
+        let rows = match &self.rows {
+            Rows::Dense(values) => values.capacity() * size_of::<Option<T>>(),
+            Rows::Keyed(_) => self.len * (size_of::<usize>() + size_of::<T>()),
+            Rows::Marker => 0,
+        };
+        rows + self.present.heap_bytes() + self.changed.capacity() * size_of::<u32>()
     }
 
     #[inline]
     fn push(&mut self, value: Option<T>) {
-        self.0.push(value);
+        let index = self.rows();
+        self.present.push(value.is_some());
+        self.len += value.is_some() as usize;
+        self.changed.push(self.tick);
+        match &mut self.rows {
+            Rows::Dense(values) => values.push(value),
+            Rows::Keyed(values) => {
+                if let Some(value) = value {
+                    values.insert(index, value);
+                }
+            }
+            Rows::Marker => core::mem::forget(value),
+        }
     }
 
     #[inline]
     fn swap_remove(&mut self, index: usize) {
-        self.0.swap_remove(index);
+        let last = self.rows() - 1;
+        self.len -= self.present.contains(index) as usize;
+        self.present.swap_remove(index);
+        self.changed.swap_remove(index);
+        match &mut self.rows {
+            Rows::Dense(values) => drop(values.swap_remove(index)),
+            Rows::Keyed(values) => {
+                drop(values.remove(index));
+                if let Some(value) = values.remove(last) {
+                    values.insert(index, value);
+                }
+            }
+            Rows::Marker => {}
+        }
+    }
+
+    fn extend_none(&mut self, additional: usize) {
+        self.present.extend_unset(additional);
+        self.changed
+            .resize(self.changed.len() + additional, self.tick);
+        if let Rows::Dense(values) = &mut self.rows {
+            values.resize_with(values.len() + additional, || None);
+        }
+    }
+
+    /// Custom and sparse storages allocate per value, so only dense columns are pre-sized.
+    fn reserve(&mut self, additional: usize) {
+        self.present.reserve(additional);
+        self.changed.reserve(additional);
+        if let Rows::Dense(values) = &mut self.rows {
+            values.reserve(additional);
+        }
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.present.shrink_to_fit();
+        self.changed.shrink_to_fit();
+        if let Rows::Dense(values) = &mut self.rows {
+            values.shrink_to_fit();
+        }
+    }
+
+    fn clear(&mut self) {
+        self.present.clear();
+        self.len = 0;
+        self.changed.clear();
+        match &mut self.rows {
+            Rows::Dense(values) => values.clear(),
+            Rows::Keyed(values) => values.clear(),
+            Rows::Marker => {}
+        }
+    }
+
+    fn retain_rows(&mut self, keep: &[bool]) {
+        let old_present = self.present.clone();
+        self.present.retain_rows(keep);
+        self.len = self.present.count();
+        let mut kept = keep.iter();
+        self.changed.retain(|_| *kept.next().unwrap());
+        match &mut self.rows {
+            Rows::Dense(values) => {
+                let mut keep = keep.iter();
+                values.retain(|_| *keep.next().unwrap());
+            }
+            Rows::Keyed(values) => {
+                // kept rows only move down, so moving them in order never overwrites a value
+                let mut kept = 0;
+                for (index, keep) in keep.iter().enumerate() {
+                    let value = old_present
+                        .contains(index)
+                        .then(|| values.remove(index))
+                        .flatten();
+                    if *keep {
+                        if let Some(value) = value {
+                            values.insert(kept, value);
+                        }
+                        kept += 1;
+                    }
+                }
+            }
+            Rows::Marker => {}
+        }
+    }
+
+    fn append(&mut self, other: &mut ComponentsImpl<T>) {
+        let rows = self.rows();
+        let mut other_changed = core::mem::take(&mut other.changed);
+        match (&mut self.rows, &mut other.rows) {
+            (Rows::Dense(values), Rows::Dense(other_values)) => values.append(other_values),
+            (Rows::Marker, Rows::Marker) => {}
+            _ => {
+                for index in 0..other.rows() {
+                    let value = other.take(index);
+                    self.push(value);
+                }
+                other.clear();
+                self.changed.truncate(rows);
+                self.changed.append(&mut other_changed);
+                return;
+            }
+        }
+        self.len += core::mem::take(&mut other.len);
+        self.present.append(&mut other.present);
+        self.changed.append(&mut other_changed);
+    }
+}
+
+/// The value of a zero-sized type, which needs no memory.
+#[inline]
+fn marker<'a, T>() -> &'a mut T {
+    debug_assert_eq!(size_of::<T>(), 0);
+    // SAFETY: a dangling but aligned pointer is valid for zero-sized accesses.
+    unsafe { NonNull::dangling().as_mut() }
+}
+
+/// The rows of a dense or marker column, read from several threads by `Query::par_for_each`.
+#[cfg(feature = "std")]
+pub struct SharedRows<'a, T>(&'a ComponentsImpl<T>);
+
+// SAFETY: without a `Storage`, reading a row only reads the `Vec` of values and the bitset.
+#[cfg(feature = "std")]
+unsafe impl<T: Sync> Sync for SharedRows<'_, T> {}
+
+#[cfg(feature = "std")]
+impl<'a, T> SharedRows<'a, T> {
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.0.rows()
+    }
+
+    #[inline]
+    pub(crate) fn present(&self) -> &'a Bitset {
+        &self.0.present
+    }
+
+    #[inline]
+    pub(crate) fn get(&self, index: usize) -> Option<&'a T> {
+        self.0.get(index)
+    }
+}
+
+/// The rows of a dense or marker column, borrowed mutably from several threads by
+/// `Query::par_for_each`, each row by a single thread.
+#[cfg(feature = "std")]
+pub struct MutRows<'a, T> {
+    // null for marker columns
+    values: *mut Option<T>,
+    changed: *mut u32,
+    present: &'a Bitset,
+    tick: u32,
+    _values: core::marker::PhantomData<&'a mut T>,
+}
+
+// SAFETY: the threads borrow distinct rows, see `get_mut`.
+#[cfg(feature = "std")]
+unsafe impl<T: Send> Sync for MutRows<'_, T> {}
+
+#[cfg(feature = "std")]
+impl<'a, T> MutRows<'a, T> {
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.present.len()
+    }
+
+    #[inline]
+    pub(crate) fn present(&self) -> &'a Bitset {
+        self.present
+    }
+
+    /// Like `ComponentsImpl::get_mut`.
+    ///
+    /// # Safety
+    ///
+    /// `index` must be below `len` and not borrowed through this view already.
+    #[inline]
+    pub(crate) unsafe fn get_mut(&self, index: usize) -> Option<&'a mut T> {
+        if !self.present.contains(index) {
+            return None;
+        }
+        // SAFETY: both pointers come from the `&'a mut` given to `mut_rows` and are valid for
+        // `len` rows, of which the caller owns `index`.
+        unsafe {
+            *self.changed.add(index) = self.tick;
+            match self.values.is_null() {
+                true => Some(marker()),
+                false => (*self.values.add(index)).as_mut(),
+            }
+        }
+    }
+}
+
+/// Yields whether each row holds a value, skipping rows in constant time.
+struct MarkerRows<'a> {
+    present: &'a Bitset,
+    row: usize,
+}
+
+impl<'a> MarkerRows<'a> {
+    fn new(present: &'a Bitset) -> Self {
+        Self { present, row: 0 }
+    }
+}
+
+impl Iterator for MarkerRows<'_> {
+    type Item = Option<()>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Option<()>> {
+        if self.row >= self.present.len() {
+            return None;
+        }
+        self.row += 1;
+        Some(self.present.contains(self.row - 1).then_some(()))
+    }
+
+    #[inline]
+    fn nth(&mut self, n: usize) -> Option<Option<()>> {
+        self.row = self.row.saturating_add(n);
+        self.next()
+    }
+}
+
+/// Yields the value of every row, given the `(row, value)` pairs ordered by row.
+struct KeyedRows<I: Iterator> {
+    row: usize,
+    rows: usize,
+    entries: Peekable<I>,
+}
+
+impl<V, I: Iterator<Item = (usize, V)>> KeyedRows<I> {
+    fn new(rows: usize, entries: I) -> Self {
+        Self {
+            row: 0,
+            rows,
+            entries: entries.peekable(),
+        }
+    }
+}
+
+impl<V, I: Iterator<Item = (usize, V)>> Iterator for KeyedRows<I> {
+    type Item = Option<V>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Option<V>> {
+        if self.row >= self.rows {
+            return None;
+        }
+        let row = self.row;
+        self.row += 1;
+        Some(
+            self.entries
+                .next_if(|(index, _)| *index == row)
+                .map(|(_, value)| value),
+        )
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Option<V>> {
+        self.row = self.row.saturating_add(n);
+        while self
+            .entries
+            .next_if(|(index, _)| *index < self.row)
+            .is_some()
+        {}
+        self.next()
+    }
+}
+
+/// Skips rows in constant time for dense and marker columns, see `Query::presence`.
+enum RowsIter<'a, D, F, S, M> {
+    Dense(D, F),
+    Keyed(S),
+    Marker(MarkerRows<'a>, M),
+}
+
+impl<D, F, S, M, I> Iterator for RowsIter<'_, D, F, S, M>
+where
+    D: Iterator,
+    F: FnMut(D::Item) -> Option<I>,
+    S: Iterator<Item = Option<I>>,
+    M: FnMut(()) -> I,
+{
+    type Item = Option<I>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Option<I>> {
+        match self {
+            RowsIter::Dense(iter, f) => iter.next().map(f),
+            RowsIter::Keyed(iter) => iter.next(),
+            RowsIter::Marker(iter, f) => iter.next().map(|row| row.map(f)),
+        }
+    }
+
+    #[inline]
+    fn nth(&mut self, n: usize) -> Option<Option<I>> {
+        match self {
+            RowsIter::Dense(iter, f) => iter.nth(n).map(f),
+            RowsIter::Keyed(iter) => iter.nth(n),
+            RowsIter::Marker(iter, f) => iter.nth(n).map(|row| row.map(f)),
+        }
+    }
+}
+
+/// A tuple of component types that can be registered at once, see `ChunkBuilder::with_components`.
+pub trait ComponentSet {
+    fn register(builder: ChunkBuilder) -> ChunkBuilder;
+}
+
+macro_rules! impl_component_set_for_tuple {
+    ( $($T:ident),+ ) => {
+        impl<$($T: 'static),+> ComponentSet for ($($T,)+) {
+            fn register(builder: ChunkBuilder) -> ChunkBuilder {
+                builder $(.with_component::<$T>())+
+            }
+        }
+    };
+}
+
+impl_component_set_for_tuple!(C1);
+impl_component_set_for_tuple!(C1, C2);
+impl_component_set_for_tuple!(C1, C2, C3);
+impl_component_set_for_tuple!(C1, C2, C3, C4);
+impl_component_set_for_tuple!(C1, C2, C3, C4, C5);
+impl_component_set_for_tuple!(C1, C2, C3, C4, C5, C6);
+impl_component_set_for_tuple!(C1, C2, C3, C4, C5, C6, C7);
+impl_component_set_for_tuple!(C1, C2, C3, C4, C5, C6, C7, C8);
+impl_component_set_for_tuple!(C1, C2, C3, C4, C5, C6, C7, C8, C9);
+impl_component_set_for_tuple!(C1, C2, C3, C4, C5, C6, C7, C8, C9, C10);
+impl_component_set_for_tuple!(C1, C2, C3, C4, C5, C6, C7, C8, C9, C10, C11);
+impl_component_set_for_tuple!(C1, C2, C3, C4, C5, C6, C7, C8, C9, C10, C11, C12);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::Query;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Dead;
+
+    #[test]
+    fn mutable_borrows_stamp_the_current_tick() {
+        let mut chunk = ChunkBuilder::default().with_component::<u32>().build();
+        let [first, second, third] = [(); 3].map(|_| chunk.spawn().unwrap());
+        let id = TypeId::of::<u32>();
+        for entity in [first, second] {
+            chunk.add_component(entity, 0u32).unwrap();
+        }
+        assert_eq!(chunk.advance_tick(), 1);
+        {
+            let values = chunk.components_ref::<u32>().unwrap();
+            assert_eq!((&values).query().count(), 2);
+            assert!(chunk.component_dyn(first, id).unwrap().is_some());
+        }
+        *chunk
+            .components_mut::<u32>()
+            .unwrap()
+            .get_mut(second)
+            .unwrap() = 1;
+        let values = chunk.components_ref::<u32>().unwrap();
+        let ticks = [first, second, third].map(|entity| values.last_changed(entity));
+        assert_eq!(ticks, [Some(0), Some(1), None]);
+        drop(values);
+
+        chunk.advance_tick();
+        for value in (&mut chunk.components_mut::<u32>().unwrap()).query() {
+            *value += 1;
+        }
+        chunk.advance_tick();
+        chunk.component_dyn_mut(second, id).unwrap();
+        let values = chunk.components_ref::<u32>().unwrap();
+        let ticks = [first, second].map(|entity| values.last_changed(entity));
+        assert_eq!(ticks, [Some(2), Some(3)]);
+    }
+
+    #[test]
+    fn markers_are_stored_as_presence_only() {
+        let mut chunk = ChunkBuilder::default().with_component::<Dead>().build();
+        let entities = [(); 4].map(|_| chunk.spawn().unwrap());
+        chunk.add_component(entities[1], Dead).unwrap();
+        chunk.add_component(entities[3], Dead).unwrap();
+        chunk.add_component(entities[3], Dead).unwrap();
+        {
+            let column = chunk.components.values_mut::<Dead>().unwrap();
+            assert!(matches!(column.rows, Rows::Marker));
+            assert_eq!(column.len, 2);
+        }
+        let dead = chunk.components_ref::<Dead>().unwrap();
+        assert_eq!(dead.get(entities[1]), Some(&Dead));
+        assert!(!dead.contains(entities[0]));
+        drop(dead);
+
+        chunk.remove_component::<Dead>(entities[1]).unwrap();
+        chunk.remove_component::<Dead>(entities[2]).unwrap();
+        let mut dead = chunk.components_mut::<Dead>().unwrap();
+        assert!(!dead.contains(entities[1]));
+        assert_eq!(dead.len(), 1);
+        dead.insert(entities[0], Dead).unwrap();
+        dead.remove(entities[3]).unwrap();
+        assert_eq!(dead.get_mut(entities[0]), Some(&mut Dead));
+        assert_eq!(dead.len(), 1);
+    }
+
+    #[test]
+    fn shared_reads_block_writes() {
+        let mut chunk = ChunkBuilder::default().with_component::<u32>().build();
+        let entity = chunk.spawn().unwrap();
+        chunk.add_component(entity, 1u32).unwrap();
+
+        let shared = chunk.shared_components_ref::<u32>().unwrap();
+        let read = chunk.components_ref::<u32>().unwrap();
+        assert!(matches!(
+            chunk.components_mut::<u32>(),
+            Err(Error::ComponentAlreadyBorrowedMutably(_))
+        ));
+        drop(shared);
+        assert!(chunk.components_mut::<u32>().is_err());
+        drop(read);
+
+        let write = chunk.components_mut::<u32>().unwrap();
+        assert!(chunk.components_ref::<u32>().is_err());
+        assert!(chunk.shared_components_ref::<u32>().is_err());
+        drop(write);
+        assert_eq!(chunk.components_ref::<u32>().unwrap().get(entity), Some(&1));
+    }
+
+    struct Stored;
+
+    impl Component for Stored {
+        type Storage = BTreeMap<usize, Stored>;
+    }
+
+    #[test]
+    fn custom_storages_are_not_shared() {
+        let chunk = ChunkBuilder::default()
+            .with_custom_storage::<Stored>()
+            .build();
+        assert!(matches!(
+            chunk.shared_components_ref::<Stored>(),
+            Err(Error::ComponentStorageNotShareable(_))
+        ));
+    }
+
+    #[cfg(all(
+        feature = "std",
+        any(not(feature = "single-threaded"), feature = "sync")
+    ))]
+    #[test]
+    fn shared_reads_cross_threads() {
+        let mut chunk = ChunkBuilder::default().with_component::<u64>().build();
+        for value in 0..100u64 {
+            let entity = chunk.spawn().unwrap();
+            chunk.add_component(entity, value).unwrap();
+        }
+        let shared = chunk.shared_components_ref::<u64>().unwrap();
+        let sum = std::thread::scope(|scope| {
+            let shared = &shared;
+            let halves = [0..50, 50..100].map(|values| {
+                scope.spawn(move || {
+                    shared
+                        .iter_with_entities()
+                        .map(|(_, value)| *value)
+                        .filter(|value| values.contains(value))
+                        .sum::<u64>()
+                })
+            });
+            halves.map(|half| half.join().unwrap()).iter().sum::<u64>()
+        });
+        assert_eq!(sum, 4950);
+        assert!(chunk.components_mut::<u64>().is_err());
+        drop(shared);
+        assert!(chunk.components_mut::<u64>().is_ok());
     }
 }
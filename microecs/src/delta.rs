@@ -0,0 +1,135 @@
+use crate::components::ColumnDeltas;
+use crate::entities::Entity;
+use crate::{Chunk, Error};
+use alloc::vec::Vec;
+use hashbrown::HashSet;
+
+/// The entities of a chunk and the components changed since a tick, see `Chunk::delta_since`.
+pub struct ChunkDelta {
+    entities: Vec<Entity>,
+    next_id: u64,
+    columns: ColumnDeltas,
+    tick: u32,
+}
+
+impl ChunkDelta {
+    /// The tick of the chunk when the delta was taken, to pass to the next `delta_since`.
+    #[inline]
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+}
+
+impl Chunk {
+    /// Copies the components added, changed or removed at or after `tick`, along with the ids of
+    /// every entity, so that `apply_delta` can bring a copy of the chunk up to date, e.g. on the
+    /// other end of a network connection. Every component type holding changed values must have
+    /// been registered with `ChunkBuilder::with_cloneable_component`. Items are not part of the
+    /// delta.
+    pub fn delta_since(&self, tick: u32) -> Result<ChunkDelta, Error> {
+        Ok(ChunkDelta {
+            entities: self.entities.iter().collect(),
+            next_id: self.entities.next_id(),
+            columns: self.components.delta_since(&self.entities, tick)?,
+            tick: self.tick(),
+        })
+    }
+
+    /// Spawns and destroys entities to match the chunk `delta` was taken from, keeping their ids,
+    /// then applies its components. The chunk is expected to be built like that one and to spawn
+    /// no entities of its own. Component hooks are not called.
+    pub fn apply_delta(&mut self, delta: ChunkDelta) -> Result<(), Error> {
+        let live = delta.entities.iter().copied().collect::<HashSet<_>>();
+        let destroyed = self
+            .entities
+            .iter()
+            .filter(|entity| !live.contains(entity))
+            .collect::<Vec<_>>();
+        for entity in destroyed {
+            self.entities.destroy(&mut self.components, entity)?;
+        }
+        self.entities.reserve_until(delta.next_id);
+        for &entity in delta.entities.iter() {
+            if self.entities.index(entity).is_none() {
+                self.entities.spawn_reserved(&mut self.components, entity)?;
+            }
+        }
+        self.components.apply_delta(&self.entities, delta.columns)?;
+        self.names.clear();
+        self.index_names(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChunkBuilder;
+
+    #[derive(Clone)]
+    struct Health(u32);
+
+    fn chunk() -> Chunk {
+        ChunkBuilder::default()
+            .with_cloneable_component::<Health>()
+            .build()
+    }
+
+    fn health(chunk: &Chunk, entity: Entity) -> Option<u32> {
+        let health = chunk.components_ref::<Health>().unwrap();
+        health.get(entity).map(|h| h.0)
+    }
+
+    #[test]
+    fn only_sends_the_changed_components() {
+        let mut source = chunk();
+        let first = source.spawn().unwrap();
+        let second = source.spawn().unwrap();
+        source.add_component(first, Health(1)).unwrap();
+        source.add_component(second, Health(2)).unwrap();
+        let mut copy = chunk();
+        let delta = source.delta_since(0).unwrap();
+        let tick = source.advance_tick();
+        copy.apply_delta(delta).unwrap();
+        assert_eq!(health(&copy, first), Some(1));
+        assert_eq!(health(&copy, second), Some(2));
+
+        source
+            .components_mut::<Health>()
+            .unwrap()
+            .get_mut(second)
+            .unwrap()
+            .0 = 3;
+        // only `second` is sent, so the change made to the copy is kept
+        copy.components_mut::<Health>()
+            .unwrap()
+            .get_mut(first)
+            .unwrap()
+            .0 = 4;
+        copy.apply_delta(source.delta_since(tick).unwrap()).unwrap();
+        assert_eq!(health(&copy, first), Some(4));
+        assert_eq!(health(&copy, second), Some(3));
+    }
+
+    #[test]
+    fn sends_removals_spawns_and_names() {
+        let mut source = chunk();
+        let first = source.spawn().unwrap();
+        let second = source.spawn().unwrap();
+        source.add_component(first, Health(1)).unwrap();
+        source.add_component(second, Health(2)).unwrap();
+        let mut copy = chunk();
+        copy.apply_delta(source.delta_since(0).unwrap()).unwrap();
+
+        let tick = source.advance_tick();
+        source.remove_component::<Health>(first).unwrap();
+        source.destroy(second).unwrap();
+        let third = source.spawn().unwrap();
+        source.set_name(third, "third").unwrap();
+        copy.apply_delta(source.delta_since(tick).unwrap()).unwrap();
+        assert_eq!(health(&copy, first), None);
+        assert!(copy.entity(second).is_err());
+        assert_eq!(copy.entity_by_name("third"), Some(third));
+        // ids handed out later do not collide with the copied ones
+        assert_eq!(copy.spawn().unwrap(), source.spawn().unwrap());
+    }
+}
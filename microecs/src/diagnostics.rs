@@ -0,0 +1,77 @@
+use crate::{Chunk, Error};
+use alloc::vec::Vec;
+
+/// Statistics about a chunk, see `Chunk::diagnostics`.
+#[derive(Clone, Debug)]
+pub struct Diagnostics {
+    entities: usize,
+    columns: Vec<ColumnDiagnostics>,
+}
+
+/// Statistics about the values of one component type.
+#[derive(Clone, Debug)]
+pub struct ColumnDiagnostics {
+    pub(crate) type_name: &'static str,
+    pub(crate) rows: usize,
+    pub(crate) values: usize,
+    pub(crate) heap_bytes: usize,
+}
+
+impl Diagnostics {
+    #[inline]
+    pub fn entities(&self) -> usize {
+        self.entities
+    }
+
+    #[inline]
+    pub fn columns(&self) -> &[ColumnDiagnostics] {
+        &self.columns
+    }
+
+    /// The approximate heap memory used by all the columns.
+    pub fn heap_bytes(&self) -> usize {
+        self.columns.iter().map(ColumnDiagnostics::heap_bytes).sum()
+    }
+}
+
+impl ColumnDiagnostics {
+    #[inline]
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// The number of rows, one per entity of the chunk.
+    #[inline]
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// The number of rows holding a value.
+    #[inline]
+    pub fn values(&self) -> usize {
+        self.values
+    }
+
+    /// The share of rows holding a value, between 0 and 1.
+    pub fn density(&self) -> f32 {
+        if self.rows == 0 {
+            return 0.0;
+        }
+        self.values as f32 / self.rows as f32
+    }
+
+    /// The approximate heap memory used by the values, excluding what they own themselves.
+    #[inline]
+    pub fn heap_bytes(&self) -> usize {
+        self.heap_bytes
+    }
+}
+
+impl Chunk {
+    pub fn diagnostics(&self) -> Result<Diagnostics, Error> {
+        Ok(Diagnostics {
+            entities: self.entities.len(),
+            columns: self.components.diagnostics()?,
+        })
+    }
+}
@@ -0,0 +1,87 @@
+use crate::components::ComponentDynRef;
+use crate::entities::Entity;
+use crate::{Chunk, Error};
+use alloc::boxed::Box;
+use core::any::{Any, TypeId};
+
+impl Chunk {
+    /// The type id and type name of every registered component type, for tools that only know
+    /// components by name.
+    pub fn component_types(&self) -> impl Iterator<Item = (TypeId, &'static str)> + use<'_> {
+        self.components.types()
+    }
+
+    /// Returns the type id of the registered component type called `type_name`, as given by
+    /// `core::any::type_name`.
+    pub fn component_type_id(&self, type_name: &str) -> Option<TypeId> {
+        self.component_types()
+            .find(|(_, name)| *name == type_name)
+            .map(|(type_id, _)| type_id)
+    }
+
+    /// Returns `None` if the entity has no component of type `type_id`, or if it is not
+    /// registered. Unlike `component_dyn_mut`, does not mark the component as changed. Fails if
+    /// the column is borrowed mutably.
+    pub fn component_dyn(
+        &self,
+        entity: Entity,
+        type_id: TypeId,
+    ) -> Result<Option<ComponentDynRef<'_>>, Error> {
+        let (Some(index), Ok(column)) = (
+            self.entities.index(entity),
+            self.components.column_dyn(type_id),
+        ) else {
+            return Ok(None);
+        };
+        column.get_dyn(index)
+    }
+
+    /// Returns `None` if the entity has no component of type `type_id`, or if it is not
+    /// registered.
+    pub fn component_dyn_mut(&mut self, entity: Entity, type_id: TypeId) -> Option<&mut dyn Any> {
+        let index = self.entities.index(entity)?;
+        self.components
+            .column_dyn_mut(type_id)
+            .ok()?
+            .get_dyn_mut(index)
+    }
+
+    /// Like `add_component`, with `value` being of the registered component type `type_id`.
+    pub fn insert_dynamic(
+        &mut self,
+        entity: Entity,
+        type_id: TypeId,
+        value: Box<dyn Any>,
+    ) -> Result<(), Error> {
+        let index = self
+            .entities
+            .index(entity)
+            .ok_or(Error::InvalidEntity(entity))?;
+        let column = self.components.column_dyn_mut(type_id)?;
+        let previous = column.set_dyn(index, Some(value))?;
+        match column.on_add() {
+            Some(hook) if previous.is_none() => self.run_hook(hook, entity),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Like `remove_component`, returning the removed value.
+    pub fn remove_dynamic(
+        &mut self,
+        entity: Entity,
+        type_id: TypeId,
+    ) -> Result<Option<Box<dyn Any>>, Error> {
+        let index = self
+            .entities
+            .index(entity)
+            .ok_or(Error::InvalidEntity(entity))?;
+        let column = self.components.column_dyn_mut(type_id)?;
+        let previous = column.set_dyn(index, None)?;
+        match column.on_remove() {
+            Some(hook) if previous.is_some() => self.run_hook(hook, entity),
+            _ => {}
+        }
+        Ok(previous)
+    }
+}
@@ -1,4 +1,5 @@
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
 use hashbrown::HashMap;
 
 use crate::{components::ChunkComponents, Error};
@@ -9,10 +10,74 @@ pub struct Entity(pub(crate) u64);
 
 pub struct Entities<'a>(pub(crate) &'a ChunkEntities);
 
+/// Maps the entities of one chunk to the entities they became in another, e.g. after a merge.
+#[derive(Clone, Debug, Default)]
+pub struct EntityMap(HashMap<Entity, Entity>);
+
+/// Implemented by components holding entities, so that `Chunk::merge` and `Chunk::map_entities`
+/// can rewrite them. See `ChunkBuilder::with_mapped_component`.
+pub trait MapEntities {
+    fn map_entities(&mut self, map: &EntityMap);
+}
+
+impl EntityMap {
+    #[inline]
+    pub fn get(&self, entity: Entity) -> Option<Entity> {
+        self.0.get(&entity).copied()
+    }
+
+    #[inline]
+    pub fn insert(&mut self, from: Entity, to: Entity) {
+        self.0.insert(from, to);
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The pairs of the map, ordered by the entity they map from.
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, Entity)> + use<'_> {
+        let mut pairs = self
+            .0
+            .iter()
+            .map(|(from, to)| (*from, *to))
+            .collect::<Vec<_>>();
+        pairs.sort_unstable();
+        pairs.into_iter()
+    }
+
+    /// Returns what `entity` became, or `entity` itself if it is not part of the map.
+    #[inline]
+    pub fn map(&self, entity: Entity) -> Entity {
+        self.get(entity).unwrap_or(entity)
+    }
+}
+
+/// Hands out the `ChunkEntities::owner` of every chunk.
+static NEXT_OWNER: AtomicU64 = AtomicU64::new(0);
+
 pub(crate) struct ChunkEntities {
     indexes: HashMap<Entity, usize>,
     id: Vec<Entity>,
-    entity_id_generator: Entity,
+    entity_id_generator: AtomicU64,
+    owner: u64,
+}
+
+impl Clone for ChunkEntities {
+    fn clone(&self) -> Self {
+        Self {
+            indexes: self.indexes.clone(),
+            id: self.id.clone(),
+            entity_id_generator: AtomicU64::new(self.entity_id_generator.load(Ordering::Relaxed)),
+            owner: self.owner,
+        }
+    }
 }
 
 impl ChunkEntities {
@@ -20,20 +85,82 @@ impl ChunkEntities {
         Self {
             indexes: HashMap::new(),
             id: Vec::new(),
-            entity_id_generator: Entity(0),
+            entity_id_generator: AtomicU64::new(0),
+            owner: NEXT_OWNER.fetch_add(1, Ordering::Relaxed),
         }
     }
 
+    /// Identifies the chunk the ids handed out by `reserve` belong to. Copies made for snapshots
+    /// keep it.
+    #[inline]
+    pub fn owner(&self) -> u64 {
+        self.owner
+    }
+
     pub fn spawn(&mut self, components: &mut ChunkComponents) -> Result<Entity, Error> {
-        let id = self.entity_id_generator;
-        let index = self.id.len();
-        self.id.push(id);
-        components.push_none()?;
-        self.indexes.insert(id, index);
-        self.entity_id_generator = Entity(self.entity_id_generator.0 + 1);
+        let id = self.reserve();
+        self.spawn_reserved(components, id)?;
         Ok(id)
     }
 
+    /// Hands out a fresh id without creating its row, which only happens once the id is passed
+    /// to `spawn_reserved`.
+    pub fn reserve(&self) -> Entity {
+        Entity(self.entity_id_generator.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn spawn_reserved(
+        &mut self,
+        components: &mut ChunkComponents,
+        id: Entity,
+    ) -> Result<(), Error> {
+        if self.indexes.contains_key(&id) || id.0 >= *self.entity_id_generator.get_mut() {
+            return Err(Error::InvalidEntity(id));
+        }
+        components.push_none()?;
+        self.push_row(id);
+        Ok(())
+    }
+
+    /// The id `reserve` hands out next.
+    #[inline]
+    pub fn next_id(&self) -> u64 {
+        self.entity_id_generator.load(Ordering::Relaxed)
+    }
+
+    /// Skips the ids below `next_id`, so that they can be spawned through `spawn_reserved`.
+    pub fn reserve_until(&mut self, next_id: u64) {
+        let generator = self.entity_id_generator.get_mut();
+        *generator = (*generator).max(next_id);
+    }
+
+    pub fn reserve_rows(&mut self, additional: usize) {
+        self.indexes.reserve(additional);
+        self.id.reserve(additional);
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.indexes.shrink_to_fit();
+        self.id.shrink_to_fit();
+    }
+
+    /// Appends a row for a fresh id without touching the component columns.
+    pub fn push_row_unchecked(&mut self) -> Entity {
+        let id = self.reserve();
+        self.push_row(id);
+        id
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.id.len()
+    }
+
+    fn push_row(&mut self, id: Entity) {
+        self.indexes.insert(id, self.id.len());
+        self.id.push(id);
+    }
+
     pub fn destroy(&mut self, components: &mut ChunkComponents, id: Entity) -> Result<(), Error> {
         let Some(index) = self.indexes.remove(&id) else {
             return Err(Error::InvalidEntity(id));
@@ -47,6 +174,21 @@ impl ChunkEntities {
         Ok(())
     }
 
+    pub fn clear(&mut self) {
+        self.indexes.clear();
+        self.id.clear();
+    }
+
+    /// Keeps the rows whose entry in `keep` is true, preserving their order.
+    pub fn retain_rows(&mut self, keep: &[bool]) {
+        let mut keep = keep.iter();
+        self.id.retain(|_| *keep.next().unwrap());
+        self.indexes.clear();
+        for (index, id) in self.id.iter().enumerate() {
+            self.indexes.insert(*id, index);
+        }
+    }
+
     pub fn index(&self, entity: Entity) -> Option<usize> {
         self.indexes.get(&entity).copied()
     }
@@ -54,4 +196,9 @@ impl ChunkEntities {
     pub fn iter(&self) -> impl Iterator<Item = Entity> + use<'_> {
         self.id.iter().copied()
     }
+
+    #[inline]
+    pub fn at(&self, index: usize) -> Option<Entity> {
+        self.id.get(index).copied()
+    }
 }
@@ -0,0 +1,99 @@
+use crate::components::ComponentRef;
+use crate::entities::Entity;
+use crate::{Chunk, Error};
+
+/// Read access to the components of a single entity. See `Chunk::entity`.
+pub struct EntityRef<'a> {
+    chunk: &'a Chunk,
+    entity: Entity,
+    index: usize,
+}
+
+impl<'a> EntityRef<'a> {
+    pub(crate) fn new(chunk: &'a Chunk, entity: Entity) -> Result<Self, Error> {
+        let index = chunk
+            .entities
+            .index(entity)
+            .ok_or(Error::InvalidEntity(entity))?;
+        Ok(Self {
+            chunk,
+            entity,
+            index,
+        })
+    }
+
+    #[inline]
+    pub fn id(&self) -> Entity {
+        self.entity
+    }
+
+    /// Returns `None` if the entity has no `T`, or if `T` is not registered or borrowed mutably.
+    #[inline]
+    pub fn get<T: 'static>(&self) -> Option<ComponentRef<'a, T>> {
+        self.chunk.components.component_ref::<T>(self.index)
+    }
+
+    #[inline]
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.get::<T>().is_some()
+    }
+}
+
+/// Read and write access to the components of a single entity. See `Chunk::entity_mut`.
+pub struct EntityMut<'a> {
+    chunk: &'a mut Chunk,
+    entity: Entity,
+    index: usize,
+}
+
+impl<'a> EntityMut<'a> {
+    pub(crate) fn new(chunk: &'a mut Chunk, entity: Entity) -> Result<Self, Error> {
+        let index = chunk
+            .entities
+            .index(entity)
+            .ok_or(Error::InvalidEntity(entity))?;
+        Ok(Self {
+            chunk,
+            entity,
+            index,
+        })
+    }
+
+    #[inline]
+    pub fn id(&self) -> Entity {
+        self.entity
+    }
+
+    /// Returns `None` if the entity has no `T`, or if `T` is not registered.
+    #[inline]
+    pub fn get<T: 'static>(&self) -> Option<ComponentRef<'_, T>> {
+        self.chunk.components.component_ref::<T>(self.index)
+    }
+
+    /// Returns `None` if the entity has no `T`, or if `T` is not registered.
+    #[inline]
+    pub fn get_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.chunk
+            .components
+            .values_mut::<T>()
+            .ok()?
+            .get_mut(self.index)
+    }
+
+    #[inline]
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.get::<T>().is_some()
+    }
+
+    #[inline]
+    pub fn insert<T: 'static>(&mut self, value: T) -> Result<&mut Self, Error> {
+        self.chunk.add_component(self.entity, value)?;
+        Ok(self)
+    }
+
+    #[inline]
+    pub fn remove<T: 'static>(&mut self) -> Result<&mut Self, Error> {
+        self.chunk.remove_component::<T>(self.entity)?;
+        Ok(self)
+    }
+}
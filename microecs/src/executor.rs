@@ -0,0 +1,204 @@
+//! The systems `Schedule::run_par` runs on worker threads. Their params are fetched on the thread
+//! running the schedule, which keeps the chunk to itself, and only the fetched borrows are sent.
+
+use crate::commands::EntitySpawner;
+use crate::components::{ComponentsMut, ComponentsRef};
+use crate::entities::Entities;
+use crate::resources::{ItemMut, ItemRef, ResourceMut, ResourceRef};
+use crate::systems::{System, SystemOutput, SystemParam, SystemsContext};
+use crate::{Chunk, CommandQueue, Commands, Error, Resources};
+use alloc::boxed::Box;
+use core::any::type_name;
+use core::marker::PhantomData;
+
+/// A `SystemParam` that can be used from a worker thread once fetched, see
+/// `Schedule::add_par_system`. Not implemented by `NonSend` and `NonSendMut`, nor by the params
+/// of `derive(SystemParam)`.
+///
+/// # Safety
+///
+/// A fetched param for which `check_send` returned `Ok` must be sound to send to another thread,
+/// and to use and drop there.
+pub unsafe trait ParSystemParam: SystemParam {
+    /// Fails if `param` borrows something that may not leave the thread, e.g. a column kept by a
+    /// custom `Storage`.
+    fn check_send(_param: &Self::Param<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// A system whose params are all `ParSystemParam`s.
+///
+/// # Safety
+///
+/// As for `ParSystemParam`, for all the params of the system at once.
+pub unsafe trait ParSystem<Params>: System<Params> {
+    fn check_send(params: &Self::Params<'_>) -> Result<(), Error>;
+}
+
+// SAFETY: the entity table only holds plain data and an atomic.
+unsafe impl ParSystemParam for Entities<'_> {}
+
+// SAFETY: as for `SharedComponentsRef`, the built-in storages only hold `T`s, which are `Sync`.
+unsafe impl<T: Sync + 'static> ParSystemParam for ComponentsRef<'_, T> {
+    fn check_send(param: &Self::Param<'_>) -> Result<(), Error> {
+        match param.values.is_custom() {
+            true => Err(Error::ComponentStorageNotShareable(type_name::<T>())),
+            false => Ok(()),
+        }
+    }
+}
+
+// SAFETY: as for `ComponentsRef`, with `T`s that are also `Send` since they are written to.
+unsafe impl<T: Send + Sync + 'static> ParSystemParam for ComponentsMut<'_, T> {
+    fn check_send(param: &Self::Param<'_>) -> Result<(), Error> {
+        match param.values.is_custom() {
+            true => Err(Error::ComponentStorageNotShareable(type_name::<T>())),
+            false => Ok(()),
+        }
+    }
+}
+
+// SAFETY: commands are recorded into segments pushed onto a lock-free stack.
+unsafe impl ParSystemParam for Commands<'_> {}
+
+// SAFETY: see `Commands`, entities are reserved through an atomic.
+unsafe impl ParSystemParam for EntitySpawner<'_> {}
+
+// SAFETY: the guards of atomic locks may be released from any thread.
+unsafe impl<T: Sync + 'static> ParSystemParam for ResourceRef<'_, T> {}
+
+// SAFETY: see `ResourceRef`.
+unsafe impl<T: Send + Sync + 'static> ParSystemParam for ResourceMut<'_, T> {}
+
+// SAFETY: see `ResourceRef`.
+unsafe impl<T: Sync + 'static> ParSystemParam for ItemRef<'_, T> {}
+
+// SAFETY: see `ResourceRef`.
+unsafe impl<T: Send + Sync + 'static> ParSystemParam for ItemMut<'_, T> {}
+
+// SAFETY: checks both params.
+unsafe impl<A, B> ParSystemParam for (A, B)
+where
+    A: ParSystemParam,
+    B: ParSystemParam,
+{
+    fn check_send((a, b): &Self::Param<'_>) -> Result<(), Error> {
+        A::check_send(a)?;
+        B::check_send(b)
+    }
+}
+
+macro_rules! impl_par_system_for_tuple {
+    ( $($T:ident),+ ) => {
+        // SAFETY: checks every param.
+        unsafe impl<Func, Out, $($T),+> ParSystem<($($T,)+)> for Func
+        where
+            Func: FnMut($($T,)+) -> Out,
+            Func: for<'a> FnMut($($T::Param<'a>,)+) -> Out,
+            $($T: ParSystemParam,)+
+        {
+            #[allow(non_snake_case)]
+            fn check_send(params: &Self::Params<'_>) -> Result<(), Error> {
+                let ($($T,)+) = params;
+                $($T::check_send($T)?;)+
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_par_system_for_tuple!(Param1);
+impl_par_system_for_tuple!(Param1, Param2);
+impl_par_system_for_tuple!(Param1, Param2, Param3);
+impl_par_system_for_tuple!(Param1, Param2, Param3, Param4);
+impl_par_system_for_tuple!(Param1, Param2, Param3, Param4, Param5);
+impl_par_system_for_tuple!(Param1, Param2, Param3, Param4, Param5, Param6);
+impl_par_system_for_tuple!(Param1, Param2, Param3, Param4, Param5, Param6, Param7);
+impl_par_system_for_tuple!(Param1, Param2, Param3, Param4, Param5, Param6, Param7, Param8);
+impl_par_system_for_tuple!(Param1, Param2, Param3, Param4, Param5, Param6, Param7, Param8, Param9);
+#[rustfmt::skip]
+impl_par_system_for_tuple!(Param1, Param2, Param3, Param4, Param5, Param6, Param7, Param8, Param9, Param10);
+#[rustfmt::skip]
+impl_par_system_for_tuple!(Param1, Param2, Param3, Param4, Param5, Param6, Param7, Param8, Param9, Param10, Param11);
+#[rustfmt::skip]
+impl_par_system_for_tuple!(Param1, Param2, Param3, Param4, Param5, Param6, Param7, Param8, Param9, Param10, Param11, Param12);
+
+/// A system with its params fetched, to run on a worker thread.
+pub(crate) type Job<'a> = Box<dyn FnOnce() -> Result<(), Error> + Send + 'a>;
+
+/// A system added through `Schedule::add_par_system`.
+pub(crate) trait ParScheduled {
+    /// Fetches the params of the system, recording its commands into `command_queue`.
+    fn fetch<'a>(
+        &'a mut self,
+        chunk: &'a Chunk,
+        resources: &'a Resources,
+    ) -> Result<Job<'a>, Error>;
+
+    /// Runs the system on the calling thread, recording its commands into the context's queue.
+    fn run(&mut self, context: &mut SystemsContext) -> Result<(), Error>;
+
+    /// The queue the commands of the last `fetch`ed job were recorded into.
+    fn command_queue(&mut self) -> &mut CommandQueue;
+}
+
+pub(crate) struct ParScheduledSystem<F: System<P>, P> {
+    system: F,
+    state: F::State,
+    command_queue: CommandQueue,
+    _params: PhantomData<fn() -> P>,
+}
+
+impl<F: System<P>, P> ParScheduledSystem<F, P> {
+    pub(crate) fn new(system: F) -> Self {
+        Self {
+            system,
+            state: F::State::default(),
+            command_queue: CommandQueue::new(),
+            _params: PhantomData,
+        }
+    }
+}
+
+/// Params for which `ParSystem::check_send` returned `Ok`.
+struct Checked<T>(T);
+
+// SAFETY: see `ParSystem`.
+unsafe impl<T> Send for Checked<T> {}
+
+impl<T> Checked<T> {
+    // takes the whole wrapper, closures would otherwise only capture the unchecked field
+    #[inline]
+    fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<F, P> ParScheduled for ParScheduledSystem<F, P>
+where
+    F: ParSystem<P, Input = ()> + Send,
+    F::Output: SystemOutput,
+{
+    fn fetch<'a>(
+        &'a mut self,
+        chunk: &'a Chunk,
+        resources: &'a Resources,
+    ) -> Result<Job<'a>, Error> {
+        let params = F::get_params(&mut self.state, chunk, resources, &self.command_queue)?;
+        F::check_send(&params)?;
+        let params = Checked(params);
+        let system = &mut self.system;
+        Ok(Box::new(move || {
+            system.run((), params.into_inner()).into_result()
+        }))
+    }
+
+    fn run(&mut self, context: &mut SystemsContext) -> Result<(), Error> {
+        context.run_system(&mut self.system, &mut self.state)
+    }
+
+    fn command_queue(&mut self) -> &mut CommandQueue {
+        &mut self.command_queue
+    }
+}
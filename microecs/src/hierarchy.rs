@@ -0,0 +1,223 @@
+use crate::entities::{Entity, EntityMap, MapEntities};
+use crate::{Chunk, Error};
+use alloc::{vec, vec::Vec};
+
+/// The parent of an entity. Managed through `Chunk::set_parent` and `Chunk::remove_parent`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Parent(pub(crate) Entity);
+
+impl Parent {
+    #[inline]
+    pub fn get(&self) -> Entity {
+        self.0
+    }
+}
+
+/// The children of an entity, in the order in which they were attached.
+#[derive(Clone, Debug, Default)]
+pub struct Children(pub(crate) Vec<Entity>);
+
+impl MapEntities for Parent {
+    fn map_entities(&mut self, map: &EntityMap) {
+        self.0 = map.map(self.0);
+    }
+}
+
+impl Children {
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + use<'_> {
+        self.0.iter().copied()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl MapEntities for Children {
+    fn map_entities(&mut self, map: &EntityMap) {
+        for child in self.0.iter_mut() {
+            *child = map.map(*child);
+        }
+    }
+}
+
+impl Chunk {
+    /// Attaches `child` to `parent`, detaching it from its previous parent first.
+    pub fn set_parent(&mut self, child: Entity, parent: Entity) -> Result<(), Error> {
+        self.entities
+            .index(parent)
+            .ok_or(Error::InvalidEntity(parent))?;
+        self.entities
+            .index(child)
+            .ok_or(Error::InvalidEntity(child))?;
+
+        let parents = self.components_ref::<Parent>()?;
+        let mut ancestor = Some(parent);
+        while let Some(entity) = ancestor {
+            if entity == child {
+                return Err(Error::HierarchyCycle(child));
+            }
+            ancestor = parents.get(entity).map(Parent::get);
+        }
+        drop(parents);
+
+        self.remove_parent(child)?;
+        self.components_mut::<Parent>()?
+            .insert(child, Parent(parent))?;
+        let mut children = self.components_mut::<Children>()?;
+        match children.get_mut(parent) {
+            Some(children) => children.0.push(child),
+            None => children.insert(parent, Children(vec![child]))?,
+        }
+        Ok(())
+    }
+
+    /// Detaches `child` from its parent, if it has one.
+    pub fn remove_parent(&mut self, child: Entity) -> Result<(), Error> {
+        let index = self
+            .entities
+            .index(child)
+            .ok_or(Error::InvalidEntity(child))?;
+        let Some(Parent(parent)) = self.components_mut::<Parent>()?.values.remove(index) else {
+            return Ok(());
+        };
+        let mut children = self.components_mut::<Children>()?;
+        if let Some(siblings) = children.get_mut(parent) {
+            siblings.0.retain(|&entity| entity != child);
+            if siblings.0.is_empty() {
+                children.remove(parent)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Destroys `entity` together with all of its descendants.
+    pub fn despawn_recursive(&mut self, entity: Entity) -> Result<(), Error> {
+        self.entities
+            .index(entity)
+            .ok_or(Error::InvalidEntity(entity))?;
+        let mut subtree = vec![entity];
+        {
+            let children = self.components_ref::<Children>()?;
+            let mut i = 0;
+            while i < subtree.len() {
+                if let Some(entity_children) = children.get(subtree[i]) {
+                    subtree.extend(entity_children.iter());
+                }
+                i += 1;
+            }
+        }
+        // destroying the root first leaves the rest of the subtree without a parent to update
+        for entity in subtree {
+            self.destroy(entity)?;
+        }
+        Ok(())
+    }
+
+    /// Unlinks `entity` from its parent and orphans its children, before it gets destroyed.
+    pub(crate) fn detach_hierarchy(&mut self, entity: Entity) -> Result<(), Error> {
+        self.remove_parent(entity)?;
+        let index = self
+            .entities
+            .index(entity)
+            .ok_or(Error::InvalidEntity(entity))?;
+        let Some(Children(children)) = self.components_mut::<Children>()?.values.remove(index)
+        else {
+            return Ok(());
+        };
+        let mut parents = self.components_mut::<Parent>()?;
+        for child in children {
+            parents.remove(child)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChunkBuilder;
+
+    fn children(chunk: &Chunk, parent: Entity) -> Vec<Entity> {
+        let children = chunk.components_ref::<Children>().unwrap();
+        children
+            .get(parent)
+            .map_or(Vec::new(), |c| c.iter().collect())
+    }
+
+    fn parent(chunk: &Chunk, child: Entity) -> Option<Entity> {
+        chunk
+            .components_ref::<Parent>()
+            .unwrap()
+            .get(child)
+            .map(Parent::get)
+    }
+
+    #[test]
+    fn reparenting_detaches_from_the_previous_parent() {
+        let mut chunk = ChunkBuilder::default().build();
+        let [first, second, child] = [(); 3].map(|_| chunk.spawn().unwrap());
+        chunk.set_parent(child, first).unwrap();
+        assert_eq!(
+            (parent(&chunk, child), children(&chunk, first)),
+            (Some(first), vec![child])
+        );
+        chunk.set_parent(child, second).unwrap();
+        assert_eq!(parent(&chunk, child), Some(second));
+        assert!(chunk
+            .components_ref::<Children>()
+            .unwrap()
+            .get(first)
+            .is_none());
+        assert_eq!(children(&chunk, second), [child]);
+        chunk.remove_parent(child).unwrap();
+        assert_eq!(
+            (parent(&chunk, child), children(&chunk, second)),
+            (None, vec![])
+        );
+    }
+
+    #[test]
+    fn fails_to_attach_an_entity_under_itself() {
+        let mut chunk = ChunkBuilder::default().build();
+        let [root, child, grandchild] = [(); 3].map(|_| chunk.spawn().unwrap());
+        chunk.set_parent(child, root).unwrap();
+        chunk.set_parent(grandchild, child).unwrap();
+        let cycle = chunk.set_parent(root, grandchild);
+        assert!(matches!(cycle, Err(Error::HierarchyCycle(e)) if e == root));
+        assert!(matches!(
+            chunk.set_parent(root, root),
+            Err(Error::HierarchyCycle(_))
+        ));
+        assert_eq!(parent(&chunk, root), None);
+    }
+
+    #[test]
+    fn despawns_whole_subtrees() {
+        let mut chunk = ChunkBuilder::default().build();
+        let [root, child, grandchild, sibling] = [(); 4].map(|_| chunk.spawn().unwrap());
+        chunk.set_parent(child, root).unwrap();
+        chunk.set_parent(grandchild, child).unwrap();
+        chunk.set_parent(sibling, root).unwrap();
+        chunk.despawn_recursive(child).unwrap();
+        assert!(chunk.entity(child).is_err() && chunk.entity(grandchild).is_err());
+        assert_eq!(children(&chunk, root), [sibling]);
+    }
+
+    #[test]
+    fn destroying_a_parent_orphans_its_children() {
+        let mut chunk = ChunkBuilder::default().build();
+        let [root, child] = [(); 2].map(|_| chunk.spawn().unwrap());
+        chunk.set_parent(child, root).unwrap();
+        chunk.destroy(root).unwrap();
+        assert!(chunk.entity(child).is_ok());
+        assert_eq!(parent(&chunk, child), None);
+    }
+}
@@ -0,0 +1,18 @@
+use crate::Chunk;
+use core::fmt;
+
+impl Chunk {
+    /// Writes every entity followed by the type names of its components, one per line, and the
+    /// values of those registered with `ChunkBuilder::with_debug_component`. Component types
+    /// borrowed mutably cannot be read, they are listed once first instead of under each entity.
+    pub fn debug_dump(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        for type_name in self.components.borrowed_mutably() {
+            writeln!(out, "{type_name} (borrowed mutably, not listed)")?;
+        }
+        for (index, entity) in self.entities.iter().enumerate() {
+            writeln!(out, "{entity:?}")?;
+            self.components.debug_row(index, out)?;
+        }
+        Ok(())
+    }
+}
@@ -1,28 +1,107 @@
 #![no_std]
 
 extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
 
+mod access;
+mod bitset;
+mod bundle;
+mod commands;
 mod components;
+mod delta;
+mod diagnostics;
+mod dynamic;
 mod entities;
+mod entity_ref;
+#[cfg(all(
+    feature = "std",
+    any(not(feature = "single-threaded"), feature = "sync")
+))]
+mod executor;
+mod hierarchy;
+mod inspect;
+mod lock;
+mod names;
+#[cfg(feature = "std")]
+mod parallel;
+mod plugin;
+mod prefab;
+mod profiling;
 mod query;
+mod registry;
 mod resources;
+mod schedule;
+mod snapshot;
+mod state;
+mod storage;
 mod systems;
+mod tags;
+mod world;
 
-use alloc::{boxed::Box, collections::vec_deque::VecDeque};
-use components::{ChunkComponents, ComponentsBuilder, ComponentsMut, ComponentsRef};
-use entities::{ChunkEntities, Entity};
+use alloc::{boxed::Box, string::String, vec::Vec};
+use bundle::Bundle;
+use commands::DelayedCommands;
+pub use commands::{CommandQueue, Commands, EntitySpawner, FlushPolicy};
+use components::{
+    ChunkComponents, ComponentHook, ComponentId, ComponentSet, ComponentsBuilder, ComponentsMut,
+    ComponentsRef, SharedComponentsRef, StorageKind,
+};
+use core::any::{Any, TypeId};
+use entities::{ChunkEntities, Entity, EntityMap, MapEntities};
+use entity_ref::{EntityMut, EntityRef};
+use hashbrown::HashMap;
+use hierarchy::{Children, Parent};
+use names::Name;
 use prelude::ResourcesBuilder;
-use resources::Resources;
-use spin::{RwLock, RwLockWriteGuard};
+use resources::{Resource, Resources};
+use storage::Component;
 use systems::SystemsContext;
+use tags::{TagRegistry, Tags};
+use world::ChunkId;
 
 pub mod prelude {
-    pub use crate::components::{ComponentsMut, ComponentsRef};
-    pub use crate::entities::{Entity, Entities};
+    pub use crate::access::{Access, AccessTarget};
+    pub use crate::bitset::Bitset;
+    pub use crate::bundle::Bundle;
+    pub use crate::components::{
+        ComponentDynRef, ComponentHook, ComponentId, ComponentRef, ComponentSet, ComponentsMut,
+        ComponentsRef, SharedComponentsRef, StorageKind,
+    };
+    pub use crate::delta::ChunkDelta;
+    pub use crate::diagnostics::{ColumnDiagnostics, Diagnostics};
+    pub use crate::entities::{Entities, Entity, EntityMap, MapEntities};
+    pub use crate::entity_ref::{EntityMut, EntityRef};
+    #[cfg(all(
+        feature = "std",
+        any(not(feature = "single-threaded"), feature = "sync")
+    ))]
+    pub use crate::executor::{ParSystem, ParSystemParam};
+    pub use crate::hierarchy::{Children, Parent};
+    pub use crate::names::Name;
+    #[cfg(feature = "std")]
+    pub use crate::parallel::{ParQuery, ParRows};
+    pub use crate::plugin::Plugin;
+    pub use crate::prefab::Prefab;
+    pub use crate::profiling::{Clock, SystemTiming, SystemTimings};
     pub use crate::query::*;
-    pub use crate::resources::{ResourceMut, ResourceRef, Resources, ResourcesBuilder, ItemMut, ItemRef};
-    pub use crate::systems::{System, SystemsContext};
-    pub use crate::{Chunk, ChunkBuilder, CommandQueue, Commands};
+    pub use crate::registry::{ComponentInfo, ComponentRegistry};
+    pub use crate::resources::{
+        ItemMut, ItemRef, NonSend, NonSendMut, Resource, ResourceMut, ResourceRef, Resources,
+        ResourcesBuilder,
+    };
+    pub use crate::schedule::{Conflict, Schedule, SetConfig, SystemConfig, SystemInfo};
+    pub use crate::snapshot::ChunkSnapshot;
+    pub use crate::state::{
+        apply_state_transition, in_state, on_enter, on_exit, NextState, State, StateCondition,
+    };
+    pub use crate::storage::{Component, Storage};
+    pub use crate::systems::{In, Pipe, System, SystemOutput, SystemParam, SystemsContext};
+    pub use crate::tags::Tags;
+    pub use crate::world::{ChunkId, World};
+    pub use crate::{Chunk, ChunkBuilder, CommandQueue, Commands, EntitySpawner, FlushPolicy};
+    #[cfg(feature = "derive")]
+    pub use microecs_derive::{Bundle, Component, SystemParam};
 }
 
 #[derive(Clone, Debug)]
@@ -36,12 +115,150 @@ pub enum Error {
     CorruptedResource(&'static str),
     CommandQueueMissing,
     CommandQueueAlreadyBorrowedMutably,
+    HierarchyCycle(Entity),
+    ComponentNotCloneable(&'static str),
+    ChunkNotFound(ChunkId),
+    QueryNoMatch(&'static str),
+    QueryMultipleMatches(&'static str),
+    NonSendAccessedFromOtherThread(&'static str),
+    UnknownComponentName(String),
+    ComponentTypeMismatch(&'static str),
+    ComponentTypeNotRegistered(TypeId),
+    ScheduleCycle(&'static str),
+    ComponentStorageNotShareable(&'static str),
+    TooManyTags,
+    ForeignEntity(Entity),
+    CommandFailed(CommandContext, Box<Error>),
+    CommandsFailed(Vec<Error>),
+}
+
+/// Where a command failing during a flush came from.
+#[derive(Clone, Debug)]
+pub struct CommandContext {
+    /// The type name of the system that queued the command, `None` if it was queued outside of
+    /// `SystemsContext`.
+    pub system: Option<&'static str>,
+    /// The position of the command among the ones applied by the flush.
+    pub index: usize,
+    /// The `Commands` method that queued the command, e.g. `"insert"`.
+    pub command: &'static str,
+}
+
+impl Error {
+    pub(crate) fn command_failed(
+        system: Option<&'static str>,
+        index: usize,
+        command: &'static str,
+        error: Error,
+    ) -> Self {
+        let context = CommandContext {
+            system,
+            index,
+            command,
+        };
+        Error::CommandFailed(context, Box::new(error))
+    }
+
+    /// The error that caused this one, looking through the context of `CommandFailed`.
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::CommandFailed(_, error) => error.root(),
+            error => error,
+        }
+    }
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::InvalidEntity(entity) => write!(f, "{entity:?} does not exist"),
+            Error::InternalStorageError(name) => write!(f, "storage of {name} is corrupted"),
+            Error::ComponentNotRegistered(name) => write!(f, "component {name} is not registered"),
+            Error::ComponentAlreadyBorrowedMutably(name) => {
+                write!(f, "component {name} is already borrowed mutably")
+            }
+            Error::ResourceNotFound(name) => write!(f, "resource {name} not found"),
+            Error::ResourceAlreadyBorrowedMutably(name) => {
+                write!(f, "resource {name} is already borrowed mutably")
+            }
+            Error::CorruptedResource(name) => write!(f, "resource {name} is corrupted"),
+            Error::CommandQueueMissing => write!(f, "no command queue to record commands into"),
+            Error::CommandQueueAlreadyBorrowedMutably => {
+                write!(f, "command queue is already borrowed mutably")
+            }
+            Error::HierarchyCycle(entity) => {
+                write!(f, "{entity:?} cannot become a descendant of itself")
+            }
+            Error::ComponentNotCloneable(name) => {
+                write!(f, "component {name} was registered without a clone hook")
+            }
+            Error::ChunkNotFound(id) => write!(f, "{id:?} not found"),
+            Error::QueryNoMatch(name) => write!(f, "query {name} matched no entity"),
+            Error::QueryMultipleMatches(name) => {
+                write!(f, "query {name} matched more than one entity")
+            }
+            Error::NonSendAccessedFromOtherThread(name) => write!(
+                f,
+                "non-send resource {name} accessed from another thread than the one that added it"
+            ),
+            Error::UnknownComponentName(name) => {
+                write!(f, "no component registered under the name {name:?}")
+            }
+            Error::ComponentTypeMismatch(name) => write!(f, "value is not a {name}"),
+            Error::ComponentTypeNotRegistered(type_id) => {
+                write!(f, "component of {type_id:?} is not registered")
+            }
+            Error::ScheduleCycle(name) => {
+                write!(f, "system {name} waits on a cycle of ordering constraints")
+            }
+            Error::ComponentStorageNotShareable(name) => {
+                write!(
+                    f,
+                    "component {name} has a custom storage, which may not be shared"
+                )
+            }
+            Error::TooManyTags => write!(f, "a chunk cannot have more than 64 tags"),
+            Error::ForeignEntity(entity) => {
+                write!(f, "{entity:?} was reserved by another chunk")
+            }
+            Error::CommandFailed(context, error) => {
+                let CommandContext {
+                    system,
+                    index,
+                    command,
+                } = context;
+                write!(f, "command {index} ({command}) ")?;
+                match system {
+                    Some(system) => write!(f, "queued by {system} failed: {error}"),
+                    None => write!(f, "failed: {error}"),
+                }
+            }
+            Error::CommandsFailed(errors) => {
+                write!(f, "{} commands failed", errors.len())?;
+                match errors.first() {
+                    Some(error) => write!(f, ", the first: {error}"),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+impl core::error::Error for Error {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Error::CommandFailed(_, error) => Some(&**error),
+            Error::CommandsFailed(errors) => errors.first().map(|error| error as _),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Default)]
 pub struct ChunkBuilder {
     components_builder: ComponentsBuilder,
     items_builder: ResourcesBuilder,
+    flush_policy: FlushPolicy,
 }
 
 impl ChunkBuilder {
@@ -50,7 +267,65 @@ impl ChunkBuilder {
         self
     }
 
-    pub fn with_item<T: 'static>(mut self, value: T) -> Self {
+    /// Registers `T` along with the clone hook used by `Chunk::clone_entity`.
+    pub fn with_cloneable_component<T: Clone + 'static>(mut self) -> Self {
+        self.components_builder = self.components_builder.with_clone_hook::<T>();
+        self
+    }
+
+    /// Registers `T` along with the hook used by `Chunk::debug_dump` to print its values.
+    pub fn with_debug_component<T: core::fmt::Debug + 'static>(mut self) -> Self {
+        self.components_builder = self.components_builder.with_debug_hook::<T>();
+        self
+    }
+
+    /// Registers `T` along with the hook rewriting the entities it holds on `Chunk::merge` and
+    /// `Chunk::map_entities`.
+    pub fn with_mapped_component<T: MapEntities + 'static>(mut self) -> Self {
+        self.components_builder = self.components_builder.with_map_entities_hook::<T>();
+        self
+    }
+
+    /// Registers `T` with the given storage, dense being the default.
+    pub fn with_storage<T: 'static>(mut self, kind: StorageKind) -> Self {
+        self.components_builder = self.components_builder.with_storage::<T>(kind);
+        self
+    }
+
+    /// Registers `T` with the storage chosen by its `Component` impl.
+    pub fn with_custom_storage<T: Component>(mut self) -> Self {
+        self.components_builder = self.components_builder.with_custom_storage::<T>();
+        self
+    }
+
+    /// Calls `hook` whenever a `T` is added to an entity that had none, through
+    /// `Chunk::add_component`, `Chunk::insert_batch`, `Chunk::spawn_batch`, `Chunk::clone_entity`,
+    /// `Chunk::merge`, `Chunk::move_entity` (in the destination), `EntityMut::insert` or
+    /// `Commands::insert`. Views such as `ComponentsMut` and `Chunk::restore` do not call hooks.
+    pub fn on_add<T: 'static>(mut self, hook: ComponentHook) -> Self {
+        self.components_builder = self.components_builder.with_on_add::<T>(hook);
+        self
+    }
+
+    /// Calls `hook` whenever a `T` is removed from an entity, through `Chunk::remove_component`,
+    /// `Chunk::destroy`, `Chunk::retain`, `Chunk::clear`, `Chunk::move_entity` (in the source),
+    /// `EntityMut::remove` or `Commands`.
+    pub fn on_remove<T: 'static>(mut self, hook: ComponentHook) -> Self {
+        self.components_builder = self.components_builder.with_on_remove::<T>(hook);
+        self
+    }
+
+    /// Sets the `FlushPolicy` of the queue used by `Chunk::with`.
+    pub fn with_flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.flush_policy = policy;
+        self
+    }
+
+    pub fn with_components<T: ComponentSet>(self) -> Self {
+        T::register(self)
+    }
+
+    pub fn with_item<T: Resource>(mut self, value: T) -> Self {
         self.items_builder = self.items_builder.with_resource::<T>(value);
         self
     }
@@ -58,8 +333,26 @@ impl ChunkBuilder {
     pub fn build(self) -> Chunk {
         Chunk {
             entities: ChunkEntities::new(),
-            components: self.components_builder.build(),
+            components: self
+                .components_builder
+                .with_clone_hook::<Parent>()
+                .with_map_entities_hook::<Parent>()
+                .with_clone_hook::<Children>()
+                .with_map_entities_hook::<Children>()
+                .with_clone_hook::<Name>()
+                .with_clone_hook::<Tags>()
+                .with_debug_hook::<Parent>()
+                .with_debug_hook::<Children>()
+                .with_debug_hook::<Name>()
+                .with_debug_hook::<Tags>()
+                .build(),
             items: self.items_builder.build(),
+            names: HashMap::new(),
+            tags: TagRegistry::default(),
+            hook_commands: CommandQueue::new(),
+            command_queue: CommandQueue::new().with_flush_policy(self.flush_policy),
+            delayed_commands: DelayedCommands::default(),
+            system_states: HashMap::new(),
         }
     }
 }
@@ -68,10 +361,25 @@ pub struct Chunk {
     entities: ChunkEntities,
     components: ChunkComponents,
     items: Resources,
+    names: HashMap<String, Entity>,
+    tags: TagRegistry,
+    // commands recorded by component hooks, applied after the commands of each system
+    hook_commands: CommandQueue,
+    // the queue of the systems run through `with`
+    command_queue: CommandQueue,
+    delayed_commands: DelayedCommands,
+    // the param states of the systems run through `SystemsContext`, by system type
+    system_states: HashMap<TypeId, Box<dyn Any>>,
 }
 
 impl Chunk {
-    pub fn with<'a>(
+    /// Runs systems on this chunk, recording their commands into a queue owned by the chunk.
+    pub fn with<'a>(&'a mut self, resources: &'a mut Resources) -> SystemsContext<'a> {
+        SystemsContext::with_chunk_queue(self, resources)
+    }
+
+    /// Like `with`, but records the commands into `command_queue`.
+    pub fn with_queue<'a>(
         &'a mut self,
         resources: &'a mut Resources,
         command_queue: &'a mut CommandQueue,
@@ -79,80 +387,440 @@ impl Chunk {
         SystemsContext::new(self, resources, command_queue)
     }
 
+    #[inline]
+    pub fn entity(&self, entity: Entity) -> Result<EntityRef<'_>, Error> {
+        EntityRef::new(self, entity)
+    }
+
+    #[inline]
+    pub fn entity_mut(&mut self, entity: Entity) -> Result<EntityMut<'_>, Error> {
+        EntityMut::new(self, entity)
+    }
+
     #[inline]
     pub fn spawn(&mut self) -> Result<Entity, Error> {
         self.entities.spawn(&mut self.components)
     }
 
+    /// The current tick, which components borrowed mutably are stamped with, see
+    /// `ComponentsRef::last_changed`. Starts at 0 and only moves through `advance_tick` and
+    /// `restore`.
+    #[inline]
+    pub fn tick(&self) -> u32 {
+        self.components.tick()
+    }
+
+    /// Moves to the next tick, wrapping around after `u32::MAX`, and returns it. Typically called
+    /// once per frame or per network update.
+    pub fn advance_tick(&mut self) -> u32 {
+        self.components.advance_tick()
+    }
+
+    /// Makes room for `additional` more entities in the entity table and in every component
+    /// column, so that spawning them does not reallocate.
+    pub fn reserve_entities(&mut self, additional: usize) {
+        self.entities.reserve_rows(additional);
+        self.components.reserve(additional);
+    }
+
+    /// Releases the memory the entity table and the component columns hold beyond their rows.
+    pub fn shrink_to_fit(&mut self) {
+        self.entities.shrink_to_fit();
+        self.components.shrink_to_fit();
+    }
+
+    /// Spawns one entity per bundle, growing the entity table and the component columns once.
+    pub fn spawn_batch<B: Bundle>(
+        &mut self,
+        bundles: impl IntoIterator<Item = B>,
+    ) -> Result<Vec<Entity>, Error> {
+        // fail before creating any row if one of the components is not registered
+        drop(B::columns(self)?);
+        let bundles = bundles.into_iter().collect::<Vec<_>>();
+        let first_index = self.entities.len();
+        self.entities.reserve_rows(bundles.len());
+        let spawned = (0..bundles.len())
+            .map(|_| self.entities.push_row_unchecked())
+            .collect::<Vec<_>>();
+        self.components.extend_none(bundles.len());
+        let mut columns = B::columns(self)?;
+        for (i, bundle) in bundles.into_iter().enumerate() {
+            bundle.write(&mut columns, first_index + i);
+        }
+        drop(columns);
+        self.run_add_hooks_from(first_index);
+        Ok(spawned)
+    }
+
+    /// Spawns a copy of `entity`. Every component of `entity` must have been registered with
+    /// `ChunkBuilder::with_cloneable_component`. The copy gets the same parent but no children,
+    /// and no name.
+    pub fn clone_entity(&mut self, entity: Entity) -> Result<Entity, Error> {
+        let index = self
+            .entities
+            .index(entity)
+            .ok_or(Error::InvalidEntity(entity))?;
+        let skip = [
+            TypeId::of::<Parent>(),
+            TypeId::of::<Children>(),
+            TypeId::of::<Name>(),
+        ];
+        if let Some(component) = self.components.non_cloneable(index, &skip) {
+            return Err(Error::ComponentNotCloneable(component));
+        }
+        let parent = self.components_ref::<Parent>()?.get(entity).copied();
+        let clone = self.spawn()?;
+        let clone_index = self.entities.index(clone).unwrap();
+        self.components.clone_row(index, clone_index, &skip)?;
+        self.run_add_hooks_from(clone_index);
+        if let Some(Parent(parent)) = parent {
+            self.set_parent(clone, parent)?;
+        }
+        Ok(clone)
+    }
+
     #[inline]
     pub fn destroy(&mut self, entity: Entity) -> Result<(), Error> {
+        self.detach_hierarchy(entity)?;
+        self.remove_name(entity)?;
+        self.run_remove_hooks(entity)?;
         self.entities.destroy(&mut self.components, entity)
     }
 
+    /// Destroys every entity at once, calling the `on_remove` hooks of all their components. The
+    /// commands still queued in the chunk, including those of `Commands::defer_after`, are
+    /// dropped first. Entity ids are not reused afterwards.
+    pub fn clear(&mut self) {
+        self.command_queue.clear();
+        self.hook_commands.clear();
+        self.delayed_commands.clear();
+        for index in 0..self.entities.len() {
+            let entity = self.entities.at(index).unwrap();
+            for hook in self.components.on_remove_hooks(index) {
+                self.run_hook(hook, entity);
+            }
+        }
+        self.entities.clear();
+        self.components.clear();
+        self.names.clear();
+    }
+
+    /// Destroys every entity for which `predicate` returns false, compacting the rows of the
+    /// survivors in a single pass over each column.
+    pub fn retain(&mut self, mut predicate: impl FnMut(Entity) -> bool) -> Result<(), Error> {
+        let keep = self.entities.iter().map(&mut predicate).collect::<Vec<_>>();
+        let removed = self
+            .entities
+            .iter()
+            .zip(keep.iter())
+            .filter_map(|(entity, keep)| (!keep).then_some(entity))
+            .collect::<Vec<_>>();
+        if removed.is_empty() {
+            return Ok(());
+        }
+        for entity in removed {
+            self.detach_hierarchy(entity)?;
+            self.remove_name(entity)?;
+            self.run_remove_hooks(entity)?;
+        }
+        self.entities.retain_rows(&keep);
+        self.components.retain_rows(&keep);
+        Ok(())
+    }
+
     pub fn add_component<T: 'static>(&mut self, entity: Entity, value: T) -> Result<(), Error> {
         let index = self
             .entities
             .index(entity)
             .ok_or(Error::InvalidEntity(entity))?;
-        self.components_mut::<T>()?.values.set(index, Some(value));
+        let values = self.components.values_mut::<T>()?;
+        let added = values.get(index).is_none();
+        values.set(index, Some(value));
+        match self.components.on_add::<T>() {
+            Some(hook) if added => self.run_hook(hook, entity),
+            _ => {}
+        }
         Ok(())
     }
 
+    /// Like `add_component` for many entities, looking up the column of `T` once. Stops at the
+    /// first invalid entity, keeping the values inserted before it.
+    pub fn insert_batch<T: 'static>(
+        &mut self,
+        values: impl IntoIterator<Item = (Entity, T)>,
+    ) -> Result<(), Error> {
+        let hook = self.components.on_add::<T>();
+        let column = self.components.values_mut::<T>()?;
+        let mut added = Vec::new();
+        let result = values.into_iter().try_for_each(|(entity, value)| {
+            let index = self
+                .entities
+                .index(entity)
+                .ok_or(Error::InvalidEntity(entity))?;
+            if hook.is_some() && column.get(index).is_none() {
+                added.push(entity);
+            }
+            column.set(index, Some(value));
+            Ok(())
+        });
+        if let Some(hook) = hook {
+            for entity in added {
+                self.run_hook(hook, entity);
+            }
+        }
+        result
+    }
+
     pub fn remove_component<T: 'static>(&mut self, entity: Entity) -> Result<(), Error> {
         let index = self
             .entities
             .index(entity)
             .ok_or(Error::InvalidEntity(entity))?;
-        self.components_mut::<T>()?.values.set(index, None);
+        let removed = self.components.values_mut::<T>()?.remove(index);
+        match self.components.on_remove::<T>() {
+            Some(hook) if removed.is_some() => self.run_hook(hook, entity),
+            _ => {}
+        }
         Ok(())
     }
 
+    /// Applies the commands recorded by component hooks. Done after every system run by
+    /// `SystemsContext`, only needed after changing components outside of systems.
+    pub fn flush_hook_commands(&mut self, resources: &mut Resources) -> Result<(), Error> {
+        if self.hook_commands.is_empty() {
+            return Ok(());
+        }
+        let mut queue = core::mem::take(&mut self.hook_commands);
+        // commands recorded while flushing go to the new queue and are flushed recursively
+        let result = queue.flush(self, resources);
+        self.hook_commands = queue;
+        result
+    }
+
+    /// Runs the commands of `Commands::defer_after` whose tick has come.
+    fn run_due_commands(
+        &mut self,
+        resources: &mut Resources,
+        policy: FlushPolicy,
+        failures: &mut Vec<Error>,
+    ) -> Result<(), Error> {
+        let tick = self.tick();
+        let mut due = self.delayed_commands.take_due(tick).into_iter().enumerate();
+        while let Some((index, (system, command))) = due.next() {
+            if let Err(error) = command(self, resources) {
+                let error = Error::command_failed(system, index, "defer_after", error);
+                if let Err(error) = policy.handle(error, failures) {
+                    // keep the commands after the failing one for the next flush
+                    for (_, (system, command)) in due {
+                        self.delayed_commands.push(tick, system, command);
+                    }
+                    return Err(error);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn run_remove_hooks(&mut self, entity: Entity) -> Result<(), Error> {
+        let index = self
+            .entities
+            .index(entity)
+            .ok_or(Error::InvalidEntity(entity))?;
+        for hook in self.components.on_remove_hooks(index) {
+            self.run_hook(hook, entity);
+        }
+        Ok(())
+    }
+
+    /// Calls the `on_add` hooks of the components of every row from `first_index` on.
+    fn run_add_hooks_from(&mut self, first_index: usize) {
+        for index in first_index..self.entities.len() {
+            let entity = self.entities.at(index).unwrap();
+            for hook in self.components.on_add_hooks(index) {
+                self.run_hook(hook, entity);
+            }
+        }
+    }
+
+    fn run_hook(&self, hook: ComponentHook, entity: Entity) {
+        hook(
+            entity,
+            &mut self.hook_commands.deferred_commands(&self.entities),
+        );
+    }
+
+    /// Rewrites the entities held by every component registered with
+    /// `ChunkBuilder::with_mapped_component`, e.g. after loading entities saved elsewhere.
+    pub fn map_entities(&mut self, map: &EntityMap) {
+        self.components.map_entities(0, map);
+    }
+
+    /// Registers `T` on an already built chunk. Existing entities start without the component.
+    /// Does nothing if `T` is already registered.
+    pub fn register_component<T: 'static>(&mut self) {
+        self.components.register::<T>(self.entities.len());
+    }
+
+    /// Appends all the entities of `other` to this chunk, returning which entity each of them
+    /// became. Fails without modifying this chunk if it lacks one of the component types used by
+    /// `other`.
+    pub fn merge(&mut self, mut other: Chunk) -> Result<EntityMap, Error> {
+        if let Some(missing) = other.components.missing_columns_in(&self.components) {
+            return Err(Error::ComponentNotRegistered(missing));
+        }
+        let tags = self.tags.merge(&other.tags)?;
+        let first_index = self.entities.len();
+        let mut map = EntityMap::default();
+        for entity in other.entities.iter() {
+            map.insert(entity, self.entities.push_row_unchecked());
+        }
+        self.components
+            .append(&mut other.components, other.entities.len())?;
+        self.components.map_entities(first_index, &map);
+        self.index_names(first_index)?;
+        self.remap_tags(first_index, &tags)?;
+        self.run_add_hooks_from(first_index);
+        Ok(map)
+    }
+
+    /// Moves `entity` and all of its components into `destination`, returning its new handle.
+    /// Fails without modifying either chunk if `destination` lacks one of the entity's component
+    /// types or has no room for the tags of this chunk. The entity is detached from its parent and
+    /// children beforehand, its name is kept.
+    pub fn move_entity(
+        &mut self,
+        destination: &mut Chunk,
+        entity: Entity,
+    ) -> Result<Entity, Error> {
+        let index = self
+            .entities
+            .index(entity)
+            .ok_or(Error::InvalidEntity(entity))?;
+        if let Some(missing) = self.components.missing_in(index, &destination.components) {
+            return Err(Error::ComponentNotRegistered(missing));
+        }
+        destination.tags.check_merge(&self.tags)?;
+        // nothing below fails: the columns match by type and, both chunks being borrowed
+        // mutably, none of them is locked
+        let tags = destination.tags.merge(&self.tags)?;
+        self.detach_hierarchy(entity)?;
+        let name = self.components_ref::<Name>()?.get(entity).cloned();
+        self.remove_name(entity)?;
+        self.run_remove_hooks(entity)?;
+        let moved = destination.spawn()?;
+        let destination_index = destination.entities.index(moved).unwrap();
+        self.components
+            .move_row(index, &mut destination.components, destination_index)?;
+        self.entities.destroy(&mut self.components, entity)?;
+        if let Some(Name(name)) = name {
+            destination.set_name(moved, name)?;
+        }
+        destination.remap_tags(destination_index, &tags)?;
+        destination.run_add_hooks_from(destination_index);
+        Ok(moved)
+    }
+
     #[inline]
-    pub fn components_ref<T: 'static>(&self) -> Result<ComponentsRef<T>, Error> {
+    pub fn components_ref<T: 'static>(&self) -> Result<ComponentsRef<'_, T>, Error> {
         self.components.components_ref::<T>(&self.entities)
     }
 
     #[inline]
-    pub fn components_mut<T: 'static>(&self) -> Result<ComponentsMut<T>, Error> {
+    pub fn components_mut<T: 'static>(&self) -> Result<ComponentsMut<'_, T>, Error> {
         self.components.components_mut::<T>(&self.entities)
     }
-}
 
-pub struct CommandQueue(RwLock<VecDeque<Command>>);
+    /// Borrows the values of `T` for reading from other threads, e.g. through
+    /// `std::thread::scope`. Writing `T` fails until the returned view is dropped. Fails for
+    /// components registered with `ChunkBuilder::with_custom_storage`, whose storage may not be
+    /// safe to share.
+    #[inline]
+    pub fn shared_components_ref<T: Sync + 'static>(
+        &self,
+    ) -> Result<SharedComponentsRef<'_, T>, Error> {
+        self.components.shared_components_ref::<T>(&self.entities)
+    }
 
-impl CommandQueue {
-    pub fn new() -> Self {
-        Self(RwLock::new(VecDeque::new()))
+    /// Looks up the id of the column of `T` once, so that hot loops can then borrow it through
+    /// `components_ref_by_id` and `components_mut_by_id` without hashing its `TypeId`.
+    #[inline]
+    pub fn component_id<T: 'static>(&self) -> Result<ComponentId, Error> {
+        self.components.component_id::<T>()
     }
 
-    pub fn flush(&mut self, chunk: &mut Chunk, resources: &mut Resources) -> Result<(), Error> {
-        let mut command_queue = self
-            .0
-            .try_write()
-            .ok_or(Error::CommandQueueAlreadyBorrowedMutably)?;
-        while let Some(command) = command_queue.pop_front() {
-            (command)(chunk, resources)?;
-        }
-        Ok(())
+    /// Fails if `id` is not the id of the column of `T` in this chunk.
+    #[inline]
+    pub fn components_ref_by_id<T: 'static>(
+        &self,
+        id: ComponentId,
+    ) -> Result<ComponentsRef<'_, T>, Error> {
+        self.components
+            .components_ref_by_id::<T>(&self.entities, id)
     }
 
-    pub(crate) fn deferred_commands(&self) -> Result<Commands, Error> {
-        self.0
-            .try_write()
-            .ok_or(Error::CommandQueueAlreadyBorrowedMutably)
-            .map(Commands)
+    /// Fails if `id` is not the id of the column of `T` in this chunk.
+    #[inline]
+    pub fn components_mut_by_id<T: 'static>(
+        &self,
+        id: ComponentId,
+    ) -> Result<ComponentsMut<'_, T>, Error> {
+        self.components
+            .components_mut_by_id::<T>(&self.entities, id)
     }
 }
 
-type Command = Box<dyn Fn(&mut Chunk, &mut Resources) -> Result<(), Error> + Send + Sync>;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-pub struct Commands<'a>(pub(crate) RwLockWriteGuard<'a, VecDeque<Command>>);
+    struct Health(u32);
 
-impl Commands<'_> {
-    pub fn defer(
-        &mut self,
-        command: impl Fn(&mut Chunk, &mut Resources) -> Result<(), Error> + Send + Sync + 'static,
-    ) {
-        self.0.push_back(Box::new(command));
+    struct Armor;
+
+    fn health(chunk: &Chunk, entity: Entity) -> Option<u32> {
+        chunk
+            .components_ref::<Health>()
+            .unwrap()
+            .get(entity)
+            .map(|h| h.0)
+    }
+
+    #[test]
+    fn merges_entities_with_their_components_names_and_parents() {
+        let mut chunk = ChunkBuilder::default().with_component::<Health>().build();
+        let kept = chunk.spawn().unwrap();
+        chunk.add_component(kept, Health(1)).unwrap();
+
+        let mut other = ChunkBuilder::default().with_component::<Health>().build();
+        let parent = other.spawn().unwrap();
+        let child = other.spawn().unwrap();
+        other.add_component(child, Health(2)).unwrap();
+        other.set_parent(child, parent).unwrap();
+        other.set_name(parent, "parent").unwrap();
+
+        let map = chunk.merge(other).unwrap();
+        let (parent, child) = (map.map(parent), map.map(child));
+        assert_eq!(map.len(), 2);
+        assert_eq!(
+            [kept, parent, child].map(|e| health(&chunk, e)),
+            [Some(1), None, Some(2)]
+        );
+        let parents = chunk.components_ref::<Parent>().unwrap();
+        assert_eq!(parents.get(child).map(Parent::get), Some(parent));
+        assert_eq!(chunk.entity_by_name("parent"), Some(parent));
+    }
+
+    #[test]
+    fn fails_to_merge_unregistered_components() {
+        let mut chunk = ChunkBuilder::default().with_component::<Health>().build();
+        chunk.spawn().unwrap();
+        let mut other = ChunkBuilder::default().with_component::<Armor>().build();
+        let entity = other.spawn().unwrap();
+        other.add_component(entity, Armor).unwrap();
+        assert!(matches!(
+            chunk.merge(other),
+            Err(Error::ComponentNotRegistered(_))
+        ));
+        assert_eq!(chunk.entities.len(), 1);
     }
 }
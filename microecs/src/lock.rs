@@ -0,0 +1,69 @@
+//! The locks guarding columns and resources. With the `single-threaded` feature they are
+//! `RefCell`s, which skip the atomic operations of `spin::RwLock` while failing the same way.
+//! `sync` takes precedence: with both features the locks stay `spin::RwLock`s, so that crates
+//! enabling either one can share a build.
+
+#[cfg(any(not(feature = "single-threaded"), feature = "sync"))]
+pub(crate) use spin::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+#[cfg(all(feature = "single-threaded", not(feature = "sync")))]
+pub(crate) use cell::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+#[cfg(all(feature = "single-threaded", not(feature = "sync")))]
+mod cell {
+    use core::cell::{Ref, RefCell, RefMut};
+
+    pub(crate) type RwLockReadGuard<'a, T> = Ref<'a, T>;
+
+    pub(crate) type RwLockWriteGuard<'a, T> = RefMut<'a, T>;
+
+    /// A `RefCell` with the methods of `spin::RwLock` used by the crate.
+    #[derive(Default)]
+    pub(crate) struct RwLock<T>(RefCell<T>);
+
+    impl<T> RwLock<T> {
+        #[inline]
+        pub(crate) const fn new(value: T) -> Self {
+            Self(RefCell::new(value))
+        }
+
+        #[inline]
+        pub(crate) fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+            self.0.try_borrow().ok()
+        }
+
+        #[inline]
+        pub(crate) fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+            self.0.try_borrow_mut().ok()
+        }
+
+        #[inline]
+        pub(crate) fn get_mut(&mut self) -> &mut T {
+            self.0.get_mut()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RwLock;
+
+    // both backends must fail in the same cases, the crate maps `None` to its borrow errors
+    #[test]
+    fn borrows_exclude_writes() {
+        let mut lock = RwLock::new(1);
+        let first = lock.try_read().unwrap();
+        let second = lock.try_read().unwrap();
+        assert!(lock.try_write().is_none());
+        drop((first, second));
+
+        let mut write = lock.try_write().unwrap();
+        *write += 1;
+        assert!(lock.try_read().is_none());
+        assert!(lock.try_write().is_none());
+        drop(write);
+
+        assert_eq!(*lock.get_mut(), 2);
+        assert_eq!(*lock.try_read().unwrap(), 2);
+    }
+}
@@ -0,0 +1,61 @@
+use crate::entities::Entity;
+use crate::{Chunk, Error};
+use alloc::string::String;
+
+/// The name of an entity, managed through `Chunk::set_name` so that `Chunk::entity_by_name` can
+/// find it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Name(pub(crate) String);
+
+impl Name {
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Chunk {
+    /// Names `entity`, replacing its previous name. Names are expected to be unique: when two
+    /// entities share a name, the lookup returns the one named last.
+    pub fn set_name(&mut self, entity: Entity, name: impl Into<String>) -> Result<(), Error> {
+        let name = name.into();
+        self.remove_name(entity)?;
+        self.names.insert(name.clone(), entity);
+        self.components_mut::<Name>()?.insert(entity, Name(name))
+    }
+
+    pub fn remove_name(&mut self, entity: Entity) -> Result<(), Error> {
+        let index = self
+            .entities
+            .index(entity)
+            .ok_or(Error::InvalidEntity(entity))?;
+        let mut names = self.components.components_mut::<Name>(&self.entities)?;
+        if let Some(Name(name)) = names.values.remove(index) {
+            if self.names.get(&name) == Some(&entity) {
+                self.names.remove(&name);
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn entity_by_name(&self, name: &str) -> Option<Entity> {
+        self.names.get(name).copied()
+    }
+
+    /// Indexes the names of the rows starting at `first_index`, e.g. after a merge.
+    pub(crate) fn index_names(&mut self, first_index: usize) -> Result<(), Error> {
+        let names = self.components.components_ref::<Name>(&self.entities)?;
+        let indexed = self
+            .entities
+            .iter()
+            .skip(first_index)
+            .zip(names.values.iter().skip(first_index));
+        for (entity, name) in indexed {
+            if let Some(Name(name)) = name {
+                self.names.insert(name.clone(), entity);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,403 @@
+use crate::bitset::Bitset;
+use crate::components::{ComponentsMut, ComponentsRef, MutRows, SharedRows};
+use crate::entities::Entities;
+use crate::query::{Filter, Maybe, Query, With, Without};
+use alloc::vec::Vec;
+use std::thread;
+
+/// A query that `Query::par_for_each` splits into ranges of rows, one per thread.
+pub trait ParQuery<'a>: Query<'a> + Sized {
+    type Rows: ParRows<Item = Self::Item>;
+
+    /// Returns false if one of the columns is kept by a `Storage`, sparse or custom, which
+    /// cannot lend its rows to several threads.
+    fn splittable(&self) -> bool;
+
+    /// Only called once `splittable` returned true.
+    fn par_rows(self) -> Self::Rows;
+}
+
+/// The rows of a `ParQuery`, fetched from several threads at once.
+///
+/// # Safety
+///
+/// `fetch` must be sound to call from several threads at once as long as no row is fetched
+/// twice.
+pub unsafe trait ParRows: Sync {
+    type Item;
+
+    /// The number of rows of the chunk.
+    fn rows(&self) -> usize;
+
+    /// The rows that may yield an item, if known, see `Query::presence`.
+    fn presence(&self) -> Option<Bitset>;
+
+    /// Returns the item of `row`, like `Query::fetch`.
+    ///
+    /// # Safety
+    ///
+    /// `row` must be below `rows` and not have been fetched before.
+    unsafe fn fetch(&self, row: usize) -> Option<Self::Item>;
+}
+
+/// See `Query::par_for_each`.
+pub(crate) fn par_for_each<'a, Q>(query: Q, f: impl Fn(Q::Item) + Sync)
+where
+    Q: ParQuery<'a>,
+    Q::Item: Send,
+{
+    if !query.splittable() {
+        return par_for_each_collected(query, f);
+    }
+    let rows = query.par_rows();
+    let presence = rows.presence();
+    let len = rows.rows();
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let batch = len.div_ceil(threads).max(1);
+    let run = |start: usize| {
+        for row in start..(start + batch).min(len) {
+            if presence
+                .as_ref()
+                .is_some_and(|presence| !presence.contains(row))
+            {
+                continue;
+            }
+            // SAFETY: `row` is below `len` and the ranges of the batches do not overlap.
+            if let Some(item) = unsafe { rows.fetch(row) } {
+                f(item);
+            }
+        }
+    };
+    if batch >= len {
+        return run(0);
+    }
+    let run = &run;
+    thread::scope(|scope| {
+        for start in (0..len).step_by(batch) {
+            scope.spawn(move || run(start));
+        }
+    });
+}
+
+/// Spreads the items of a query that cannot be split by rows over the threads.
+fn par_for_each_collected<'a, Q>(query: Q, f: impl Fn(Q::Item) + Sync)
+where
+    Q: Query<'a>,
+    Q::Item: Send,
+{
+    let items = query.query().collect::<Vec<_>>();
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let batch = items.len().div_ceil(threads).max(1);
+    let f = &f;
+    let mut items = items.into_iter();
+    thread::scope(|scope| loop {
+        let batch = items.by_ref().take(batch).collect::<Vec<_>>();
+        if batch.is_empty() {
+            break;
+        }
+        scope.spawn(move || batch.into_iter().for_each(f));
+    });
+}
+
+impl<'a, 'b> ParQuery<'a> for &'a Entities<'b> {
+    type Rows = Self;
+
+    fn splittable(&self) -> bool {
+        true
+    }
+
+    fn par_rows(self) -> Self::Rows {
+        self
+    }
+}
+
+// SAFETY: fetching an entity only reads the entity table.
+unsafe impl ParRows for &Entities<'_> {
+    type Item = crate::entities::Entity;
+
+    fn rows(&self) -> usize {
+        self.0.len()
+    }
+
+    fn presence(&self) -> Option<Bitset> {
+        None
+    }
+
+    unsafe fn fetch(&self, row: usize) -> Option<Self::Item> {
+        self.0.at(row)
+    }
+}
+
+impl<'a, T: Sync> ParQuery<'a> for &'a ComponentsRef<'_, T> {
+    type Rows = SharedRows<'a, T>;
+
+    fn splittable(&self) -> bool {
+        !self.values.is_keyed()
+    }
+
+    fn par_rows(self) -> Self::Rows {
+        // checked by `splittable`
+        self.values.shared_rows().unwrap()
+    }
+}
+
+impl<'a, T: Sync> ParQuery<'a> for &'a ComponentsMut<'_, T> {
+    type Rows = SharedRows<'a, T>;
+
+    fn splittable(&self) -> bool {
+        !self.values.is_keyed()
+    }
+
+    fn par_rows(self) -> Self::Rows {
+        // checked by `splittable`
+        self.values.shared_rows().unwrap()
+    }
+}
+
+// SAFETY: shared rows are only read.
+unsafe impl<'a, T: Sync> ParRows for SharedRows<'a, T> {
+    type Item = &'a T;
+
+    fn rows(&self) -> usize {
+        SharedRows::len(self)
+    }
+
+    fn presence(&self) -> Option<Bitset> {
+        Some(self.present().clone())
+    }
+
+    unsafe fn fetch(&self, row: usize) -> Option<Self::Item> {
+        self.get(row)
+    }
+}
+
+impl<'a, T: Send> ParQuery<'a> for &'a mut ComponentsMut<'_, T> {
+    type Rows = MutRows<'a, T>;
+
+    fn splittable(&self) -> bool {
+        !self.values.is_keyed()
+    }
+
+    fn par_rows(self) -> Self::Rows {
+        // checked by `splittable`
+        self.values.mut_rows().unwrap()
+    }
+}
+
+// SAFETY: `MutRows::get_mut` only requires each row to be borrowed once.
+unsafe impl<'a, T: Send> ParRows for MutRows<'a, T> {
+    type Item = &'a mut T;
+
+    fn rows(&self) -> usize {
+        MutRows::len(self)
+    }
+
+    fn presence(&self) -> Option<Bitset> {
+        Some(self.present().clone())
+    }
+
+    unsafe fn fetch(&self, row: usize) -> Option<Self::Item> {
+        unsafe { self.get_mut(row) }
+    }
+}
+
+impl<'a, Q: ParQuery<'a>> ParQuery<'a> for Maybe<Q> {
+    type Rows = Maybe<Q::Rows>;
+
+    fn splittable(&self) -> bool {
+        self.0.splittable()
+    }
+
+    fn par_rows(self) -> Self::Rows {
+        Maybe(self.0.par_rows())
+    }
+}
+
+// SAFETY: forwards to the wrapped rows.
+unsafe impl<R: ParRows> ParRows for Maybe<R> {
+    type Item = Option<R::Item>;
+
+    fn rows(&self) -> usize {
+        self.0.rows()
+    }
+
+    fn presence(&self) -> Option<Bitset> {
+        None
+    }
+
+    unsafe fn fetch(&self, row: usize) -> Option<Self::Item> {
+        Some(unsafe { self.0.fetch(row) })
+    }
+}
+
+impl<'a, Q: ParQuery<'a>, F: ParQuery<'a>> ParQuery<'a> for With<Q, F> {
+    type Rows = With<Q::Rows, F::Rows>;
+
+    fn splittable(&self) -> bool {
+        self.0.splittable() && self.1.splittable()
+    }
+
+    fn par_rows(self) -> Self::Rows {
+        With(self.0.par_rows(), self.1.par_rows())
+    }
+}
+
+// SAFETY: forwards to the wrapped rows, fetching each row once from both.
+unsafe impl<Q: ParRows, F: ParRows> ParRows for With<Q, F> {
+    type Item = Q::Item;
+
+    fn rows(&self) -> usize {
+        self.0.rows()
+    }
+
+    fn presence(&self) -> Option<Bitset> {
+        self.1.presence().or_else(|| self.0.presence())
+    }
+
+    unsafe fn fetch(&self, row: usize) -> Option<Self::Item> {
+        unsafe {
+            self.1.fetch(row)?;
+            self.0.fetch(row)
+        }
+    }
+}
+
+impl<'a, Q: ParQuery<'a>, F: ParQuery<'a>> ParQuery<'a> for Without<Q, F> {
+    type Rows = Without<Q::Rows, F::Rows>;
+
+    fn splittable(&self) -> bool {
+        self.0.splittable() && self.1.splittable()
+    }
+
+    fn par_rows(self) -> Self::Rows {
+        Without(self.0.par_rows(), self.1.par_rows())
+    }
+}
+
+// SAFETY: forwards to the wrapped rows, fetching each row once from both.
+unsafe impl<Q: ParRows, F: ParRows> ParRows for Without<Q, F> {
+    type Item = Q::Item;
+
+    fn rows(&self) -> usize {
+        self.0.rows()
+    }
+
+    fn presence(&self) -> Option<Bitset> {
+        self.0.presence()
+    }
+
+    unsafe fn fetch(&self, row: usize) -> Option<Self::Item> {
+        unsafe {
+            match self.1.fetch(row) {
+                Some(_) => None,
+                None => self.0.fetch(row),
+            }
+        }
+    }
+}
+
+impl<'a, Q, P> ParQuery<'a> for Filter<Q, P>
+where
+    Q: ParQuery<'a>,
+    P: Fn(&Q::Item) -> bool + Sync,
+{
+    type Rows = Filter<Q::Rows, P>;
+
+    fn splittable(&self) -> bool {
+        self.0.splittable()
+    }
+
+    fn par_rows(self) -> Self::Rows {
+        Filter(self.0.par_rows(), self.1)
+    }
+}
+
+// SAFETY: forwards to the wrapped rows, the predicate being `Sync`.
+unsafe impl<R, P> ParRows for Filter<R, P>
+where
+    R: ParRows,
+    P: Fn(&R::Item) -> bool + Sync,
+{
+    type Item = R::Item;
+
+    fn rows(&self) -> usize {
+        self.0.rows()
+    }
+
+    fn presence(&self) -> Option<Bitset> {
+        self.0.presence()
+    }
+
+    unsafe fn fetch(&self, row: usize) -> Option<Self::Item> {
+        unsafe { self.0.fetch(row) }.filter(|item| (self.1)(item))
+    }
+}
+
+macro_rules! impl_par_query_for_tuple {
+    ( $(($Q:ident, $q:ident)),+ ) => {
+        impl<'a, $($Q),+> ParQuery<'a> for ($($Q,)+)
+        where
+            $($Q: ParQuery<'a>,)+
+        {
+            type Rows = ($($Q::Rows,)+);
+
+            fn splittable(&self) -> bool {
+                let ($($q,)+) = self;
+                true $(&& $q.splittable())+
+            }
+
+            fn par_rows(self) -> Self::Rows {
+                let ($($q,)+) = self;
+                ($($q.par_rows(),)+)
+            }
+        }
+
+        // SAFETY: forwards to the rows of every element, fetching each row once from each.
+        unsafe impl<$($Q),+> ParRows for ($($Q,)+)
+        where
+            $($Q: ParRows,)+
+        {
+            type Item = ($($Q::Item,)+);
+
+            fn rows(&self) -> usize {
+                // every element belongs to the same chunk
+                let ($($q,)+) = self;
+                0 $(.max($q.rows()))+
+            }
+
+            fn presence(&self) -> Option<Bitset> {
+                let ($($q,)+) = self;
+                let mut known = [$($q.presence()),+].into_iter().flatten();
+                let mut presence = known.next()?;
+                for other in known {
+                    presence.intersect(&other);
+                }
+                Some(presence)
+            }
+
+            unsafe fn fetch(&self, row: usize) -> Option<Self::Item> {
+                let ($($q,)+) = self;
+                // every element is fetched, as in `Query::fetch`
+                let ($($q,)+) = unsafe { ($($q.fetch(row),)+) };
+                Some(($($q?,)+))
+            }
+        }
+    };
+}
+
+impl_par_query_for_tuple!((A, a), (B, b));
+impl_par_query_for_tuple!((A, a), (B, b), (C, c));
+impl_par_query_for_tuple!((A, a), (B, b), (C, c), (D, d));
+impl_par_query_for_tuple!((A, a), (B, b), (C, c), (D, d), (E, e));
+impl_par_query_for_tuple!((A, a), (B, b), (C, c), (D, d), (E, e), (F, f));
+impl_par_query_for_tuple!((A, a), (B, b), (C, c), (D, d), (E, e), (F, f), (G, g));
+impl_par_query_for_tuple!(
+    (A, a),
+    (B, b),
+    (C, c),
+    (D, d),
+    (E, e),
+    (F, f),
+    (G, g),
+    (H, h)
+);
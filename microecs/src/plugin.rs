@@ -0,0 +1,21 @@
+use crate::resources::ResourcesBuilder;
+use crate::ChunkBuilder;
+
+/// A group of registrations shipped together, e.g. the components, storages and hooks of a
+/// physics library along with its resources. See `ChunkBuilder::with_plugin`.
+pub trait Plugin {
+    fn build(
+        &self,
+        chunk: ChunkBuilder,
+        resources: ResourcesBuilder,
+    ) -> (ChunkBuilder, ResourcesBuilder);
+}
+
+impl ChunkBuilder {
+    /// Lets `plugin` register its components on this chunk and its resources in `resources`.
+    pub fn with_plugin(self, plugin: impl Plugin, resources: &mut ResourcesBuilder) -> Self {
+        let (chunk, plugin_resources) = plugin.build(self, core::mem::take(resources));
+        *resources = plugin_resources;
+        chunk
+    }
+}
@@ -0,0 +1,66 @@
+use crate::entities::Entity;
+use crate::entity_ref::EntityMut;
+use crate::{Chunk, Error};
+use alloc::{boxed::Box, vec::Vec};
+
+type InsertClone = Box<dyn Fn(&mut Chunk, Entity) -> Result<(), Error>>;
+
+/// A set of component values spawned together, e.g. the components of an enemy type. Every
+/// instance gets its own clone of each value.
+#[derive(Default)]
+pub struct Prefab {
+    components: Vec<InsertClone>,
+}
+
+impl Prefab {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `value` to the components of every instance.
+    pub fn with<T: Clone + 'static>(mut self, value: T) -> Self {
+        self.components.push(Box::new(move |chunk, entity| {
+            chunk.add_component(entity, value.clone())
+        }));
+        self
+    }
+
+    /// The number of components of every instance.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    /// Spawns an entity with a clone of every component, calling the component hooks. If a
+    /// component is not registered on `chunk`, the entity is destroyed again and the error
+    /// returned.
+    pub fn spawn(&self, chunk: &mut Chunk) -> Result<Entity, Error> {
+        self.spawn_with(chunk, |_| Ok(()))
+    }
+
+    /// Like `spawn`, then lets `overrides` change or add components of the new entity.
+    pub fn spawn_with(
+        &self,
+        chunk: &mut Chunk,
+        overrides: impl FnOnce(&mut EntityMut) -> Result<(), Error>,
+    ) -> Result<Entity, Error> {
+        let entity = chunk.spawn()?;
+        let result = self
+            .components
+            .iter()
+            .try_for_each(|insert| insert(chunk, entity))
+            .and_then(|()| overrides(&mut chunk.entity_mut(entity)?));
+        match result {
+            Ok(()) => Ok(entity),
+            Err(error) => {
+                chunk.destroy(entity)?;
+                Err(error)
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::time::Duration;
+
+/// A monotonic clock. Supplied by the user since microecs does not depend on `std`.
+pub trait Clock: Send + Sync + 'static {
+    /// The time elapsed since an arbitrary, fixed origin.
+    fn now(&self) -> Duration;
+}
+
+/// When added as a resource, `SystemsContext::run` records how long each system took, its
+/// commands included, keyed by the type name of the system.
+pub struct SystemTimings {
+    clock: Box<dyn Clock>,
+    timings: Vec<(&'static str, SystemTiming)>,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemTiming {
+    runs: u32,
+    last: Duration,
+    total: Duration,
+}
+
+impl SystemTimings {
+    pub fn new(clock: impl Clock) -> Self {
+        Self {
+            clock: Box::new(clock),
+            timings: Vec::new(),
+        }
+    }
+
+    /// The timings of every system run so far, in the order in which they first ran.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &SystemTiming)> + use<'_> {
+        self.timings.iter().map(|(name, timing)| (*name, timing))
+    }
+
+    pub fn get(&self, system: &str) -> Option<&SystemTiming> {
+        self.iter()
+            .find(|(name, _)| *name == system)
+            .map(|(_, timing)| timing)
+    }
+
+    /// Forgets every timing, e.g. at the start of a frame.
+    pub fn reset(&mut self) {
+        self.timings.clear();
+    }
+
+    #[inline]
+    pub(crate) fn now(&self) -> Duration {
+        self.clock.now()
+    }
+
+    pub(crate) fn record(&mut self, system: &'static str, duration: Duration) {
+        let index = match self.timings.iter().position(|(name, _)| *name == system) {
+            Some(index) => index,
+            None => {
+                self.timings.push((system, SystemTiming::default()));
+                self.timings.len() - 1
+            }
+        };
+        let timing = &mut self.timings[index].1;
+        timing.runs += 1;
+        timing.last = duration;
+        timing.total += duration;
+    }
+}
+
+impl SystemTiming {
+    #[inline]
+    pub fn runs(&self) -> u32 {
+        self.runs
+    }
+
+    #[inline]
+    pub fn last(&self) -> Duration {
+        self.last
+    }
+
+    #[inline]
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    pub fn average(&self) -> Duration {
+        self.total.checked_div(self.runs).unwrap_or_default()
+    }
+}
@@ -1,5 +1,12 @@
-use crate::components::{ComponentsMut, ComponentsRef};
+use crate::bitset::Bitset;
+use crate::components::{ComponentId, ComponentsImpl, ComponentsMut, ComponentsRef};
 use crate::entities::{Entities, Entity};
+use crate::lock::RwLock;
+use crate::{Chunk, Error};
+use alloc::vec::{self, Vec};
+use core::any::type_name;
+use core::cmp::Ordering;
+use core::marker::PhantomData;
 
 /// A trait useful for querying components from a collection.
 pub trait Query<'a> {
@@ -7,11 +14,257 @@ pub trait Query<'a> {
 
     fn iter(self) -> impl Iterator<Item = Option<Self::Item>>;
 
+    /// Returns the row of the chunk where `entity` is stored.
+    fn index_of(&self, entity: Entity) -> Option<usize>;
+
+    /// Returns the item of row `index`, the same as the `index`-th item of `iter`.
+    fn fetch(self, index: usize) -> Option<Self::Item>;
+
+    /// The rows that may yield an item, if known. Tuples intersect the presence of their
+    /// elements to step over the rows that one of them lacks without visiting the others.
+    fn presence(&self) -> Option<&Bitset> {
+        None
+    }
+
+    /// Returns the item of `entity`, looking up its row only once for all the elements of a
+    /// tuple, e.g. `(&positions, &velocities).get(entity)`.
+    fn get(self, entity: Entity) -> Option<Self::Item>
+    where
+        Self: Sized,
+    {
+        let index = self.index_of(entity)?;
+        self.fetch(index)
+    }
+
     fn query(self) -> impl Iterator<Item = Self::Item>
     where
         Self: Sized,
     {
-        self.iter().filter_map(|v| v)
+        self.iter().flatten()
+    }
+
+    /// Like `query`, yielding the items ordered by `key`, e.g. to draw sprites back to front. The
+    /// sort is stable.
+    fn sorted_by_key<K: Ord>(
+        self,
+        mut key: impl FnMut(&Self::Item) -> K,
+    ) -> vec::IntoIter<Self::Item>
+    where
+        Self: Sized,
+    {
+        self.sorted_by(|a, b| key(a).cmp(&key(b)))
+    }
+
+    /// Like `sorted_by_key` for keys that are not `Ord`, e.g.
+    /// `sorted_by(|a, b| a.y.total_cmp(&b.y))`.
+    fn sorted_by(
+        self,
+        compare: impl FnMut(&Self::Item, &Self::Item) -> Ordering,
+    ) -> vec::IntoIter<Self::Item>
+    where
+        Self: Sized,
+    {
+        let mut items = self.query().collect::<Vec<_>>();
+        items.sort_by(compare);
+        items.into_iter()
+    }
+
+    /// Yields every unordered combination of `K` distinct items, e.g. the pairs of colliders with
+    /// `(&entities, &colliders).iter_combinations::<2>()`. Needs items that can be copied, see
+    /// `for_each_pair_mut` for mutable ones.
+    fn iter_combinations<const K: usize>(self) -> impl Iterator<Item = [Self::Item; K]>
+    where
+        Self: Sized,
+        Self::Item: Clone,
+    {
+        let items = self.query().collect::<Vec<_>>();
+        let mut indexes = core::array::from_fn::<usize, K, _>(|i| i);
+        let mut done = K == 0 || K > items.len();
+        core::iter::from_fn(move || {
+            if done {
+                return None;
+            }
+            let combination = indexes.map(|i| items[i].clone());
+            done = !next_combination(&mut indexes, items.len());
+            Some(combination)
+        })
+    }
+
+    /// Runs `f` on every unordered pair of distinct items, which may borrow mutably.
+    fn for_each_pair_mut(self, mut f: impl FnMut(&mut Self::Item, &mut Self::Item))
+    where
+        Self: Sized,
+    {
+        let mut items = self.query().collect::<Vec<_>>();
+        for second in 1..items.len() {
+            let (before, after) = items.split_at_mut(second);
+            for first in before.iter_mut() {
+                f(first, &mut after[0]);
+            }
+        }
+    }
+
+    /// Runs `f` on every item, splitting the rows into one range per available core, each visited
+    /// by its own scoped thread. The items of queries over a sparse or custom storage column are
+    /// collected first, then spread over the threads.
+    #[cfg(feature = "std")]
+    fn par_for_each(self, f: impl Fn(Self::Item) + Sync)
+    where
+        Self: crate::parallel::ParQuery<'a>,
+        Self::Item: Send,
+    {
+        crate::parallel::par_for_each(self, f)
+    }
+
+    /// Returns the only item of the query, e.g. the player or the camera.
+    fn single(self) -> Result<Self::Item, Error>
+    where
+        Self: Sized,
+    {
+        let mut items = self.query();
+        let item = items
+            .next()
+            .ok_or(Error::QueryNoMatch(type_name::<Self::Item>()))?;
+        match items.next() {
+            Some(_) => Err(Error::QueryMultipleMatches(type_name::<Self::Item>())),
+            None => Ok(item),
+        }
+    }
+
+    /// Same as `single`, for queries over `&mut ComponentsMut`.
+    fn single_mut(self) -> Result<Self::Item, Error>
+    where
+        Self: Sized,
+    {
+        self.single()
+    }
+
+    /// Like `query`, with the entity each item belongs to.
+    fn query_with_entities(
+        self,
+        entities: &'a Entities<'_>,
+    ) -> impl Iterator<Item = (Entity, Self::Item)>
+    where
+        Self: Sized,
+    {
+        (entities, self).query()
+    }
+
+    /// Skips the rows for which `other` yields nothing, e.g. `positions.with(&players)`.
+    fn with<F: Query<'a>>(self, other: F) -> With<Self, F>
+    where
+        Self: Sized,
+    {
+        With(self, other)
+    }
+
+    /// Skips the rows for which `other` yields something.
+    fn without<F: Query<'a>>(self, other: F) -> Without<Self, F>
+    where
+        Self: Sized,
+    {
+        Without(self, other)
+    }
+
+    /// Skips the rows whose item does not satisfy `predicate`.
+    fn filter<P: FnMut(&Self::Item) -> bool>(self, predicate: P) -> Filter<Self, P>
+    where
+        Self: Sized,
+    {
+        Filter(self, predicate)
+    }
+}
+
+/// See `Query::with`.
+pub struct With<Q, F>(pub(crate) Q, pub(crate) F);
+
+impl<'a, Q, F> Query<'a> for With<Q, F>
+where
+    Q: Query<'a>,
+    F: Query<'a>,
+{
+    type Item = Q::Item;
+
+    fn iter(self) -> impl Iterator<Item = Option<Self::Item>> {
+        self.0
+            .iter()
+            .zip(self.1.iter())
+            .map(|(item, other)| item.filter(|_| other.is_some()))
+    }
+
+    fn index_of(&self, entity: Entity) -> Option<usize> {
+        self.0.index_of(entity)
+    }
+
+    fn fetch(self, index: usize) -> Option<Self::Item> {
+        self.1.fetch(index)?;
+        self.0.fetch(index)
+    }
+
+    fn presence(&self) -> Option<&Bitset> {
+        self.1.presence().or(self.0.presence())
+    }
+}
+
+/// See `Query::without`.
+pub struct Without<Q, F>(pub(crate) Q, pub(crate) F);
+
+impl<'a, Q, F> Query<'a> for Without<Q, F>
+where
+    Q: Query<'a>,
+    F: Query<'a>,
+{
+    type Item = Q::Item;
+
+    fn iter(self) -> impl Iterator<Item = Option<Self::Item>> {
+        self.0
+            .iter()
+            .zip(self.1.iter())
+            .map(|(item, other)| item.filter(|_| other.is_none()))
+    }
+
+    fn index_of(&self, entity: Entity) -> Option<usize> {
+        self.0.index_of(entity)
+    }
+
+    fn fetch(self, index: usize) -> Option<Self::Item> {
+        match self.1.fetch(index) {
+            Some(_) => None,
+            None => self.0.fetch(index),
+        }
+    }
+
+    fn presence(&self) -> Option<&Bitset> {
+        self.0.presence()
+    }
+}
+
+/// See `Query::filter`.
+pub struct Filter<Q, P>(pub(crate) Q, pub(crate) P);
+
+impl<'a, Q, P> Query<'a> for Filter<Q, P>
+where
+    Q: Query<'a>,
+    P: FnMut(&Q::Item) -> bool,
+{
+    type Item = Q::Item;
+
+    fn iter(self) -> impl Iterator<Item = Option<Self::Item>> {
+        let Filter(query, mut predicate) = self;
+        query.iter().map(move |item| item.filter(&mut predicate))
+    }
+
+    fn index_of(&self, entity: Entity) -> Option<usize> {
+        self.0.index_of(entity)
+    }
+
+    fn fetch(self, index: usize) -> Option<Self::Item> {
+        let Filter(query, mut predicate) = self;
+        query.fetch(index).filter(&mut predicate)
+    }
+
+    fn presence(&self) -> Option<&Bitset> {
+        self.0.presence()
     }
 }
 
@@ -21,6 +274,14 @@ impl<'a> Query<'a> for &'a Entities<'_> {
     fn iter(self) -> impl Iterator<Item = Option<Self::Item>> {
         self.0.iter().map(Some)
     }
+
+    fn index_of(&self, entity: Entity) -> Option<usize> {
+        self.0.index(entity)
+    }
+
+    fn fetch(self, index: usize) -> Option<Self::Item> {
+        self.0.at(index)
+    }
 }
 
 impl<'a, T> Query<'a> for &'a ComponentsRef<'_, T> {
@@ -29,6 +290,18 @@ impl<'a, T> Query<'a> for &'a ComponentsRef<'_, T> {
     fn iter(self) -> impl Iterator<Item = Option<Self::Item>> {
         self.values.iter()
     }
+
+    fn index_of(&self, entity: Entity) -> Option<usize> {
+        self.entities.index(entity)
+    }
+
+    fn fetch(self, index: usize) -> Option<Self::Item> {
+        self.values.get(index)
+    }
+
+    fn presence(&self) -> Option<&Bitset> {
+        Some(self.values.present())
+    }
 }
 
 impl<'a, T> Query<'a> for &'a ComponentsMut<'_, T> {
@@ -37,6 +310,18 @@ impl<'a, T> Query<'a> for &'a ComponentsMut<'_, T> {
     fn iter(self) -> impl Iterator<Item = Option<Self::Item>> {
         self.values.iter()
     }
+
+    fn index_of(&self, entity: Entity) -> Option<usize> {
+        self.entities.index(entity)
+    }
+
+    fn fetch(self, index: usize) -> Option<Self::Item> {
+        self.values.get(index)
+    }
+
+    fn presence(&self) -> Option<&Bitset> {
+        Some(self.values.present())
+    }
 }
 
 impl<'a, T> Query<'a> for &'a mut ComponentsMut<'_, T> {
@@ -45,17 +330,256 @@ impl<'a, T> Query<'a> for &'a mut ComponentsMut<'_, T> {
     fn iter(self) -> impl Iterator<Item = Option<Self::Item>> {
         self.values.iter_mut()
     }
+
+    fn index_of(&self, entity: Entity) -> Option<usize> {
+        self.entities.index(entity)
+    }
+
+    fn fetch(self, index: usize) -> Option<Self::Item> {
+        self.values.get_mut(index)
+    }
+
+    fn presence(&self) -> Option<&Bitset> {
+        Some(self.values.present())
+    }
 }
 
-impl<'a, A, B> Query<'a> for (A, B)
+/// Yields `None` for the rows the wrapped query would skip, instead of skipping them,
+/// e.g. `(&positions, Maybe(&sprites)).query()`.
+pub struct Maybe<Q>(pub Q);
+
+impl<'a, Q> Query<'a> for Maybe<Q>
 where
-    A: Query<'a>,
-    B: Query<'a>,
+    Q: Query<'a>,
 {
-    type Item = (A::Item, B::Item);
+    type Item = Option<Q::Item>;
 
     fn iter(self) -> impl Iterator<Item = Option<Self::Item>> {
-        let (a, b) = self;
-        A::iter(a).zip(B::iter(b)).map(|(a, b)| a.zip(b))
+        self.0.iter().map(Some)
+    }
+
+    fn index_of(&self, entity: Entity) -> Option<usize> {
+        self.0.index_of(entity)
+    }
+
+    fn fetch(self, index: usize) -> Option<Self::Item> {
+        Some(self.0.fetch(index))
+    }
+}
+
+/// Advances `indexes`, increasing indexes into `len` items, to the next combination in
+/// lexicographic order. Returns false if it was the last one.
+fn next_combination<const K: usize>(indexes: &mut [usize; K], len: usize) -> bool {
+    let Some(i) = (0..K).rev().find(|i| indexes[*i] < len - K + i) else {
+        return false;
+    };
+    indexes[i] += 1;
+    for j in i + 1..K {
+        indexes[j] = indexes[j - 1] + 1;
+    }
+    true
+}
+
+/// The rows present in every known presence, or `None` if none is known.
+fn intersect<const N: usize>(presences: [Option<&Bitset>; N]) -> Option<Bitset> {
+    let mut known = presences.into_iter().flatten();
+    let mut presence = known.next()?.clone();
+    for other in known {
+        presence.intersect(other);
+    }
+    Some(presence)
+}
+
+macro_rules! impl_query_for_tuple {
+    ( $(($Q:ident, $q:ident)),+ ) => {
+        impl<'a, $($Q),+> Query<'a> for ($($Q,)+)
+        where
+            $($Q: Query<'a>,)+
+        {
+            type Item = ($($Q::Item,)+);
+
+            fn iter(self) -> impl Iterator<Item = Option<Self::Item>> {
+                let ($($q,)+) = self;
+                let presence = intersect([$($q.presence()),+]);
+                let ($(mut $q,)+) = ($($Q::iter($q),)+);
+                let mut row = 0;
+                let mut skipped = 0;
+                core::iter::from_fn(move || {
+                    if let Some(presence) = &presence {
+                        if row >= presence.len() {
+                            return None;
+                        }
+                        row += 1;
+                        if !presence.contains(row - 1) {
+                            skipped += 1;
+                            return Some(None);
+                        }
+                    }
+                    let skip = core::mem::take(&mut skipped);
+                    // every iterator is advanced before checking the row, to keep them aligned
+                    let ($($q,)+) = ($($q.nth(skip)?,)+);
+                    Some((|| Some(($($q?,)+)))())
+                })
+            }
+
+            fn index_of(&self, entity: Entity) -> Option<usize> {
+                // every element belongs to the same chunk, so any of them can answer
+                let ($($q,)+) = self;
+                None$(.or_else(|| $q.index_of(entity)))+
+            }
+
+            fn fetch(self, index: usize) -> Option<Self::Item> {
+                let ($($q,)+) = self;
+                let ($($q,)+) = ($($Q::fetch($q, index),)+);
+                Some(($($q?,)+))
+            }
+
+            fn presence(&self) -> Option<&Bitset> {
+                let ($($q,)+) = self;
+                None$(.or_else(|| $q.presence()))+
+            }
+        }
+    };
+}
+
+impl_query_for_tuple!((A, a), (B, b));
+impl_query_for_tuple!((A, a), (B, b), (C, c));
+impl_query_for_tuple!((A, a), (B, b), (C, c), (D, d));
+impl_query_for_tuple!((A, a), (B, b), (C, c), (D, d), (E, e));
+impl_query_for_tuple!((A, a), (B, b), (C, c), (D, d), (E, e), (F, f));
+impl_query_for_tuple!((A, a), (B, b), (C, c), (D, d), (E, e), (F, f), (G, g));
+impl_query_for_tuple!(
+    (A, a),
+    (B, b),
+    (C, c),
+    (D, d),
+    (E, e),
+    (F, f),
+    (G, g),
+    (H, h)
+);
+
+/// Remembers the `ComponentId` of `T` in a chunk, so that borrowing it every frame skips the
+/// `TypeId` lookup. The lookup is done again when used with a chunk storing `T` elsewhere.
+pub struct QueryState<T> {
+    column: Option<ComponentId>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: 'static> QueryState<T> {
+    pub fn new() -> Self {
+        Self {
+            column: None,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn get<'a>(&mut self, chunk: &'a Chunk) -> Result<ComponentsRef<'a, T>, Error> {
+        let values = self
+            .components_rwlock(chunk)?
+            .try_read()
+            .ok_or(Error::ComponentAlreadyBorrowedMutably(type_name::<T>()))?;
+        Ok(ComponentsRef {
+            entities: &chunk.entities,
+            values,
+        })
+    }
+
+    pub fn get_mut<'a>(&mut self, chunk: &'a Chunk) -> Result<ComponentsMut<'a, T>, Error> {
+        let values = self
+            .components_rwlock(chunk)?
+            .try_write()
+            .ok_or(Error::ComponentAlreadyBorrowedMutably(type_name::<T>()))?;
+        Ok(ComponentsMut {
+            entities: &chunk.entities,
+            values,
+        })
+    }
+
+    fn components_rwlock<'a>(
+        &mut self,
+        chunk: &'a Chunk,
+    ) -> Result<&'a RwLock<ComponentsImpl<T>>, Error> {
+        if let Some(values) = self
+            .column
+            .and_then(|column| chunk.components.components_rwlock_at::<T>(column))
+        {
+            return Ok(values);
+        }
+        let column = chunk.components.component_id::<T>()?;
+        self.column = Some(column);
+        chunk
+            .components
+            .components_rwlock_at::<T>(column)
+            .ok_or(Error::InternalStorageError(type_name::<T>()))
+    }
+}
+
+impl<T: 'static> Default for QueryState<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChunkBuilder;
+
+    struct Position(i32);
+
+    struct Velocity(i32);
+
+    struct Frozen;
+
+    // rows 0 and 3 have both components, the others only one of them
+    fn chunk() -> (Chunk, [Entity; 4]) {
+        let mut chunk = ChunkBuilder::default()
+            .with_component::<Position>()
+            .with_component::<Velocity>()
+            .with_component::<Frozen>()
+            .build();
+        let entities = [(); 4].map(|_| chunk.spawn().unwrap());
+        for (i, entity) in entities.into_iter().enumerate() {
+            let i = i as i32;
+            if i != 1 {
+                chunk.add_component(entity, Position(i)).unwrap();
+            }
+            if i != 2 {
+                chunk.add_component(entity, Velocity(10 * i)).unwrap();
+            }
+        }
+        chunk.add_component(entities[3], Frozen).unwrap();
+        (chunk, entities)
+    }
+
+    #[test]
+    fn tuples_yield_the_rows_holding_every_element() {
+        let (chunk, entities) = chunk();
+        let mut positions = chunk.components_mut::<Position>().unwrap();
+        let velocities = chunk.components_ref::<Velocity>().unwrap();
+        for (position, velocity) in (&mut positions, &velocities).query() {
+            position.0 += velocity.0;
+        }
+        let moved = (&positions).query().map(|p| p.0).collect::<Vec<_>>();
+        assert_eq!(moved, [0, 2, 33]);
+        let pair = (&velocities, Maybe(&positions)).get(entities[1]);
+        assert!(matches!(pair, Some((Velocity(10), None))));
+        assert!((&positions, &velocities).get(entities[2]).is_none());
+    }
+
+    #[test]
+    fn tuples_of_eight_elements_are_queries() {
+        let (chunk, entities) = chunk();
+        let positions = chunk.components_ref::<Position>().unwrap();
+        let velocities = chunk.components_ref::<Velocity>().unwrap();
+        let frozen = chunk.components_ref::<Frozen>().unwrap();
+        let all = Entities(&chunk.entities);
+        let (p, v) = (&positions, &velocities);
+        let items = (p, v, p, v, p, v, p, &frozen).query_with_entities(&all);
+        let rows = items
+            .map(|(entity, (p, ..))| (entity, p.0))
+            .collect::<Vec<_>>();
+        assert_eq!(rows, [(entities[3], 3)]);
     }
 }
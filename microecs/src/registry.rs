@@ -0,0 +1,147 @@
+use crate::entities::Entity;
+use crate::{Chunk, Error};
+use alloc::{boxed::Box, string::ToString, vec::Vec};
+use core::any::{type_name, Any, TypeId};
+use hashbrown::HashMap;
+
+/// Maps stable names to component types, so that save files and tools can refer to components
+/// independently of their Rust type names, which may change from one build to another.
+#[derive(Default)]
+pub struct ComponentRegistry {
+    infos: Vec<ComponentInfo>,
+    by_name: HashMap<&'static str, usize>,
+    names: HashMap<TypeId, &'static str>,
+}
+
+/// The type-erased operations of a component type registered in a `ComponentRegistry`.
+#[derive(Clone, Copy, Debug)]
+pub struct ComponentInfo {
+    name: &'static str,
+    type_id: TypeId,
+    type_name: &'static str,
+    register: fn(&mut Chunk),
+    insert: InsertFn,
+    remove: fn(&mut Chunk, Entity) -> Result<(), Error>,
+}
+
+type InsertFn = fn(&mut Chunk, Entity, Box<dyn Any>) -> Result<(), Error>;
+
+impl ComponentRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `T` under `name`, replacing whatever was registered under that name before.
+    pub fn register<T: 'static>(&mut self, name: &'static str) -> &mut Self {
+        if let Some(previous) = self.names.get(&TypeId::of::<T>()).copied() {
+            self.unregister(previous);
+        }
+        self.unregister(name);
+        self.by_name.insert(name, self.infos.len());
+        self.names.insert(TypeId::of::<T>(), name);
+        self.infos.push(ComponentInfo::new::<T>(name));
+        self
+    }
+
+    #[inline]
+    pub fn get(&self, name: &str) -> Option<&ComponentInfo> {
+        self.by_name.get(name).map(|index| &self.infos[*index])
+    }
+
+    #[inline]
+    pub fn name_of<T: 'static>(&self) -> Option<&'static str> {
+        self.names.get(&TypeId::of::<T>()).copied()
+    }
+
+    /// The registered component types, in registration order.
+    pub fn iter(&self) -> impl Iterator<Item = &ComponentInfo> + use<'_> {
+        self.infos.iter()
+    }
+
+    /// Inserts `value`, which must be of the type registered under `name`, into `entity`.
+    pub fn insert(
+        &self,
+        chunk: &mut Chunk,
+        entity: Entity,
+        name: &str,
+        value: Box<dyn Any>,
+    ) -> Result<(), Error> {
+        self.info(name)?.insert(chunk, entity, value)
+    }
+
+    pub fn remove(&self, chunk: &mut Chunk, entity: Entity, name: &str) -> Result<(), Error> {
+        self.info(name)?.remove(chunk, entity)
+    }
+
+    fn unregister(&mut self, name: &str) {
+        let Some(index) = self.by_name.remove(name) else {
+            return;
+        };
+        let info = self.infos.remove(index);
+        self.names.remove(&info.type_id);
+        for index in self.by_name.values_mut().filter(|i| **i > index) {
+            *index -= 1;
+        }
+    }
+
+    fn info(&self, name: &str) -> Result<&ComponentInfo, Error> {
+        self.get(name)
+            .ok_or_else(|| Error::UnknownComponentName(name.to_string()))
+    }
+}
+
+impl ComponentInfo {
+    fn new<T: 'static>(name: &'static str) -> Self {
+        Self {
+            name,
+            type_id: TypeId::of::<T>(),
+            type_name: type_name::<T>(),
+            register: Chunk::register_component::<T>,
+            insert: insert::<T>,
+            remove: Chunk::remove_component::<T>,
+        }
+    }
+
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    #[inline]
+    pub fn type_id(&self) -> TypeId {
+        self.type_id
+    }
+
+    #[inline]
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// Registers the component on `chunk`, see `Chunk::register_component`.
+    #[inline]
+    pub fn register_on(&self, chunk: &mut Chunk) {
+        (self.register)(chunk)
+    }
+
+    #[inline]
+    pub fn insert(
+        &self,
+        chunk: &mut Chunk,
+        entity: Entity,
+        value: Box<dyn Any>,
+    ) -> Result<(), Error> {
+        (self.insert)(chunk, entity, value)
+    }
+
+    #[inline]
+    pub fn remove(&self, chunk: &mut Chunk, entity: Entity) -> Result<(), Error> {
+        (self.remove)(chunk, entity)
+    }
+}
+
+fn insert<T: 'static>(chunk: &mut Chunk, entity: Entity, value: Box<dyn Any>) -> Result<(), Error> {
+    let value = value
+        .downcast::<T>()
+        .map_err(|_| Error::ComponentTypeMismatch(type_name::<T>()))?;
+    chunk.add_component(entity, *value)
+}
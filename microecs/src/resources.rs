@@ -1,40 +1,186 @@
 use core::any::{type_name, Any, TypeId};
 
+use crate::lock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 use alloc::boxed::Box;
 use hashbrown::HashMap;
-use spin::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 use crate::Error;
 
+/// A type that can be stored in `Resources`. With the `sync` feature resources must be
+/// `Send + Sync`, which makes `Resources` itself shareable across threads.
+#[cfg(feature = "sync")]
+pub trait Resource: Send + Sync + 'static {}
+
+#[cfg(feature = "sync")]
+impl<T: Send + Sync + 'static> Resource for T {}
+
+/// A type that can be stored in `Resources`. With the `sync` feature resources must be
+/// `Send + Sync`, which makes `Resources` itself shareable across threads.
+#[cfg(not(feature = "sync"))]
+pub trait Resource: 'static {}
+
+#[cfg(not(feature = "sync"))]
+impl<T: 'static> Resource for T {}
+
+#[cfg(feature = "sync")]
+type BoxedResource = Box<dyn Any + Send + Sync>;
+
+#[cfg(not(feature = "sync"))]
+type BoxedResource = Box<dyn Any>;
+
 #[derive(Default)]
 pub struct Resources {
+    values: HashMap<TypeId, BoxedResource>,
+    non_send: NonSendResources,
+}
+
+/// Values that must stay on the thread that added them, like window or GPU context handles.
+#[derive(Default)]
+struct NonSendResources {
     values: HashMap<TypeId, Box<dyn Any>>,
+    #[cfg(feature = "sync")]
+    owner: Option<std::thread::ThreadId>,
+}
+
+// SAFETY: the values are only handed out on the owner thread, see `NonSendResources::check_thread`,
+// and are leaked rather than dropped anywhere else.
+#[cfg(feature = "sync")]
+unsafe impl Send for NonSendResources {}
+
+#[cfg(feature = "sync")]
+unsafe impl Sync for NonSendResources {}
+
+impl NonSendResources {
+    fn values<T>(&self) -> Result<&HashMap<TypeId, Box<dyn Any>>, Error> {
+        self.check_thread(type_name::<T>())?;
+        Ok(&self.values)
+    }
+
+    fn values_mut<T>(&mut self) -> Result<&mut HashMap<TypeId, Box<dyn Any>>, Error> {
+        self.check_thread(type_name::<T>())?;
+        #[cfg(feature = "sync")]
+        self.owner
+            .get_or_insert_with(|| std::thread::current().id());
+        Ok(&mut self.values)
+    }
+
+    #[cfg(feature = "sync")]
+    fn check_thread(&self, type_name: &'static str) -> Result<(), Error> {
+        match self.owner {
+            Some(owner) if owner != std::thread::current().id() => {
+                Err(Error::NonSendAccessedFromOtherThread(type_name))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    #[cfg(not(feature = "sync"))]
+    fn check_thread(&self, _type_name: &'static str) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sync")]
+impl Drop for NonSendResources {
+    fn drop(&mut self) {
+        if self.check_thread("").is_err() {
+            core::mem::forget(core::mem::take(&mut self.values));
+        }
+    }
 }
 
 impl Resources {
-    pub fn add_resource<T: 'static>(&mut self, value: T) {
+    pub fn add_resource<T: Resource>(&mut self, value: T) {
         self.values
             .insert(TypeId::of::<T>(), Box::new(RwLock::new(value)));
     }
 
+    /// Adds `T::default()` unless a `T` is already present.
+    pub fn init_resource<T: Resource + Default>(&mut self) {
+        self.get_or_insert_with(T::default);
+    }
+
+    /// Returns the `T`, adding the result of `f` first if there is none.
+    pub fn get_or_insert_with<T: Resource>(&mut self, f: impl FnOnce() -> T) -> &mut T {
+        self.values
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(RwLock::new(f())))
+            .downcast_mut::<RwLock<T>>()
+            .unwrap()
+            .get_mut()
+    }
+
+    /// Takes `T` out for the duration of `f`, so that `f` can mutate it while accessing the
+    /// other resources. `T` is put back afterwards, replacing any `T` added by `f`.
+    pub fn resource_scope<T: Resource, R>(
+        &mut self,
+        f: impl FnOnce(&mut Resources, &mut T) -> R,
+    ) -> Result<R, Error> {
+        let mut value = self
+            .values
+            .remove(&TypeId::of::<T>())
+            .ok_or(Error::ResourceNotFound(type_name::<T>()))?
+            .downcast::<RwLock<T>>()
+            .map_err(|_| Error::CorruptedResource(type_name::<T>()))?;
+        let result = f(self, value.get_mut());
+        self.values.insert(TypeId::of::<T>(), value);
+        Ok(result)
+    }
+
     pub fn remove_resource<T: 'static>(&mut self) {
         self.values.remove(&TypeId::of::<T>());
     }
 
-    pub fn resource_ref<T: 'static>(&self) -> Result<ResourceRef<T>, Error> {
+    pub fn resource_ref<T: 'static>(&self) -> Result<ResourceRef<'_, T>, Error> {
         self.resource_rw_lock::<T>()?
             .try_read()
             .ok_or(Error::ResourceAlreadyBorrowedMutably(type_name::<T>()))
             .map(ResourceRef)
     }
 
-    pub fn resource_mut<T: 'static>(&self) -> Result<ResourceMut<T>, Error> {
+    pub fn resource_mut<T: 'static>(&self) -> Result<ResourceMut<'_, T>, Error> {
         self.resource_rw_lock::<T>()?
             .try_write()
             .ok_or(Error::ResourceAlreadyBorrowedMutably(type_name::<T>()))
             .map(ResourceMut)
     }
 
+    /// Adds a resource that is only accessible from the thread that added the first one.
+    pub fn add_non_send<T: 'static>(&mut self, value: T) -> Result<(), Error> {
+        self.non_send
+            .values_mut::<T>()?
+            .insert(TypeId::of::<T>(), Box::new(RwLock::new(value)));
+        Ok(())
+    }
+
+    pub fn remove_non_send<T: 'static>(&mut self) -> Result<(), Error> {
+        self.non_send.values_mut::<T>()?.remove(&TypeId::of::<T>());
+        Ok(())
+    }
+
+    pub fn non_send_ref<T: 'static>(&self) -> Result<NonSend<'_, T>, Error> {
+        self.non_send_rw_lock::<T>()?
+            .try_read()
+            .ok_or(Error::ResourceAlreadyBorrowedMutably(type_name::<T>()))
+            .map(NonSend)
+    }
+
+    pub fn non_send_mut<T: 'static>(&self) -> Result<NonSendMut<'_, T>, Error> {
+        self.non_send_rw_lock::<T>()?
+            .try_write()
+            .ok_or(Error::ResourceAlreadyBorrowedMutably(type_name::<T>()))
+            .map(NonSendMut)
+    }
+
+    fn non_send_rw_lock<T: 'static>(&self) -> Result<&RwLock<T>, Error> {
+        self.non_send
+            .values::<T>()?
+            .get(&TypeId::of::<T>())
+            .ok_or(Error::ResourceNotFound(type_name::<T>()))?
+            .downcast_ref::<RwLock<T>>()
+            .ok_or(Error::CorruptedResource(type_name::<T>()))
+    }
+
     pub(crate) fn resource_rw_lock<T: 'static>(&self) -> Result<&RwLock<T>, Error> {
         self.values
             .get(&TypeId::of::<T>())
@@ -48,6 +194,10 @@ pub struct ResourceRef<'a, T>(pub(crate) RwLockReadGuard<'a, T>);
 
 pub struct ResourceMut<'a, T>(pub(crate) RwLockWriteGuard<'a, T>);
 
+pub struct NonSend<'a, T>(RwLockReadGuard<'a, T>);
+
+pub struct NonSendMut<'a, T>(RwLockWriteGuard<'a, T>);
+
 pub struct ItemRef<'a, T>(pub(crate) RwLockReadGuard<'a, T>);
 
 pub struct ItemMut<'a, T>(pub(crate) RwLockWriteGuard<'a, T>);
@@ -76,6 +226,22 @@ impl<'a, T> ResourceMut<'a, T> {
     }
 }
 
+impl<T> NonSend<'_, T> {
+    pub fn get(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> NonSendMut<'_, T> {
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+
+    pub fn get(&self) -> &T {
+        &self.0
+    }
+}
+
 impl<'a, T> ItemRef<'a, T> {
     pub fn get(&self) -> &T {
         &self.0
@@ -98,12 +264,66 @@ pub struct ResourcesBuilder {
 }
 
 impl ResourcesBuilder {
-    pub fn with_resource<T: 'static>(mut self, value: T) -> Self {
+    pub fn with_resource<T: Resource>(mut self, value: T) -> Self {
         self.resources.add_resource(value);
         self
     }
 
+    pub fn init_resource<T: Resource + Default>(mut self) -> Self {
+        self.resources.init_resource::<T>();
+        self
+    }
+
     pub fn build(self) -> Resources {
         self.resources
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::rc::Rc;
+
+    #[test]
+    fn non_send_values_are_borrowed_like_resources() {
+        let mut resources = Resources::default();
+        resources.add_non_send(Rc::new(1)).unwrap();
+        let first = resources.non_send_ref::<Rc<i32>>().unwrap();
+        let second = resources.non_send_ref::<Rc<i32>>().unwrap();
+        assert_eq!(**first.get() + **second.get(), 2);
+        assert!(matches!(
+            resources.non_send_mut::<Rc<i32>>(),
+            Err(Error::ResourceAlreadyBorrowedMutably(_))
+        ));
+        drop((first, second));
+        *resources.non_send_mut::<Rc<i32>>().unwrap().get_mut() = Rc::new(2);
+        assert_eq!(**resources.non_send_ref::<Rc<i32>>().unwrap().get(), 2);
+        resources.remove_non_send::<Rc<i32>>().unwrap();
+        assert!(matches!(
+            resources.non_send_ref::<Rc<i32>>(),
+            Err(Error::ResourceNotFound(_))
+        ));
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn non_send_values_stay_on_their_thread() {
+        let mut resources = Resources::default();
+        resources.add_non_send(Rc::new(1)).unwrap();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                assert!(matches!(
+                    resources.non_send_ref::<Rc<i32>>(),
+                    Err(Error::NonSendAccessedFromOtherThread(_))
+                ));
+            });
+        });
+        assert!(resources.non_send_ref::<Rc<i32>>().is_ok());
+
+        // moved to another thread, the values are leaked instead of dropped there
+        let dropped = Rc::new(());
+        resources.add_non_send(dropped.clone()).unwrap();
+        std::thread::spawn(move || drop(resources)).join().unwrap();
+        assert_eq!(Rc::strong_count(&dropped), 2);
+    }
+}
@@ -0,0 +1,866 @@
+use crate::access::Access;
+#[cfg(all(
+    feature = "std",
+    any(not(feature = "single-threaded"), feature = "sync")
+))]
+use crate::executor::{ParScheduled, ParScheduledSystem, ParSystem};
+use crate::systems::{System, SystemOutput, SystemsContext};
+use crate::{Chunk, Error, Resources};
+use alloc::{boxed::Box, collections::BTreeSet, vec, vec::Vec};
+use core::any::{type_name, TypeId};
+use hashbrown::HashMap;
+#[cfg(all(
+    feature = "std",
+    any(not(feature = "single-threaded"), feature = "sync")
+))]
+use std::{panic::resume_unwind, thread};
+
+type BoxedSystem = Box<dyn for<'a> FnMut(&mut SystemsContext<'a>) -> Result<(), Error>>;
+
+type BoxedCondition = Box<dyn for<'a> FnMut(&mut SystemsContext<'a>) -> Result<bool, Error>>;
+
+/// Systems run in an order derived from the constraints between their sets, systems without
+/// constraints keeping the order in which they were added.
+///
+/// Sets are plain marker types, e.g. `struct PhysicsSet;`, configured once through
+/// `configure_set` and joined through `SystemConfig::in_set`.
+#[derive(Default)]
+pub struct Schedule {
+    systems: Vec<ScheduledSystem>,
+    sets: HashMap<TypeId, ScheduledSet>,
+    // the order in which to run the systems, computed on the first run after a change
+    order: Option<Vec<usize>>,
+    // the same order cut into the stages of `run_par`
+    #[cfg(all(
+        feature = "std",
+        any(not(feature = "single-threaded"), feature = "sync")
+    ))]
+    stages: Option<Vec<Vec<usize>>>,
+}
+
+struct ScheduledSystem {
+    name: &'static str,
+    system: SystemKind,
+    access: Access,
+    conditions: Vec<BoxedCondition>,
+    sets: Vec<(TypeId, &'static str)>,
+    constraints: Constraints,
+}
+
+struct ScheduledSet {
+    conditions: Vec<BoxedCondition>,
+    constraints: Constraints,
+}
+
+enum SystemKind {
+    /// Runs alone on the calling thread.
+    Exclusive(BoxedSystem),
+    #[cfg(all(
+        feature = "std",
+        any(not(feature = "single-threaded"), feature = "sync")
+    ))]
+    Parallel(Box<dyn ParScheduled>),
+}
+
+#[derive(Default)]
+struct Constraints {
+    before: Vec<TypeId>,
+    after: Vec<TypeId>,
+}
+
+/// Configures the system just added to a `Schedule`.
+pub struct SystemConfig<'a>(&'a mut ScheduledSystem);
+
+/// Configures every system of a set, see `Schedule::configure_set`.
+pub struct SetConfig<'a>(&'a mut ScheduledSet);
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_system<F, P>(&mut self, mut system_function: F) -> SystemConfig<'_>
+    where
+        F: System<P, Input = ()> + 'static,
+        F::Output: SystemOutput,
+        P: 'static,
+    {
+        let mut state = F::State::default();
+        let system = Box::new(move |context: &mut SystemsContext| {
+            context.run_system(&mut system_function, &mut state)
+        });
+        self.push::<F, P>(SystemKind::Exclusive(system))
+    }
+
+    /// Adds a system that `run_par` runs on a worker thread, alongside the systems it does not
+    /// conflict with. `run` runs it like any other system.
+    #[cfg(all(
+        feature = "std",
+        any(not(feature = "single-threaded"), feature = "sync")
+    ))]
+    pub fn add_par_system<F, P>(&mut self, system_function: F) -> SystemConfig<'_>
+    where
+        F: ParSystem<P, Input = ()> + Send + 'static,
+        F::Output: SystemOutput,
+        P: 'static,
+    {
+        let system = Box::new(ParScheduledSystem::new(system_function));
+        self.push::<F, P>(SystemKind::Parallel(system))
+    }
+
+    fn push<F: System<P>, P>(&mut self, system: SystemKind) -> SystemConfig<'_> {
+        self.changed();
+        let mut access = Access::default();
+        F::access(&mut access);
+        self.systems.push(ScheduledSystem {
+            name: type_name::<F>(),
+            system,
+            access,
+            conditions: Vec::new(),
+            sets: Vec::new(),
+            constraints: Constraints::default(),
+        });
+        SystemConfig(self.systems.last_mut().unwrap())
+    }
+
+    /// Configures the set `S`, whose constraints and run conditions apply to all of its systems.
+    pub fn configure_set<S: 'static>(&mut self) -> SetConfig<'_> {
+        self.changed();
+        SetConfig(self.set_mut::<S>())
+    }
+
+    fn changed(&mut self) {
+        self.order = None;
+        #[cfg(all(
+            feature = "std",
+            any(not(feature = "single-threaded"), feature = "sync")
+        ))]
+        {
+            self.stages = None;
+        }
+    }
+
+    /// Runs every system whose run conditions, and those of its sets, return true, recording
+    /// their commands into the chunk's own queue. The conditions of a set are checked once per
+    /// run, right before its first system. Stops at the first failing system, whose commands are
+    /// dropped.
+    pub fn run(&mut self, chunk: &mut Chunk, resources: &mut Resources) -> Result<(), Error> {
+        let order = match self.order.take() {
+            Some(order) => order,
+            None => self.sort()?,
+        };
+        let result = self.run_in_order(&order, &mut chunk.with(resources));
+        self.order = Some(order);
+        result
+    }
+
+    fn run_in_order(&mut self, order: &[usize], context: &mut SystemsContext) -> Result<(), Error> {
+        let mut set_conditions = HashMap::<TypeId, bool>::new();
+        for &index in order {
+            if self.should_run(index, &mut set_conditions, context)? {
+                self.systems[index].run(context)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the run conditions of the system at `index` and of its sets, those of the sets
+    /// being checked only once per run.
+    fn should_run(
+        &mut self,
+        index: usize,
+        set_conditions: &mut HashMap<TypeId, bool>,
+        context: &mut SystemsContext,
+    ) -> Result<bool, Error> {
+        let system = &mut self.systems[index];
+        let mut should_run = true;
+        for (set, _) in system.sets.iter() {
+            let passes = match set_conditions.get(set) {
+                Some(passes) => *passes,
+                None => {
+                    let passes = match self.sets.get_mut(set) {
+                        Some(set) => check_all(&mut set.conditions, context)?,
+                        None => true,
+                    };
+                    set_conditions.insert(*set, passes);
+                    passes
+                }
+            };
+            should_run &= passes;
+        }
+        Ok(should_run && check_all(&mut system.conditions, context)?)
+    }
+
+    /// Runs the systems like `run`, except that the systems added through `add_par_system` run on
+    /// worker threads, together with those next to them in the order that they neither conflict
+    /// with nor are ordered relative to. Every other system is a barrier, running alone on the
+    /// calling thread.
+    ///
+    /// The params of the systems running together are fetched on the calling thread, which fails
+    /// with `Error::ComponentStorageNotShareable` for columns with a custom storage. Their commands
+    /// are flushed once all of them returned, in the order of the schedule. The run conditions of
+    /// all the systems running together are checked before any of them runs, and `SystemTimings`
+    /// only records the systems run on the calling thread.
+    #[cfg(all(
+        feature = "std",
+        any(not(feature = "single-threaded"), feature = "sync")
+    ))]
+    pub fn run_par(&mut self, chunk: &mut Chunk, resources: &mut Resources) -> Result<(), Error> {
+        let stages = match self.stages.take() {
+            Some(stages) => stages,
+            None => self.stages(&self.sort()?),
+        };
+        let result = self.run_stages(&stages, &mut chunk.with(resources));
+        self.stages = Some(stages);
+        result
+    }
+
+    /// Cuts `order` into the systems `run_par` runs together.
+    #[cfg(all(
+        feature = "std",
+        any(not(feature = "single-threaded"), feature = "sync")
+    ))]
+    fn stages(&self, order: &[usize]) -> Vec<Vec<usize>> {
+        let edges = self.edges();
+        let reachable = (0..self.systems.len())
+            .map(|from| reachable_from(&edges, from))
+            .collect::<Vec<_>>();
+        let mut stages = Vec::<Vec<usize>>::new();
+        for &index in order {
+            let system = &self.systems[index];
+            let joins = system.is_parallel()
+                && stages.last().is_some_and(|stage| {
+                    stage.iter().all(|&other| {
+                        let other_system = &self.systems[other];
+                        other_system.is_parallel()
+                            && !reachable[other][index]
+                            && system.access.conflict_with(&other_system.access).is_none()
+                    })
+                });
+            match stages.last_mut() {
+                Some(stage) if joins => stage.push(index),
+                _ => stages.push(vec![index]),
+            }
+        }
+        stages
+    }
+
+    #[cfg(all(
+        feature = "std",
+        any(not(feature = "single-threaded"), feature = "sync")
+    ))]
+    fn run_stages(
+        &mut self,
+        stages: &[Vec<usize>],
+        context: &mut SystemsContext,
+    ) -> Result<(), Error> {
+        let mut set_conditions = HashMap::<TypeId, bool>::new();
+        let mut running = Vec::new();
+        for stage in stages {
+            running.clear();
+            for &index in stage {
+                if self.should_run(index, &mut set_conditions, context)? {
+                    running.push(index);
+                }
+            }
+            match running[..] {
+                [] => {}
+                [index] => self.systems[index].run(context)?,
+                _ => self.run_stage(&running, context)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the parallel systems of `stage` at once, stopping like `run` at the first failing
+    /// system, whose commands and those of the systems after it are dropped.
+    #[cfg(all(
+        feature = "std",
+        any(not(feature = "single-threaded"), feature = "sync")
+    ))]
+    fn run_stage(&mut self, stage: &[usize], context: &mut SystemsContext) -> Result<(), Error> {
+        let mut systems = self
+            .systems
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, system)| {
+                let position = stage.iter().position(|&other| other == index)?;
+                match &mut system.system {
+                    SystemKind::Parallel(parallel) => Some((position, system.name, parallel)),
+                    SystemKind::Exclusive(_) => None,
+                }
+            })
+            .collect::<Vec<_>>();
+        systems.sort_unstable_by_key(|(position, ..)| *position);
+
+        let (chunk, resources) = context.chunk_and_resources();
+        let jobs = systems
+            .iter_mut()
+            .map(|(_, _, system)| system.fetch(chunk, resources))
+            .collect::<Result<Vec<_>, _>>();
+        // a failed fetch stops the stage before any of its systems runs
+        let results = thread::scope(|scope| {
+            let mut jobs = jobs?.into_iter();
+            let first = jobs.next();
+            let handles = jobs.map(|job| scope.spawn(job)).collect::<Vec<_>>();
+            // the calling thread runs the first job instead of waiting
+            let mut results = first.into_iter().map(|job| job()).collect::<Vec<_>>();
+            for handle in handles {
+                results.push(handle.join().unwrap_or_else(|panic| resume_unwind(panic)));
+            }
+            Ok::<_, Error>(results)
+        })?;
+
+        let mut failure = None;
+        for ((_, name, system), result) in systems.iter_mut().zip(results) {
+            let command_queue = system.command_queue();
+            match result {
+                Ok(()) if failure.is_none() => command_queue.label_segments(name),
+                result => {
+                    failure = failure.or(result.err());
+                    command_queue.discard_unlabeled();
+                }
+            }
+        }
+        for (_, _, system) in systems.iter_mut() {
+            context.flush_queue(system.command_queue())?;
+        }
+        failure.map_or(Ok(()), Err)
+    }
+
+    fn set_mut<S: 'static>(&mut self) -> &mut ScheduledSet {
+        self.sets
+            .entry(TypeId::of::<S>())
+            .or_insert_with(|| ScheduledSet {
+                conditions: Vec::new(),
+                constraints: Constraints::default(),
+            })
+    }
+
+    /// Describes the systems, in the order in which they were added.
+    pub fn systems(&self) -> impl Iterator<Item = SystemInfo<'_>> + use<'_> {
+        self.systems.iter().map(|system| SystemInfo {
+            name: system.name,
+            access: &system.access,
+            sets: &system.sets,
+        })
+    }
+
+    /// The ordering constraints between systems, as `(before, after)` pairs of indexes into
+    /// `systems`.
+    pub fn ordering(&self) -> Vec<(usize, usize)> {
+        let mut ordering = self
+            .edges()
+            .into_iter()
+            .enumerate()
+            .flat_map(|(before, after)| after.into_iter().map(move |after| (before, after)))
+            .collect::<Vec<_>>();
+        ordering.sort_unstable();
+        ordering.dedup();
+        ordering
+    }
+
+    /// Lists the pairs of systems that borrow the same component or resource, at least one of them
+    /// mutably, without being ordered relative to each other. A system paired with itself
+    /// borrows something twice and always fails to run.
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let edges = self.edges();
+        let reachable = (0..self.systems.len())
+            .map(|from| reachable_from(&edges, from))
+            .collect::<Vec<_>>();
+        let mut conflicts = Vec::new();
+        for (first, system) in self.systems.iter().enumerate() {
+            if let Some(type_name) = system.access.self_conflict() {
+                conflicts.push(Conflict {
+                    first,
+                    second: first,
+                    type_name,
+                });
+            }
+            for (second, other) in self.systems.iter().enumerate().skip(first + 1) {
+                if reachable[first][second] || reachable[second][first] {
+                    continue;
+                }
+                if let Some(type_name) = system.access.conflict_with(&other.access) {
+                    conflicts.push(Conflict {
+                        first,
+                        second,
+                        type_name,
+                    });
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Sorts the systems topologically, picking the earliest added system whenever several are
+    /// free to run.
+    fn sort(&self) -> Result<Vec<usize>, Error> {
+        let edges = self.edges();
+        let mut incoming = vec![0; self.systems.len()];
+        for &to in edges.iter().flatten() {
+            incoming[to] += 1;
+        }
+        let mut ready = (0..self.systems.len())
+            .filter(|index| incoming[*index] == 0)
+            .collect::<BTreeSet<_>>();
+        let mut order = Vec::with_capacity(self.systems.len());
+        while let Some(index) = ready.pop_first() {
+            order.push(index);
+            for &to in edges[index].iter() {
+                incoming[to] -= 1;
+                if incoming[to] == 0 {
+                    ready.insert(to);
+                }
+            }
+        }
+        match (0..self.systems.len()).find(|index| incoming[*index] > 0) {
+            Some(index) => Err(Error::ScheduleCycle(self.systems[index].name)),
+            None => Ok(order),
+        }
+    }
+
+    /// For every system, the systems that must run after it.
+    fn edges(&self) -> Vec<Vec<usize>> {
+        let mut members = HashMap::<TypeId, Vec<usize>>::new();
+        for (index, system) in self.systems.iter().enumerate() {
+            for (set, _) in system.sets.iter() {
+                members.entry(*set).or_default().push(index);
+            }
+        }
+        let members_of = |set: &TypeId| members.get(set).map(Vec::as_slice).unwrap_or(&[]);
+
+        let mut edges = vec![Vec::new(); self.systems.len()];
+        for (index, system) in self.systems.iter().enumerate() {
+            for set in system.constraints.before.iter() {
+                edges[index].extend_from_slice(members_of(set));
+            }
+            for set in system.constraints.after.iter() {
+                for &member in members_of(set) {
+                    edges[member].push(index);
+                }
+            }
+        }
+        for (id, set) in self.sets.iter() {
+            for &member in members_of(id) {
+                for before in set.constraints.before.iter() {
+                    edges[member].extend_from_slice(members_of(before));
+                }
+                for after in set.constraints.after.iter() {
+                    for &other in members_of(after) {
+                        edges[other].push(member);
+                    }
+                }
+            }
+        }
+        edges
+    }
+}
+
+impl ScheduledSystem {
+    fn run(&mut self, context: &mut SystemsContext) -> Result<(), Error> {
+        match &mut self.system {
+            SystemKind::Exclusive(system) => system(context),
+            #[cfg(all(
+                feature = "std",
+                any(not(feature = "single-threaded"), feature = "sync")
+            ))]
+            SystemKind::Parallel(system) => system.run(context),
+        }
+    }
+
+    #[cfg(all(
+        feature = "std",
+        any(not(feature = "single-threaded"), feature = "sync")
+    ))]
+    fn is_parallel(&self) -> bool {
+        matches!(self.system, SystemKind::Parallel(_))
+    }
+}
+
+/// Two systems of a `Schedule` borrowing the same target, see `Schedule::conflicts`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Conflict {
+    /// Index into `Schedule::systems`.
+    pub first: usize,
+    /// Index into `Schedule::systems`, equal to `first` if the system conflicts with itself.
+    pub second: usize,
+    /// The type name of the borrowed component or resource.
+    pub type_name: &'static str,
+}
+
+/// A system of a `Schedule`, see `Schedule::systems`.
+#[derive(Copy, Clone, Debug)]
+pub struct SystemInfo<'a> {
+    name: &'static str,
+    access: &'a Access,
+    sets: &'a [(TypeId, &'static str)],
+}
+
+impl<'a> SystemInfo<'a> {
+    /// The type name of the system function.
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    #[inline]
+    pub fn access(&self) -> &'a Access {
+        self.access
+    }
+
+    /// The type names of the sets the system is in.
+    pub fn sets(&self) -> impl Iterator<Item = &'static str> + use<'a> {
+        self.sets.iter().map(|(_, name)| *name)
+    }
+}
+
+impl SystemConfig<'_> {
+    pub fn in_set<S: 'static>(self) -> Self {
+        self.0.sets.push((TypeId::of::<S>(), type_name::<S>()));
+        self
+    }
+
+    /// Runs the system before every system of the set `S`.
+    pub fn before<S: 'static>(self) -> Self {
+        self.0.constraints.before.push(TypeId::of::<S>());
+        self
+    }
+
+    /// Runs the system after every system of the set `S`.
+    pub fn after<S: 'static>(self) -> Self {
+        self.0.constraints.after.push(TypeId::of::<S>());
+        self
+    }
+
+    /// Runs the system only if `condition` returns true, see `SystemsContext::run_if`.
+    pub fn run_if<C, CParams>(self, condition: C) -> Self
+    where
+        C: System<CParams, Input = (), Output = bool> + 'static,
+        CParams: 'static,
+    {
+        self.0.conditions.push(boxed_condition(condition));
+        self
+    }
+}
+
+impl SetConfig<'_> {
+    /// Runs the systems of this set before every system of the set `S`.
+    pub fn before<S: 'static>(self) -> Self {
+        self.0.constraints.before.push(TypeId::of::<S>());
+        self
+    }
+
+    /// Runs the systems of this set after every system of the set `S`.
+    pub fn after<S: 'static>(self) -> Self {
+        self.0.constraints.after.push(TypeId::of::<S>());
+        self
+    }
+
+    /// Runs the systems of this set only if `condition` returns true.
+    pub fn run_if<C, CParams>(self, condition: C) -> Self
+    where
+        C: System<CParams, Input = (), Output = bool> + 'static,
+        CParams: 'static,
+    {
+        self.0.conditions.push(boxed_condition(condition));
+        self
+    }
+}
+
+fn boxed_condition<C, CParams>(mut condition: C) -> BoxedCondition
+where
+    C: System<CParams, Input = (), Output = bool> + 'static,
+    CParams: 'static,
+{
+    let mut state = C::State::default();
+    Box::new(move |context| context.check(&mut condition, &mut state))
+}
+
+fn reachable_from(edges: &[Vec<usize>], from: usize) -> Vec<bool> {
+    let mut reachable = vec![false; edges.len()];
+    let mut stack = edges[from].clone();
+    while let Some(index) = stack.pop() {
+        if !reachable[index] {
+            reachable[index] = true;
+            stack.extend_from_slice(&edges[index]);
+        }
+    }
+    reachable
+}
+
+fn check_all(
+    conditions: &mut [BoxedCondition],
+    context: &mut SystemsContext,
+) -> Result<bool, Error> {
+    for condition in conditions.iter_mut() {
+        if !condition(context)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::{ResourceMut, ResourceRef};
+    use crate::systems::In;
+    use crate::ChunkBuilder;
+
+    struct Input;
+    struct Physics;
+    struct Render;
+
+    type Log = Vec<&'static str>;
+
+    fn input(mut log: ResourceMut<Log>) {
+        log.get_mut().push("input");
+    }
+
+    fn physics(mut log: ResourceMut<Log>) {
+        log.get_mut().push("physics");
+    }
+
+    fn render(mut log: ResourceMut<Log>) {
+        log.get_mut().push("render");
+    }
+
+    fn enabled(enabled: ResourceRef<bool>) -> bool {
+        *enabled.get()
+    }
+
+    fn run(schedule: &mut Schedule, enabled: bool) -> Result<Log, Error> {
+        let mut chunk = ChunkBuilder::default().build();
+        let mut resources = Resources::default();
+        resources.add_resource(Log::new());
+        resources.add_resource(enabled);
+        schedule.run(&mut chunk, &mut resources)?;
+        let log = resources.resource_ref::<Log>()?.get().clone();
+        Ok(log)
+    }
+
+    #[test]
+    fn runs_systems_in_topological_order() {
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(render)
+            .in_set::<Render>()
+            .after::<Physics>();
+        schedule.add_system(physics).in_set::<Physics>();
+        schedule.add_system(input).before::<Physics>();
+        assert_eq!(
+            run(&mut schedule, true).unwrap(),
+            ["input", "physics", "render"]
+        );
+        assert_eq!(schedule.ordering(), [(1, 0), (2, 1)]);
+    }
+
+    #[test]
+    fn keeps_the_order_of_unconstrained_systems() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(render);
+        schedule.add_system(input);
+        schedule.add_system(physics);
+        assert_eq!(
+            run(&mut schedule, true).unwrap(),
+            ["render", "input", "physics"]
+        );
+    }
+
+    #[test]
+    fn fails_on_cycles() {
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(input)
+            .in_set::<Input>()
+            .after::<Physics>();
+        schedule
+            .add_system(physics)
+            .in_set::<Physics>()
+            .after::<Input>();
+        schedule.add_system(render);
+        let Err(Error::ScheduleCycle(name)) = run(&mut schedule, true) else {
+            panic!("the cycle was not detected");
+        };
+        assert!(name.ends_with("input"));
+    }
+
+    #[test]
+    fn systems_inherit_the_constraints_and_conditions_of_their_sets() {
+        let mut schedule = Schedule::new();
+        schedule.configure_set::<Render>().after::<Physics>();
+        schedule
+            .configure_set::<Physics>()
+            .after::<Input>()
+            .run_if(enabled);
+        schedule.add_system(render).in_set::<Render>();
+        schedule.add_system(physics).in_set::<Physics>();
+        schedule.add_system(input).in_set::<Input>();
+        assert_eq!(
+            run(&mut schedule, true).unwrap(),
+            ["input", "physics", "render"]
+        );
+        assert_eq!(run(&mut schedule, false).unwrap(), ["input", "render"]);
+    }
+
+    #[test]
+    fn systems_run_only_if_their_conditions_pass() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(input);
+        schedule.add_system(physics).run_if(enabled);
+        assert_eq!(run(&mut schedule, false).unwrap(), ["input"]);
+        assert_eq!(run(&mut schedule, true).unwrap(), ["input", "physics"]);
+    }
+
+    fn count(mut log: ResourceMut<Log>) -> usize {
+        log.get_mut().len()
+    }
+
+    fn record(In(count): In<usize>, mut log: ResourceMut<Log>) {
+        log.get_mut().truncate(count);
+    }
+
+    fn both(_log: ResourceMut<Log>, _read: ResourceRef<Log>) {}
+
+    fn read(_enabled: ResourceRef<bool>) {}
+
+    #[test]
+    fn reports_unordered_systems_borrowing_the_same_target() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(input);
+        schedule.add_system(physics);
+        schedule.add_system(read);
+        schedule.add_system(read);
+        let conflict = Conflict {
+            first: 0,
+            second: 1,
+            type_name: type_name::<Log>(),
+        };
+        assert_eq!(schedule.conflicts(), [conflict]);
+
+        // ordered systems run one after the other and do not conflict
+        let mut schedule = Schedule::new();
+        schedule.add_system(input).in_set::<Input>();
+        schedule.add_system(physics).after::<Input>();
+        assert_eq!(schedule.conflicts(), []);
+    }
+
+    #[test]
+    fn reports_systems_conflicting_with_themselves() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(both);
+        // the stages of a pipe borrow their params one after the other
+        schedule.add_system(count.pipe(record));
+        let conflicts = schedule.conflicts();
+        assert_eq!(
+            conflicts
+                .iter()
+                .map(|c| (c.first, c.second))
+                .collect::<Vec<_>>(),
+            [(0, 0), (0, 1)]
+        );
+    }
+
+    #[cfg(all(
+        feature = "std",
+        any(not(feature = "single-threaded"), feature = "sync")
+    ))]
+    mod par {
+        use super::*;
+        use crate::components::ComponentsRef;
+        use crate::storage::Component;
+        use crate::Commands;
+        use alloc::collections::BTreeMap;
+        use std::sync::Barrier;
+
+        // each waits for the others, and only returns if they all run at once
+        fn log_after_meeting(barrier: &Barrier, commands: &mut Commands, name: &'static str) {
+            barrier.wait();
+            commands.defer(move |_, resources| {
+                resources.resource_mut::<Log>()?.get_mut().push(name);
+                Ok(())
+            });
+        }
+
+        fn left(barrier: ResourceRef<Barrier>, mut commands: Commands) {
+            log_after_meeting(barrier.get(), &mut commands, "left");
+        }
+
+        fn right(barrier: ResourceRef<Barrier>, mut commands: Commands) {
+            log_after_meeting(barrier.get(), &mut commands, "right");
+        }
+
+        fn fail(barrier: ResourceRef<Barrier>, mut commands: Commands) -> Result<(), Error> {
+            log_after_meeting(barrier.get(), &mut commands, "fail");
+            Err(Error::ResourceNotFound("fail"))
+        }
+
+        struct Stored;
+
+        impl Component for Stored {
+            type Storage = BTreeMap<usize, Stored>;
+        }
+
+        fn stored(_stored: ComponentsRef<Stored>) {}
+
+        fn run_par(schedule: &mut Schedule, meeting: usize) -> (Result<(), Error>, Log) {
+            let mut chunk = ChunkBuilder::default()
+                .with_custom_storage::<Stored>()
+                .build();
+            let mut resources = Resources::default();
+            resources.add_resource(Log::new());
+            resources.add_resource(true);
+            resources.add_resource(Barrier::new(meeting));
+            let result = schedule.run_par(&mut chunk, &mut resources);
+            let log = resources.resource_ref::<Log>().unwrap().get().clone();
+            (result, log)
+        }
+
+        #[test]
+        fn runs_parallel_systems_between_barriers() {
+            let mut schedule = Schedule::new();
+            schedule.add_par_system(left);
+            schedule.add_par_system(right);
+            schedule.add_system(input);
+            schedule.add_par_system(physics);
+            schedule.add_par_system(read);
+            schedule.add_par_system(render).in_set::<Render>();
+            schedule.add_par_system(read).after::<Render>();
+            let stages = schedule.stages(&schedule.sort().unwrap());
+            assert_eq!(stages, [vec![0, 1], vec![2], vec![3, 4], vec![5], vec![6]]);
+            for _ in 0..2 {
+                let (result, log) = run_par(&mut schedule, 2);
+                result.unwrap();
+                assert_eq!(log, ["left", "right", "input", "physics", "render"]);
+            }
+        }
+
+        #[test]
+        fn flushes_the_commands_of_the_systems_before_the_failing_one() {
+            let mut schedule = Schedule::new();
+            schedule.add_par_system(left);
+            schedule.add_par_system(fail);
+            schedule.add_par_system(right);
+            let (result, log) = run_par(&mut schedule, 3);
+            assert!(matches!(result, Err(Error::ResourceNotFound("fail"))));
+            assert_eq!(log, ["left"]);
+        }
+
+        #[test]
+        fn refuses_to_send_custom_storages() {
+            let mut schedule = Schedule::new();
+            schedule.add_par_system(stored);
+            schedule.add_par_system(stored);
+            let (result, _) = run_par(&mut schedule, 1);
+            assert!(matches!(
+                result,
+                Err(Error::ComponentStorageNotShareable(_))
+            ));
+            // alone, a system runs on the calling thread
+            let mut schedule = Schedule::new();
+            schedule.add_par_system(stored);
+            run_par(&mut schedule, 1).0.unwrap();
+        }
+    }
+}
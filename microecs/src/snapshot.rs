@@ -0,0 +1,111 @@
+use crate::components::ColumnSnapshots;
+use crate::entities::{ChunkEntities, Entity};
+use crate::{Chunk, Error};
+use alloc::string::String;
+use hashbrown::HashMap;
+
+/// A copy of the entities and components of a chunk, see `Chunk::snapshot`.
+pub struct ChunkSnapshot {
+    entities: ChunkEntities,
+    columns: ColumnSnapshots,
+    tick: u32,
+    names: HashMap<String, Entity>,
+}
+
+impl Chunk {
+    /// Copies every entity and component, including the state of the entity id generator and the
+    /// change ticks, so that `restore` can later put the chunk back exactly as it is now. Every
+    /// component type holding values must have been registered with
+    /// `ChunkBuilder::with_cloneable_component`. Items are not part of the snapshot.
+    pub fn snapshot(&self) -> Result<ChunkSnapshot, Error> {
+        Ok(ChunkSnapshot {
+            entities: self.entities.clone(),
+            columns: self.components.snapshot()?,
+            tick: self.tick(),
+            names: self.names.clone(),
+        })
+    }
+
+    /// Puts the chunk back to the state it was in when `snapshot` was taken. Component hooks are
+    /// not called.
+    pub fn restore(&mut self, snapshot: ChunkSnapshot) -> Result<(), Error> {
+        let rows = snapshot.entities.len();
+        self.components
+            .restore(rows, snapshot.tick, snapshot.columns)?;
+        self.entities = snapshot.entities;
+        self.names = snapshot.names;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChunkBuilder;
+
+    #[derive(Clone)]
+    struct Health(u32);
+
+    struct Sprite;
+
+    #[test]
+    fn restores_the_entities_components_and_names() {
+        let mut chunk = ChunkBuilder::default()
+            .with_cloneable_component::<Health>()
+            .build();
+        let hero = chunk.spawn().unwrap();
+        chunk.add_component(hero, Health(10)).unwrap();
+        chunk.set_name(hero, "hero").unwrap();
+        let snapshot = chunk.snapshot().unwrap();
+
+        chunk.destroy(hero).unwrap();
+        let spawned = chunk.spawn().unwrap();
+        chunk.restore(snapshot).unwrap();
+        assert!(chunk.entity(spawned).is_err());
+        assert_eq!(chunk.entity_by_name("hero"), Some(hero));
+        let health = chunk.components_ref::<Health>().unwrap();
+        assert_eq!(health.get(hero).map(|h| h.0), Some(10));
+        drop(health);
+        // the id generator is restored too, so the next entity gets the same id again
+        assert_eq!(chunk.spawn().unwrap(), spawned);
+    }
+
+    #[test]
+    fn fails_for_components_without_a_clone_hook() {
+        let mut chunk = ChunkBuilder::default().with_component::<Sprite>().build();
+        let entity = chunk.spawn().unwrap();
+        assert!(chunk.snapshot().is_ok());
+        chunk.add_component(entity, Sprite).unwrap();
+        assert!(matches!(
+            chunk.snapshot(),
+            Err(Error::ComponentNotCloneable(_))
+        ));
+    }
+
+    #[test]
+    fn restores_the_change_ticks() {
+        let mut chunk = ChunkBuilder::default()
+            .with_cloneable_component::<Health>()
+            .build();
+        let first = chunk.spawn().unwrap();
+        let second = chunk.spawn().unwrap();
+        chunk.add_component(first, Health(1)).unwrap();
+        chunk.advance_tick();
+        chunk.add_component(second, Health(2)).unwrap();
+        let snapshot = chunk.snapshot().unwrap();
+
+        chunk.advance_tick();
+        chunk
+            .components_mut::<Health>()
+            .unwrap()
+            .get_mut(first)
+            .unwrap()
+            .0 = 3;
+        chunk.restore(snapshot).unwrap();
+        assert_eq!(chunk.tick(), 1);
+        let health = chunk.components_ref::<Health>().unwrap();
+        assert_eq!(health.last_changed(first), Some(0));
+        assert_eq!(health.last_changed(second), Some(1));
+        assert_eq!(health.get(first).map(|h| h.0), Some(1));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::access::{Access, AccessTarget};
+use crate::resources::{Resource, ResourceMut, ResourceRef, Resources, ResourcesBuilder};
+use crate::systems::System;
+use crate::{Chunk, CommandQueue, Error};
+
+/// The current value of the state `S`, added by `ResourcesBuilder::with_state`. Systems change
+/// it through `NextState<S>`, the change taking effect when `apply_state_transition::<S>` runs.
+pub struct State<S> {
+    current: S,
+    previous: Option<S>,
+}
+
+impl<S> State<S> {
+    #[inline]
+    pub fn get(&self) -> &S {
+        &self.current
+    }
+
+    /// The state left during the last run of `apply_state_transition::<S>`, if it changed it.
+    #[inline]
+    pub fn previous(&self) -> Option<&S> {
+        self.previous.as_ref()
+    }
+}
+
+/// The value `State<S>` takes on the next run of `apply_state_transition::<S>`.
+pub struct NextState<S>(Option<S>);
+
+impl<S> NextState<S> {
+    #[inline]
+    pub fn set(&mut self, state: S) {
+        self.0 = Some(state);
+    }
+
+    #[inline]
+    pub fn get(&self) -> Option<&S> {
+        self.0.as_ref()
+    }
+}
+
+impl ResourcesBuilder {
+    /// Adds `State<S>`, starting at `initial`, and `NextState<S>`.
+    pub fn with_state<S: Resource>(self, initial: S) -> Self {
+        self.with_resource(State {
+            current: initial,
+            previous: None,
+        })
+        .with_resource(NextState::<S>(None))
+    }
+}
+
+/// Moves `NextState<S>` into `State<S>`. Schedule it before the systems running under
+/// `on_enter` and `on_exit`, which only see the transition until its next run. Setting the
+/// current state again is not a transition.
+pub fn apply_state_transition<S: Resource + PartialEq>(
+    mut state: ResourceMut<State<S>>,
+    mut next: ResourceMut<NextState<S>>,
+) {
+    let state = state.get_mut();
+    state.previous = match next.get_mut().0.take() {
+        Some(next) if next != state.current => Some(core::mem::replace(&mut state.current, next)),
+        _ => None,
+    };
+}
+
+/// A run condition returning true while `State<S>` is `state`, so that e.g. gameplay systems
+/// stop while a menu is open. See `SystemsContext::run_if`.
+pub fn in_state<S: Resource + PartialEq>(state: S) -> StateCondition<S> {
+    StateCondition {
+        state,
+        matches: |current, state| current.get() == state,
+    }
+}
+
+/// A run condition returning true right after `State<S>` became `state`.
+pub fn on_enter<S: Resource + PartialEq>(state: S) -> StateCondition<S> {
+    StateCondition {
+        state,
+        matches: |current, state| current.previous.is_some() && current.get() == state,
+    }
+}
+
+/// A run condition returning true right after `State<S>` stopped being `state`.
+pub fn on_exit<S: Resource + PartialEq>(state: S) -> StateCondition<S> {
+    StateCondition {
+        state,
+        matches: |current, state| current.previous() == Some(state),
+    }
+}
+
+/// See `in_state`, `on_enter` and `on_exit`.
+pub struct StateCondition<S> {
+    state: S,
+    matches: fn(&State<S>, &S) -> bool,
+}
+
+impl<S: Resource + PartialEq> System<()> for StateCondition<S> {
+    type Params<'a> = ResourceRef<'a, State<S>>;
+    type Input = ();
+    type Output = bool;
+    type State = ();
+
+    fn access(access: &mut Access) {
+        access.read::<State<S>>(AccessTarget::Resource);
+    }
+
+    fn get_params<'a>(
+        _state: &mut Self::State,
+        _chunk: &'a Chunk,
+        resources: &'a Resources,
+        _command_queue: &'a CommandQueue,
+    ) -> Result<Self::Params<'a>, Error> {
+        resources.resource_ref::<State<S>>()
+    }
+
+    fn run(&mut self, _input: Self::Input, current: Self::Params<'_>) -> Self::Output {
+        (self.matches)(current.get(), &self.state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schedule::Schedule;
+    use crate::ChunkBuilder;
+    use alloc::vec::Vec;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum Screen {
+        Menu,
+        Game,
+    }
+
+    type Log = Vec<&'static str>;
+
+    fn play(mut log: ResourceMut<Log>) {
+        log.get_mut().push("play");
+    }
+
+    fn enter_game(mut log: ResourceMut<Log>) {
+        log.get_mut().push("enter game");
+    }
+
+    fn exit_menu(mut log: ResourceMut<Log>) {
+        log.get_mut().push("exit menu");
+    }
+
+    #[test]
+    fn runs_systems_on_the_transitions_and_in_the_state() {
+        let mut chunk = ChunkBuilder::default().build();
+        let mut resources = ResourcesBuilder::default()
+            .with_state(Screen::Menu)
+            .with_resource(Log::new())
+            .build();
+        let mut schedule = Schedule::new();
+        schedule.add_system(apply_state_transition::<Screen>);
+        schedule.add_system(exit_menu).run_if(on_exit(Screen::Menu));
+        schedule
+            .add_system(enter_game)
+            .run_if(on_enter(Screen::Game));
+        schedule.add_system(play).run_if(in_state(Screen::Game));
+
+        let mut run = |resources: &mut Resources, next: Option<Screen>| {
+            if let Some(next) = next {
+                resources
+                    .resource_mut::<NextState<Screen>>()
+                    .unwrap()
+                    .get_mut()
+                    .set(next);
+            }
+            schedule.run(&mut chunk, resources).unwrap();
+            let log = resources
+                .resource_mut::<Log>()
+                .unwrap()
+                .get_mut()
+                .split_off(0);
+            log
+        };
+        assert_eq!(run(&mut resources, None), Log::new());
+        assert_eq!(
+            run(&mut resources, Some(Screen::Game)),
+            ["exit menu", "enter game", "play"]
+        );
+        assert_eq!(run(&mut resources, None), ["play"]);
+        // setting the current state again is not a transition
+        assert_eq!(run(&mut resources, Some(Screen::Game)), ["play"]);
+        let state = resources.resource_ref::<State<Screen>>().unwrap();
+        let state = state.get();
+        assert_eq!((state.get(), state.previous()), (&Screen::Game, None));
+    }
+}
@@ -0,0 +1,56 @@
+use alloc::{boxed::Box, collections::BTreeMap};
+
+/// Keeps the values of a component type by row, for the types registered with
+/// `ChunkBuilder::with_custom_storage`. The chunk tracks which rows hold a value, and moves
+/// values between rows through `remove` and `insert` when entities are destroyed.
+pub trait Storage<T> {
+    /// Stores `value` at `row`, replacing the previous value.
+    fn insert(&mut self, row: usize, value: T);
+    fn remove(&mut self, row: usize) -> Option<T>;
+    fn get(&self, row: usize) -> Option<&T>;
+    fn get_mut(&mut self, row: usize) -> Option<&mut T>;
+    /// Every `(row, value)` pair, ordered by row.
+    fn iter(&self) -> Box<dyn Iterator<Item = (usize, &T)> + '_>;
+    /// Every `(row, value)` pair, ordered by row.
+    fn iter_mut(&mut self) -> Box<dyn Iterator<Item = (usize, &mut T)> + '_>;
+    fn clear(&mut self);
+}
+
+/// A component type with its own storage, see `Storage`.
+pub trait Component: Sized + 'static {
+    type Storage: Storage<Self> + Default + 'static;
+}
+
+impl<T> Storage<T> for BTreeMap<usize, T> {
+    #[inline]
+    fn insert(&mut self, row: usize, value: T) {
+        BTreeMap::insert(self, row, value);
+    }
+
+    #[inline]
+    fn remove(&mut self, row: usize) -> Option<T> {
+        BTreeMap::remove(self, &row)
+    }
+
+    #[inline]
+    fn get(&self, row: usize) -> Option<&T> {
+        BTreeMap::get(self, &row)
+    }
+
+    #[inline]
+    fn get_mut(&mut self, row: usize) -> Option<&mut T> {
+        BTreeMap::get_mut(self, &row)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (usize, &T)> + '_> {
+        Box::new(BTreeMap::iter(self).map(|(row, value)| (*row, value)))
+    }
+
+    fn iter_mut(&mut self) -> Box<dyn Iterator<Item = (usize, &mut T)> + '_> {
+        Box::new(BTreeMap::iter_mut(self).map(|(row, value)| (*row, value)))
+    }
+
+    fn clear(&mut self) {
+        BTreeMap::clear(self);
+    }
+}
@@ -1,13 +1,25 @@
+use crate::access::{Access, AccessTarget};
+use crate::commands::EntitySpawner;
 use crate::components::{ComponentsMut, ComponentsRef};
 use crate::entities::Entities;
 use crate::prelude::Resources;
-use crate::resources::{ItemMut, ItemRef, ResourceMut, ResourceRef};
+use crate::profiling::SystemTimings;
+use crate::query::QueryState;
+use crate::resources::{ItemMut, ItemRef, NonSend, NonSendMut, ResourceMut, ResourceRef};
 use crate::{Chunk, CommandQueue, Commands, Error};
+use core::any::{type_name, TypeId};
+use core::marker::PhantomData;
 
 pub struct SystemsContext<'a> {
     chunk: &'a mut Chunk,
     resources: &'a mut Resources,
-    command_queue: &'a mut CommandQueue,
+    command_queue: ContextQueue<'a>,
+}
+
+enum ContextQueue<'a> {
+    External(&'a mut CommandQueue),
+    /// Taken out of the chunk for the life of the context, see `Chunk::with`.
+    Chunk(CommandQueue),
 }
 
 impl<'a> SystemsContext<'a> {
@@ -16,6 +28,15 @@ impl<'a> SystemsContext<'a> {
         resources: &'a mut Resources,
         command_queue: &'a mut CommandQueue,
     ) -> Self {
+        Self {
+            chunk,
+            command_queue: ContextQueue::External(command_queue),
+            resources,
+        }
+    }
+
+    pub(crate) fn with_chunk_queue(chunk: &'a mut Chunk, resources: &'a mut Resources) -> Self {
+        let command_queue = ContextQueue::Chunk(core::mem::take(&mut chunk.command_queue));
         Self {
             chunk,
             command_queue,
@@ -23,33 +44,366 @@ impl<'a> SystemsContext<'a> {
         }
     }
 
+    /// Runs `system_function` and flushes its commands. The states of its params, e.g. the ids of
+    /// the columns it borrows, are kept by the chunk for the next run of a system of its type.
     pub fn run<F, P>(&mut self, mut system_function: F) -> Result<&mut Self, Error>
     where
-        F: System<P>,
+        F: System<P, Input = ()> + 'static,
+        F::Output: SystemOutput,
+    {
+        self.with_state::<F, _, _>(|context, state| {
+            context.run_system(&mut system_function, state)
+        })?;
+        Ok(self)
+    }
+
+    /// Runs `system_function` without flushing its commands, so that the systems run after it
+    /// see the same entities and components until `flush` is called.
+    pub fn run_deferred<F, P>(&mut self, mut system_function: F) -> Result<&mut Self, Error>
+    where
+        F: System<P, Input = ()> + 'static,
+        F::Output: SystemOutput,
+    {
+        self.with_state::<F, _, _>(|context, state| {
+            context.run_timed(&mut system_function, state, false)
+        })?;
+        Ok(self)
+    }
+
+    /// Calls `run` with the param states of the systems of type `F`, taken out of the chunk for
+    /// the duration of the call.
+    fn with_state<F, P, R>(&mut self, run: impl FnOnce(&mut Self, &mut F::State) -> R) -> R
+    where
+        F: System<P> + 'static,
+    {
+        let mut state = self
+            .chunk
+            .system_states
+            .remove(&TypeId::of::<F>())
+            .and_then(|state| state.downcast::<F::State>().ok())
+            .unwrap_or_default();
+        let result = run(self, &mut state);
+        self.chunk.system_states.insert(TypeId::of::<F>(), state);
+        result
+    }
+
+    /// Applies the commands recorded by the systems run through `run_deferred`.
+    pub fn flush(&mut self) -> Result<&mut Self, Error> {
+        self.command_queue.get().flush(self.chunk, self.resources)?;
+        Ok(self)
+    }
+
+    #[cfg(all(
+        feature = "std",
+        any(not(feature = "single-threaded"), feature = "sync")
+    ))]
+    pub(crate) fn chunk_and_resources(&self) -> (&Chunk, &Resources) {
+        (self.chunk, self.resources)
+    }
+
+    /// Flushes `command_queue` with the flush policy of the context's own queue.
+    #[cfg(all(
+        feature = "std",
+        any(not(feature = "single-threaded"), feature = "sync")
+    ))]
+    pub(crate) fn flush_queue(&mut self, command_queue: &mut CommandQueue) -> Result<(), Error> {
+        command_queue.set_flush_policy(self.command_queue.get().flush_policy());
+        command_queue.flush(self.chunk, self.resources)
+    }
+
+    /// Runs `system_function` and flushes its commands.
+    pub(crate) fn run_system<F, P>(
+        &mut self,
+        system_function: &mut F,
+        state: &mut F::State,
+    ) -> Result<(), Error>
+    where
+        F: System<P, Input = ()>,
+        F::Output: SystemOutput,
+    {
+        self.run_timed(system_function, state, true)
+    }
+
+    /// Runs `system_function` and, if `flush` is true, flushes its commands, recording the
+    /// duration of both if a `SystemTimings` resource is present.
+    fn run_timed<F, P>(
+        &mut self,
+        system_function: &mut F,
+        state: &mut F::State,
+        flush: bool,
+    ) -> Result<(), Error>
+    where
+        F: System<P, Input = ()>,
+        F::Output: SystemOutput,
+    {
+        let start = self
+            .resources
+            .resource_ref::<SystemTimings>()
+            .ok()
+            .map(|timings| timings.get().now());
+        let result = system_function
+            .run_with(
+                state,
+                (),
+                self.chunk,
+                self.resources,
+                self.command_queue.get(),
+            )
+            .and_then(SystemOutput::into_result);
+        if result.is_err() {
+            // a failed system leaves nothing behind for the next flush
+            self.command_queue.get().discard_unlabeled();
+        }
+        self.command_queue.get().label_segments(type_name::<F>());
+        result?;
+        if flush {
+            self.command_queue.get().flush(self.chunk, self.resources)?;
+        }
+        if let Some(start) = start {
+            if let Ok(mut timings) = self.resources.resource_mut::<SystemTimings>() {
+                let timings = timings.get_mut();
+                let duration = timings.now().saturating_sub(start);
+                timings.record(type_name::<F>(), duration);
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `condition` without flushing anything.
+    pub(crate) fn check<C, CParams>(
+        &mut self,
+        condition: &mut C,
+        state: &mut C::State,
+    ) -> Result<bool, Error>
+    where
+        C: System<CParams, Input = (), Output = bool>,
+    {
+        condition.run_with(
+            state,
+            (),
+            self.chunk,
+            self.resources,
+            self.command_queue.get(),
+        )
+    }
+
+    /// Runs `system` only if `condition`, a system returning a `bool`, returns true.
+    pub fn run_if<C, CParams, F, P>(
+        &mut self,
+        mut condition: C,
+        system_function: F,
+    ) -> Result<&mut Self, Error>
+    where
+        C: System<CParams, Input = (), Output = bool> + 'static,
+        F: System<P, Input = ()> + 'static,
+        F::Output: SystemOutput,
+    {
+        if self.with_state::<C, _, _>(|context, state| context.check(&mut condition, state))? {
+            self.run(system_function)?;
+        }
+        Ok(self)
+    }
+
+    /// Runs `system_function` `count` times, flushing its commands after every run.
+    pub fn run_n<F, P>(&mut self, mut system_function: F, count: usize) -> Result<&mut Self, Error>
+    where
+        F: System<P, Input = ()> + 'static,
+        F::Output: SystemOutput,
+    {
+        self.with_state::<F, _, _>(|context, state| {
+            for _ in 0..count {
+                context.run_system(&mut system_function, state)?;
+            }
+            Ok(())
+        })?;
+        Ok(self)
+    }
+
+    /// Runs `system_function` for as long as `condition` returns true, checking it before every
+    /// run and flushing the commands of the system after every run.
+    pub fn run_while<C, CParams, F, P>(
+        &mut self,
+        mut system_function: F,
+        mut condition: C,
+    ) -> Result<&mut Self, Error>
+    where
+        C: System<CParams, Input = (), Output = bool> + 'static,
+        F: System<P, Input = ()> + 'static,
+        F::Output: SystemOutput,
     {
-        let params = F::get_params(&self.chunk, &self.resources, &self.command_queue)?;
-        system_function.run(params);
-        self.command_queue.flush(self.chunk, self.resources)?;
+        self.with_state::<C, _, _>(|context, condition_state| {
+            context.with_state::<F, _, _>(|context, state| {
+                while context.check(&mut condition, condition_state)? {
+                    context.run_system(&mut system_function, state)?;
+                }
+                Ok(())
+            })
+        })?;
         Ok(self)
     }
 }
 
+impl Drop for SystemsContext<'_> {
+    fn drop(&mut self) {
+        if let ContextQueue::Chunk(queue) = &mut self.command_queue {
+            self.chunk.command_queue = core::mem::take(queue);
+        }
+    }
+}
+
+impl ContextQueue<'_> {
+    #[inline]
+    fn get(&mut self) -> &mut CommandQueue {
+        match self {
+            ContextQueue::External(queue) => queue,
+            ContextQueue::Chunk(queue) => queue,
+        }
+    }
+}
+
 pub trait System<Params> {
     type Params<'a>;
+    type Input;
+    type Output;
+    /// The states of the params, kept by whoever runs the system between runs.
+    type State: Default + 'static;
+
+    /// Declares what `get_params` borrows.
+    fn access(access: &mut Access);
 
     fn get_params<'a>(
+        state: &mut Self::State,
         chunk: &'a Chunk,
         resources: &'a Resources,
         command_queue: &'a CommandQueue,
     ) -> Result<Self::Params<'a>, Error>;
 
-    fn run(&mut self, params: Self::Params<'_>);
+    fn run(&mut self, input: Self::Input, params: Self::Params<'_>) -> Self::Output;
+
+    /// Fetches the params and runs the system. The params are released before returning.
+    fn run_with(
+        &mut self,
+        state: &mut Self::State,
+        input: Self::Input,
+        chunk: &Chunk,
+        resources: &Resources,
+        command_queue: &CommandQueue,
+    ) -> Result<Self::Output, Error> {
+        let params = Self::get_params(state, chunk, resources, command_queue)?;
+        Ok(self.run(input, params))
+    }
+
+    /// Feeds the output of this system into `system`, which must take it as an `In<T>` first
+    /// parameter.
+    fn pipe<B, BParams>(self, system: B) -> Pipe<Self, B, Params, BParams>
+    where
+        Self: Sized,
+        B: System<BParams, Input = Self::Output>,
+    {
+        Pipe {
+            first: self,
+            second: system,
+            _params: PhantomData,
+        }
+    }
+}
+
+/// The value piped into a system from the output of the previous one. See `System::pipe`.
+pub struct In<T>(pub T);
+
+pub struct Pipe<A, B, AParams, BParams> {
+    first: A,
+    second: B,
+    _params: PhantomData<fn() -> (AParams, BParams)>,
+}
+
+impl<A, B, AParams, BParams> System<(AParams, BParams)> for Pipe<A, B, AParams, BParams>
+where
+    A: System<AParams>,
+    B: System<BParams, Input = A::Output>,
+{
+    type Params<'a> = (A::Params<'a>, B::Params<'a>);
+    type Input = A::Input;
+    type Output = B::Output;
+    type State = (A::State, B::State);
+
+    fn access(access: &mut Access) {
+        let mut first = Access::default();
+        A::access(&mut first);
+        access.add_stage(first);
+        let mut second = Access::default();
+        B::access(&mut second);
+        access.add_stage(second);
+    }
+
+    fn get_params<'a>(
+        state: &mut Self::State,
+        chunk: &'a Chunk,
+        resources: &'a Resources,
+        command_queue: &'a CommandQueue,
+    ) -> Result<Self::Params<'a>, Error> {
+        let (a, b) = state;
+        Ok((
+            A::get_params(a, chunk, resources, command_queue)?,
+            B::get_params(b, chunk, resources, command_queue)?,
+        ))
+    }
+
+    fn run(&mut self, input: Self::Input, params: Self::Params<'_>) -> Self::Output {
+        let (a, b) = params;
+        let output = self.first.run(input, a);
+        self.second.run(output, b)
+    }
+
+    // Both systems are free to borrow the same components: the params of the first system are
+    // dropped before the second one's are fetched.
+    fn run_with(
+        &mut self,
+        state: &mut Self::State,
+        input: Self::Input,
+        chunk: &Chunk,
+        resources: &Resources,
+        command_queue: &CommandQueue,
+    ) -> Result<Self::Output, Error> {
+        let (a, b) = state;
+        let output = self
+            .first
+            .run_with(a, input, chunk, resources, command_queue)?;
+        self.second
+            .run_with(b, output, chunk, resources, command_queue)
+    }
+}
+
+/// The values a system function may return: either nothing or a `Result<(), Error>`, whose
+/// error is propagated out of `SystemsContext::run`.
+pub trait SystemOutput {
+    fn into_result(self) -> Result<(), Error>;
+}
+
+impl SystemOutput for () {
+    #[inline]
+    fn into_result(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl SystemOutput for Result<(), Error> {
+    #[inline]
+    fn into_result(self) -> Result<(), Error> {
+        self
+    }
 }
 
 pub trait SystemParam {
     type Param<'a>;
+    /// Kept by the system between runs, e.g. the `ComponentId` of a column.
+    type State: Default + 'static;
+
+    /// Declares what `get_param` borrows.
+    fn access(access: &mut Access);
 
     fn get_param<'a>(
+        state: &mut Self::State,
         chunk: &'a Chunk,
         resources: &'a Resources,
         command_queue: &'a CommandQueue,
@@ -58,8 +412,12 @@ pub trait SystemParam {
 
 impl SystemParam for Entities<'_> {
     type Param<'a> = Entities<'a>;
+    type State = ();
+
+    fn access(_access: &mut Access) {}
 
     fn get_param<'a>(
+        _state: &mut Self::State,
         chunk: &'a Chunk,
         _resources: &'a Resources,
         _command_queue: &'a CommandQueue,
@@ -73,13 +431,19 @@ where
     T: 'static,
 {
     type Param<'a> = ComponentsRef<'a, T>;
+    type State = QueryState<T>;
+
+    fn access(access: &mut Access) {
+        access.read::<T>(AccessTarget::Component);
+    }
 
     fn get_param<'a>(
+        state: &mut Self::State,
         chunk: &'a Chunk,
         _resources: &'a Resources,
         _command_queue: &'a CommandQueue,
     ) -> Result<Self::Param<'a>, Error> {
-        chunk.components_ref()
+        state.get(chunk)
     }
 }
 
@@ -88,25 +452,53 @@ where
     T: 'static,
 {
     type Param<'a> = ComponentsMut<'a, T>;
+    type State = QueryState<T>;
+
+    fn access(access: &mut Access) {
+        access.write::<T>(AccessTarget::Component);
+    }
 
     fn get_param<'a>(
+        state: &mut Self::State,
         chunk: &'a Chunk,
         _resources: &'a Resources,
         _command_queue: &'a CommandQueue,
     ) -> Result<Self::Param<'a>, Error> {
-        chunk.components_mut()
+        state.get_mut(chunk)
     }
 }
 
 impl SystemParam for Commands<'_> {
     type Param<'a> = Commands<'a>;
+    type State = ();
+
+    fn access(_access: &mut Access) {}
 
     fn get_param<'a>(
-        _chunk: &'a Chunk,
+        _state: &mut Self::State,
+        chunk: &'a Chunk,
+        _resources: &'a Resources,
+        command_queue: &'a CommandQueue,
+    ) -> Result<Self::Param<'a>, Error> {
+        Ok(command_queue.deferred_commands(&chunk.entities))
+    }
+}
+
+impl SystemParam for EntitySpawner<'_> {
+    type Param<'a> = EntitySpawner<'a>;
+    type State = ();
+
+    fn access(_access: &mut Access) {}
+
+    fn get_param<'a>(
+        _state: &mut Self::State,
+        chunk: &'a Chunk,
         _resources: &'a Resources,
         command_queue: &'a CommandQueue,
     ) -> Result<Self::Param<'a>, Error> {
-        command_queue.deferred_commands()
+        Ok(EntitySpawner::new(
+            command_queue.deferred_commands(&chunk.entities),
+        ))
     }
 }
 
@@ -115,8 +507,14 @@ where
     T: 'static,
 {
     type Param<'a> = ResourceRef<'a, T>;
+    type State = ();
+
+    fn access(access: &mut Access) {
+        access.read::<T>(AccessTarget::Resource);
+    }
 
     fn get_param<'a>(
+        _state: &mut Self::State,
         _chunk: &'a Chunk,
         resources: &'a Resources,
         _command_queue: &'a CommandQueue,
@@ -130,8 +528,14 @@ where
     T: 'static,
 {
     type Param<'a> = ResourceMut<'a, T>;
+    type State = ();
+
+    fn access(access: &mut Access) {
+        access.write::<T>(AccessTarget::Resource);
+    }
 
     fn get_param<'a>(
+        _state: &mut Self::State,
         _chunk: &'a Chunk,
         resources: &'a Resources,
         _command_queue: &'a CommandQueue,
@@ -140,14 +544,61 @@ where
     }
 }
 
+impl<T> SystemParam for NonSend<'_, T>
+where
+    T: 'static,
+{
+    type Param<'a> = NonSend<'a, T>;
+    type State = ();
+
+    fn access(access: &mut Access) {
+        access.read::<T>(AccessTarget::NonSend);
+    }
+
+    fn get_param<'a>(
+        _state: &mut Self::State,
+        _chunk: &'a Chunk,
+        resources: &'a Resources,
+        _command_queue: &'a CommandQueue,
+    ) -> Result<Self::Param<'a>, Error> {
+        resources.non_send_ref::<T>()
+    }
+}
+
+impl<T> SystemParam for NonSendMut<'_, T>
+where
+    T: 'static,
+{
+    type Param<'a> = NonSendMut<'a, T>;
+    type State = ();
+
+    fn access(access: &mut Access) {
+        access.write::<T>(AccessTarget::NonSend);
+    }
+
+    fn get_param<'a>(
+        _state: &mut Self::State,
+        _chunk: &'a Chunk,
+        resources: &'a Resources,
+        _command_queue: &'a CommandQueue,
+    ) -> Result<Self::Param<'a>, Error> {
+        resources.non_send_mut::<T>()
+    }
+}
 
 impl<T> SystemParam for ItemRef<'_, T>
 where
     T: 'static,
 {
     type Param<'a> = ItemRef<'a, T>;
+    type State = ();
+
+    fn access(access: &mut Access) {
+        access.read::<T>(AccessTarget::Item);
+    }
 
     fn get_param<'a>(
+        _state: &mut Self::State,
         chunk: &'a Chunk,
         _resources: &'a Resources,
         _command_queue: &'a CommandQueue,
@@ -161,8 +612,14 @@ where
     T: 'static,
 {
     type Param<'a> = ItemMut<'a, T>;
+    type State = ();
+
+    fn access(access: &mut Access) {
+        access.write::<T>(AccessTarget::Item);
+    }
 
     fn get_param<'a>(
+        _state: &mut Self::State,
         chunk: &'a Chunk,
         _resources: &'a Resources,
         _command_queue: &'a CommandQueue,
@@ -177,15 +634,23 @@ where
     B: SystemParam,
 {
     type Param<'a> = (A::Param<'a>, B::Param<'a>);
+    type State = (A::State, B::State);
+
+    fn access(access: &mut Access) {
+        A::access(access);
+        B::access(access);
+    }
 
     fn get_param<'a>(
+        state: &mut Self::State,
         chunk: &'a Chunk,
         resources: &'a Resources,
         command_queue: &'a CommandQueue,
     ) -> Result<Self::Param<'a>, Error> {
+        let (a, b) = state;
         Ok((
-            A::get_param(chunk, resources, command_queue)?,
-            B::get_param(chunk, resources, command_queue)?,
+            A::get_param(a, chunk, resources, command_queue)?,
+            B::get_param(b, chunk, resources, command_queue)?,
         ))
     }
 }
@@ -194,31 +659,82 @@ where
 // variadics at home:
 macro_rules! impl_traits_for_tuple {
     ( $($T:ident),+ ) => {
-        impl<Func, $($T),+> System<($($T,)+)> for Func
+        impl<Func, Out, $($T),+> System<($($T,)+)> for Func
         where
-            Func: FnMut($($T,)+),
-            Func: for<'a> FnMut($($T::Param<'a>,)+),
+            Func: FnMut($($T,)+) -> Out,
+            Func: for<'a> FnMut($($T::Param<'a>,)+) -> Out,
             $($T: SystemParam,)+
         {
             type Params<'a> = ($($T::Param<'a>,)+);
+            type Input = ();
+            type Output = Out;
+            type State = ($($T::State,)+);
 
+            fn access(access: &mut Access) {
+                $($T::access(access);)+
+            }
+
+            #[allow(non_snake_case)]
             fn get_params<'a>(
+                state: &mut Self::State,
                 chunk: &'a Chunk,
                 resources: &'a Resources,
                 command_queue: &'a CommandQueue,
             ) -> Result<Self::Params<'a>, Error> {
-                Ok(($($T::get_param(chunk, resources, command_queue)?,)+))
+                let ($($T,)+) = state;
+                Ok(($($T::get_param($T, chunk, resources, command_queue)?,)+))
             }
 
-            fn run(&mut self, params: Self::Params<'_>) {
+            fn run(&mut self, _input: Self::Input, params: Self::Params<'_>) -> Self::Output {
                 #[allow(non_snake_case)]
                 let ($($T,)+) = params;
                 self($($T,)+)
             }
         }
+
+        impl_piped_system!($($T),+);
+    };
+}
+
+macro_rules! impl_piped_system {
+    ( $($T:ident),* ) => {
+        impl<Func, Value, Out, $($T),*> System<(In<Value>, $($T,)*)> for Func
+        where
+            Func: FnMut(In<Value>, $($T,)*) -> Out,
+            Func: for<'a> FnMut(In<Value>, $($T::Param<'a>,)*) -> Out,
+            $($T: SystemParam,)*
+        {
+            type Params<'a> = ($($T::Param<'a>,)*);
+            type Input = Value;
+            type Output = Out;
+            type State = ($($T::State,)*);
+
+            #[allow(unused_variables)]
+            fn access(access: &mut Access) {
+                $($T::access(access);)*
+            }
+
+            #[allow(non_snake_case, unused_variables)]
+            fn get_params<'a>(
+                state: &mut Self::State,
+                chunk: &'a Chunk,
+                resources: &'a Resources,
+                command_queue: &'a CommandQueue,
+            ) -> Result<Self::Params<'a>, Error> {
+                let ($($T,)*) = state;
+                Ok(($($T::get_param($T, chunk, resources, command_queue)?,)*))
+            }
+
+            fn run(&mut self, input: Self::Input, params: Self::Params<'_>) -> Self::Output {
+                #[allow(non_snake_case)]
+                let ($($T,)*) = params;
+                self(In(input), $($T,)*)
+            }
+        }
     };
 }
 
+impl_piped_system!();
 impl_traits_for_tuple!(Param1);
 impl_traits_for_tuple!(Param1, Param2);
 impl_traits_for_tuple!(Param1, Param2, Param3);
@@ -234,3 +750,46 @@ impl_traits_for_tuple!(Param1, Param2, Param3, Param4, Param5, Param6, Param7, P
 impl_traits_for_tuple!(Param1, Param2, Param3, Param4, Param5, Param6, Param7, Param8, Param9, Param10, Param11);
 #[rustfmt::skip]
 impl_traits_for_tuple!(Param1, Param2, Param3, Param4, Param5, Param6, Param7, Param8, Param9, Param10, Param11, Param12);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChunkBuilder, Resources};
+    use alloc::vec::Vec;
+
+    // the number of times the param was fetched with its state
+    struct Fetches(u32);
+
+    impl SystemParam for Fetches {
+        type Param<'a> = Fetches;
+        type State = u32;
+
+        fn access(_access: &mut Access) {}
+
+        fn get_param<'a>(
+            state: &mut Self::State,
+            _chunk: &'a Chunk,
+            _resources: &'a Resources,
+            _command_queue: &'a CommandQueue,
+        ) -> Result<Self::Param<'a>, Error> {
+            *state += 1;
+            Ok(Fetches(*state))
+        }
+    }
+
+    fn record(fetches: Fetches, mut seen: ResourceMut<Vec<u32>>) {
+        seen.get_mut().push(fetches.0);
+    }
+
+    #[test]
+    fn keeps_the_param_states_between_contexts() {
+        let mut chunk = ChunkBuilder::default().build();
+        let mut resources = Resources::default();
+        resources.add_resource(Vec::<u32>::new());
+        chunk.with(&mut resources).run(record).unwrap();
+        chunk.with(&mut resources).run_n(record, 2).unwrap();
+        chunk.with(&mut resources).run_deferred(record).unwrap();
+        let seen = resources.resource_ref::<Vec<u32>>().unwrap();
+        assert_eq!(seen.get(), &[1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,256 @@
+use crate::components::ComponentsRef;
+use crate::entities::Entity;
+use crate::query::Query;
+use crate::{Chunk, Error};
+use alloc::{string::String, vec::Vec};
+use core::ops::{BitOr, BitOrAssign};
+
+/// Up to 64 boolean markers of an entity packed into a single component, e.g. `stunned | flying`.
+/// The bits are named per chunk, see `Chunk::register_tag`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Tags(u64);
+
+impl Tags {
+    pub const NONE: Tags = Tags(0);
+
+    #[inline]
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    #[inline]
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+
+    #[inline]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns true if every tag of `tags` is set.
+    #[inline]
+    pub const fn contains(self, tags: Tags) -> bool {
+        self.0 & tags.0 == tags.0
+    }
+
+    /// Returns true if any tag of `tags` is set.
+    #[inline]
+    pub const fn intersects(self, tags: Tags) -> bool {
+        self.0 & tags.0 != 0
+    }
+
+    #[inline]
+    pub fn insert(&mut self, tags: Tags) {
+        self.0 |= tags.0;
+    }
+
+    #[inline]
+    pub fn remove(&mut self, tags: Tags) {
+        self.0 &= !tags.0;
+    }
+
+    /// Moves every bit `i` to `mapping[i]`, see `TagRegistry::merge`.
+    fn remap(self, mapping: &[Tags]) -> Tags {
+        mapping
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| self.0 & (1 << bit) != 0)
+            .fold(Tags::NONE, |remapped, (_, tag)| remapped | *tag)
+    }
+}
+
+impl BitOr for Tags {
+    type Output = Tags;
+
+    #[inline]
+    fn bitor(self, other: Tags) -> Tags {
+        Tags(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for Tags {
+    #[inline]
+    fn bitor_assign(&mut self, other: Tags) {
+        self.0 |= other.0;
+    }
+}
+
+impl ComponentsRef<'_, Tags> {
+    /// A query yielding the tags containing all of `tags`, meant as a filter, e.g.
+    /// `(&positions).with(tags.matching(enemy | flying))`.
+    pub fn matching(&self, tags: Tags) -> impl Query<'_, Item = &Tags> {
+        self.filter(move |value: &&Tags| value.contains(tags))
+    }
+}
+
+/// The names of the tags of a chunk, in the order of their bits.
+#[derive(Default)]
+pub(crate) struct TagRegistry {
+    names: Vec<String>,
+}
+
+impl TagRegistry {
+    fn get(&self, name: &str) -> Option<Tags> {
+        let bit = self.names.iter().position(|other| other == name)?;
+        Some(Tags(1 << bit))
+    }
+
+    fn register(&mut self, name: &str) -> Result<Tags, Error> {
+        if let Some(tag) = self.get(name) {
+            return Ok(tag);
+        }
+        if self.names.len() == u64::BITS as usize {
+            return Err(Error::TooManyTags);
+        }
+        self.names.push(name.into());
+        Ok(Tags(1 << (self.names.len() - 1)))
+    }
+
+    /// Fails if the tags of `other` do not all fit besides the tags of `self`.
+    pub(crate) fn check_merge(&self, other: &TagRegistry) -> Result<(), Error> {
+        let missing = other
+            .names
+            .iter()
+            .filter(|name| self.get(name).is_none())
+            .count();
+        if self.names.len() + missing > u64::BITS as usize {
+            return Err(Error::TooManyTags);
+        }
+        Ok(())
+    }
+
+    /// Registers the tags of `other`, returning the tag each of its bits became. Fails without
+    /// registering anything if they do not all fit.
+    pub(crate) fn merge(&mut self, other: &TagRegistry) -> Result<Vec<Tags>, Error> {
+        self.check_merge(other)?;
+        other.names.iter().map(|name| self.register(name)).collect()
+    }
+}
+
+impl Chunk {
+    /// Returns the tag named `name`, giving it the next free bit of `Tags` if it has none.
+    /// Fails once the 64 bits are taken.
+    pub fn register_tag(&mut self, name: &str) -> Result<Tags, Error> {
+        self.tags.register(name)
+    }
+
+    #[inline]
+    pub fn tag(&self, name: &str) -> Option<Tags> {
+        self.tags.get(name)
+    }
+
+    pub fn add_tags(&mut self, entity: Entity, tags: Tags) -> Result<(), Error> {
+        let mut column = self.components_mut::<Tags>()?;
+        match column.get_mut(entity) {
+            Some(value) => {
+                value.insert(tags);
+                Ok(())
+            }
+            None => column.insert(entity, tags),
+        }
+    }
+
+    pub fn remove_tags(&mut self, entity: Entity, tags: Tags) -> Result<(), Error> {
+        let index = self
+            .entities
+            .index(entity)
+            .ok_or(Error::InvalidEntity(entity))?;
+        if let Some(value) = self.components_mut::<Tags>()?.values.get_mut(index) {
+            value.remove(tags);
+        }
+        Ok(())
+    }
+
+    /// Returns true if `entity` has every tag of `tags`.
+    pub fn has_tags(&self, entity: Entity, tags: Tags) -> Result<bool, Error> {
+        let column = self.components_ref::<Tags>()?;
+        let index = self
+            .entities
+            .index(entity)
+            .ok_or(Error::InvalidEntity(entity))?;
+        Ok(column
+            .values
+            .get(index)
+            .copied()
+            .unwrap_or_default()
+            .contains(tags))
+    }
+
+    /// Rewrites the tags of the rows starting at `first_index` with `mapping`, see
+    /// `TagRegistry::merge`.
+    pub(crate) fn remap_tags(&mut self, first_index: usize, mapping: &[Tags]) -> Result<(), Error> {
+        let identity = (0..mapping.len()).map(|bit| Tags(1 << bit));
+        if mapping.iter().copied().eq(identity) {
+            return Ok(());
+        }
+        let mut column = self.components.components_mut::<Tags>(&self.entities)?;
+        for value in column.values.iter_mut().skip(first_index).flatten() {
+            *value = value.remap(mapping);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChunkBuilder;
+
+    #[test]
+    fn names_each_tag_once() {
+        let mut chunk = ChunkBuilder::default().build();
+        let enemy = chunk.register_tag("enemy").unwrap();
+        assert_eq!(chunk.register_tag("flying").unwrap(), Tags::from_bits(2));
+        assert_eq!(chunk.register_tag("enemy").unwrap(), enemy);
+        assert_eq!(chunk.tag("enemy"), Some(enemy));
+        assert_eq!(chunk.tag("boss"), None);
+        for bit in 2..64 {
+            chunk.register_tag(&alloc::format!("tag {bit}")).unwrap();
+        }
+        assert!(matches!(
+            chunk.register_tag("boss"),
+            Err(Error::TooManyTags)
+        ));
+    }
+
+    #[test]
+    fn adds_removes_and_matches_tags() {
+        let mut chunk = ChunkBuilder::default().with_component::<u32>().build();
+        let enemy = chunk.register_tag("enemy").unwrap();
+        let flying = chunk.register_tag("flying").unwrap();
+        let entities = [(); 3].map(|_| chunk.spawn().unwrap());
+        for (value, entity) in entities.into_iter().enumerate() {
+            chunk.add_component(entity, value as u32).unwrap();
+        }
+        chunk.add_tags(entities[0], enemy | flying).unwrap();
+        chunk.add_tags(entities[1], enemy).unwrap();
+        chunk.remove_tags(entities[0], flying).unwrap();
+        assert!(chunk.has_tags(entities[0], enemy).unwrap());
+        assert!(!chunk.has_tags(entities[0], flying).unwrap());
+        assert!(!chunk.has_tags(entities[2], enemy).unwrap());
+
+        let values = chunk.components_ref::<u32>().unwrap();
+        let tags = chunk.components_ref::<Tags>().unwrap();
+        let enemies = (&values).with(tags.matching(enemy)).query().copied();
+        assert_eq!(enemies.collect::<Vec<_>>(), [0, 1]);
+        let others = (&values).without(tags.matching(enemy)).query().copied();
+        assert_eq!(others.collect::<Vec<_>>(), [2]);
+    }
+
+    #[test]
+    fn merging_chunks_remaps_their_tags_by_name() {
+        let mut chunk = ChunkBuilder::default().build();
+        let flying = chunk.register_tag("flying").unwrap();
+        let mut other = ChunkBuilder::default().build();
+        let boss = other.register_tag("boss").unwrap();
+        let other_flying = other.register_tag("flying").unwrap();
+        let entity = other.spawn().unwrap();
+        other.add_tags(entity, boss | other_flying).unwrap();
+
+        let entity = chunk.merge(other).unwrap().map(entity);
+        let boss = chunk.tag("boss").unwrap();
+        assert_eq!(boss, Tags::from_bits(2));
+        assert!(chunk.has_tags(entity, boss | flying).unwrap());
+    }
+}
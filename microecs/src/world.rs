@@ -0,0 +1,146 @@
+use crate::entities::Entity;
+use crate::{Chunk, Error};
+use alloc::vec::Vec;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChunkId(usize);
+
+/// A set of chunks that entities can be moved between.
+#[derive(Default)]
+pub struct World {
+    chunks: Vec<Chunk>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_chunk(&mut self, chunk: Chunk) -> ChunkId {
+        self.chunks.push(chunk);
+        ChunkId(self.chunks.len() - 1)
+    }
+
+    #[inline]
+    pub fn chunk(&self, id: ChunkId) -> Result<&Chunk, Error> {
+        self.chunks.get(id.0).ok_or(Error::ChunkNotFound(id))
+    }
+
+    #[inline]
+    pub fn chunk_mut(&mut self, id: ChunkId) -> Result<&mut Chunk, Error> {
+        self.chunks.get_mut(id.0).ok_or(Error::ChunkNotFound(id))
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = (ChunkId, &Chunk)> + use<'_> {
+        self.chunks.iter().enumerate().map(|(i, c)| (ChunkId(i), c))
+    }
+
+    /// Moves `entity` from `source` into `destination`. See `Chunk::move_entity`.
+    pub fn move_entity(
+        &mut self,
+        source: ChunkId,
+        destination: ChunkId,
+        entity: Entity,
+    ) -> Result<Entity, Error> {
+        self.chunk(destination)?;
+        self.chunk(source)?
+            .entities
+            .index(entity)
+            .ok_or(Error::InvalidEntity(entity))?;
+        if source == destination {
+            return Ok(entity);
+        }
+        let (source, destination) = if source.0 < destination.0 {
+            let (left, right) = self.chunks.split_at_mut(destination.0);
+            (&mut left[source.0], &mut right[0])
+        } else {
+            let (left, right) = self.chunks.split_at_mut(source.0);
+            (&mut right[0], &mut left[destination.0])
+        };
+        source.move_entity(destination, entity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChunkBuilder;
+
+    struct Position(u32);
+
+    fn world() -> (World, ChunkId, ChunkId) {
+        let mut world = World::new();
+        let chunk = || ChunkBuilder::default().with_component::<Position>().build();
+        let source = world.add_chunk(chunk());
+        let destination = world.add_chunk(chunk());
+        (world, source, destination)
+    }
+
+    #[test]
+    fn moves_entities_with_their_components() {
+        let (mut world, source, destination) = world();
+        let chunk = world.chunk_mut(source).unwrap();
+        let entity = chunk.spawn().unwrap();
+        chunk.add_component(entity, Position(3)).unwrap();
+
+        let moved = world.move_entity(source, destination, entity).unwrap();
+        assert!(world.chunk(source).unwrap().entity(entity).is_err());
+        let chunk = world.chunk(destination).unwrap();
+        let positions = chunk.components_ref::<Position>().unwrap();
+        assert_eq!(positions.get(moved).map(|p| p.0), Some(3));
+    }
+
+    #[test]
+    fn fails_for_entities_of_other_chunks() {
+        let (mut world, source, destination) = world();
+        let entity = world.chunk_mut(destination).unwrap().spawn().unwrap();
+        let moved = world.move_entity(source, source, entity);
+        assert!(matches!(moved, Err(Error::InvalidEntity(e)) if e == entity));
+        let moved = world.move_entity(source, destination, entity);
+        assert!(matches!(moved, Err(Error::InvalidEntity(_))));
+        let moved = world.move_entity(destination, destination, entity);
+        assert_eq!(moved.unwrap(), entity);
+    }
+
+    #[test]
+    fn fails_for_components_missing_from_the_destination() {
+        let mut world = World::new();
+        let source = world.add_chunk(ChunkBuilder::default().with_component::<Position>().build());
+        let destination = world.add_chunk(ChunkBuilder::default().build());
+        let chunk = world.chunk_mut(source).unwrap();
+        let entity = chunk.spawn().unwrap();
+        chunk.add_component(entity, Position(3)).unwrap();
+
+        let moved = world.move_entity(source, destination, entity);
+        assert!(matches!(moved, Err(Error::ComponentNotRegistered(_))));
+        // the entity is left as it was
+        let chunk = world.chunk(source).unwrap();
+        let positions = chunk.components_ref::<Position>().unwrap();
+        assert_eq!(positions.get(entity).map(|p| p.0), Some(3));
+    }
+
+    #[test]
+    fn carries_names_and_tags_over() {
+        let (mut world, source, destination) = world();
+        world
+            .chunk_mut(destination)
+            .unwrap()
+            .register_tag("red")
+            .unwrap();
+        let chunk = world.chunk_mut(source).unwrap();
+        let blue = chunk.register_tag("blue").unwrap();
+        let entity = chunk.spawn().unwrap();
+        chunk.set_name(entity, "player").unwrap();
+        chunk.add_tags(entity, blue).unwrap();
+
+        let moved = world.move_entity(source, destination, entity).unwrap();
+        assert_eq!(world.chunk(source).unwrap().entity_by_name("player"), None);
+        let chunk = world.chunk(destination).unwrap();
+        assert_eq!(chunk.entity_by_name("player"), Some(moved));
+        // the tag is given a bit of its own in the destination
+        let blue = chunk.tag("blue").unwrap();
+        let red = chunk.tag("red").unwrap();
+        assert!(chunk.has_tags(moved, blue).unwrap());
+        assert!(!chunk.has_tags(moved, red).unwrap());
+    }
+}
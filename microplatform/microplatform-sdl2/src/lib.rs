@@ -4,18 +4,36 @@ use microplatform::Result;
 use sdl2_sys::*;
 use std::ffi::c_char;
 use std::ffi::c_int;
+use std::ffi::c_void;
 use std::ffi::CStr;
 use std::ffi::CString;
+use std::rc::Rc;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
 
+const WHITE: SDL_Color = SDL_Color {
+    r: 255,
+    g: 255,
+    b: 255,
+    a: 255,
+};
+
 static IS_SDL2_INITIALIZED: AtomicBool = AtomicBool::new(false);
 
+// the 32-bit glyph API of SDL_ttf 2.0.18, which sdl2-sys does not declare
+extern "C" {
+    fn TTF_GlyphIsProvided32(font: *mut ttf::TTF_Font, ch: u32) -> c_int;
+    fn TTF_GetFontKerningSizeGlyphs32(font: *mut ttf::TTF_Font, previous_ch: u32, ch: u32)
+        -> c_int;
+}
+
 pub struct BackendSDL2 {
     window: *mut SDL_Window,
     renderer: *mut SDL_Renderer,
     textures: Vec<Option<*mut SDL_Texture>>,
     fonts: Vec<Option<*mut ttf::TTF_Font>>,
+    // the data fonts are read from, shared by the sizes of a font
+    font_files: Vec<Option<Rc<[u8]>>>,
 }
 
 impl BackendSDL2 {
@@ -43,13 +61,13 @@ impl BackendSDL2 {
                 | WindowConfig::Borderless(physical_size) => {
                     (physical_size.width, physical_size.height)
                 }
-                WindowConfig::Fullscreen { .. } => (0, 0),
+                WindowConfig::Fullscreen => (0, 0),
             };
 
             let window_flags = match config {
                 WindowConfig::Bordered { .. } => SDL_WindowFlags::SDL_WINDOW_SHOWN,
                 WindowConfig::Borderless(..) => SDL_WindowFlags::SDL_WINDOW_BORDERLESS,
-                WindowConfig::Fullscreen { .. } => SDL_WindowFlags::SDL_WINDOW_FULLSCREEN,
+                WindowConfig::Fullscreen => SDL_WindowFlags::SDL_WINDOW_FULLSCREEN,
             };
 
             let window = SDL_CreateWindow(
@@ -65,18 +83,15 @@ impl BackendSDL2 {
                 return Err(sdl_error());
             }
 
-            match config {
-                WindowConfig::Bordered { resizable, .. } => {
-                    SDL_SetWindowResizable(
-                        window,
-                        if resizable {
-                            SDL_bool::SDL_TRUE
-                        } else {
-                            SDL_bool::SDL_FALSE
-                        },
-                    );
-                }
-                _ => {}
+            if let WindowConfig::Bordered { resizable, .. } = config {
+                SDL_SetWindowResizable(
+                    window,
+                    if resizable {
+                        SDL_bool::SDL_TRUE
+                    } else {
+                        SDL_bool::SDL_FALSE
+                    },
+                );
             }
 
             let renderer = SDL_CreateRenderer(
@@ -110,10 +125,134 @@ impl BackendSDL2 {
                 renderer,
                 textures: Vec::with_capacity(32),
                 fonts: Vec::with_capacity(32),
+                font_files: Vec::with_capacity(32),
             })
         }
     }
 
+    fn font(&self, font: FontId) -> Result<*mut ttf::TTF_Font> {
+        let font = self
+            .fonts
+            .get(font.0 as usize)
+            .ok_or(String::from("Font was never registered"))?;
+        font.ok_or(String::from("Font was already deleted."))
+    }
+
+    fn open_font(&mut self, file: Rc<[u8]>, scale: u8) -> Result<FontData> {
+        let (font, height, metrics) = unsafe {
+            let src = SDL_RWFromConstMem(file.as_ptr() as *const c_void, file.len() as c_int);
+            if src.is_null() {
+                return Err(sdl_error());
+            }
+
+            let font = ttf::TTF_OpenFontRW(src, 1, scale as c_int);
+            if (font as *mut ()).is_null() {
+                return Err(sdl_error());
+            }
+
+            let height = ttf::TTF_FontHeight(font);
+            let metrics = FontMetrics {
+                ascent: ttf::TTF_FontAscent(font),
+                descent: ttf::TTF_FontDescent(font),
+                line_gap: ttf::TTF_FontLineSkip(font) - height,
+            };
+            (font, height as u32, metrics)
+        };
+
+        let id = self.fonts.len();
+        self.fonts.push(Some(font));
+        self.font_files.push(Some(file));
+        Ok(FontData {
+            id: FontId(id as u32),
+            glyphs_height: height,
+            metrics,
+        })
+    }
+
+    /// Copies a white surface rendered by SDL_ttf to the target at `origin` and frees it.
+    unsafe fn render_text_surface(
+        &mut self,
+        font_glyph_surface: *mut SDL_Surface,
+        origin: Point,
+    ) -> Result {
+        if (font_glyph_surface as *mut ()).is_null() {
+            return Err(sdl_error());
+        }
+
+        let glyph_surface = SDL_ConvertSurfaceFormat(
+            font_glyph_surface,
+            SDL_PixelFormatEnum::SDL_PIXELFORMAT_RGBA8888 as u32,
+            0u32,
+        );
+
+        if (glyph_surface as *mut ()).is_null() {
+            SDL_FreeSurface(font_glyph_surface);
+            return Err(sdl_error());
+        }
+
+        let width = (*glyph_surface).w;
+        let height = (*glyph_surface).h;
+
+        // we create a surface with the equal dimensions (w == h) and copy the contents from
+        // the glyph to it. SDL might stretch the surface into a texture with width == height
+        // if it's too small.
+        let glyph_texture = {
+            let dimensions = width.max(height) as u32;
+            let pixels = (*glyph_surface).pixels;
+            let pitch = (*glyph_surface).pitch;
+
+            let texture = match self.create_raw_sdl_target_texture(dimensions, dimensions) {
+                Ok(texture) => texture,
+                Err(err) => {
+                    SDL_FreeSurface(font_glyph_surface);
+                    SDL_FreeSurface(glyph_surface);
+                    return Err(err);
+                }
+            };
+
+            let rect = SDL_Rect {
+                x: 0,
+                y: 0,
+                w: width,
+                h: height,
+            };
+
+            if SDL_SetTextureBlendMode(texture, SDL_BlendMode::SDL_BLENDMODE_NONE) != 0
+                || SDL_UpdateTexture(texture, &rect, pixels, pitch) != 0
+            {
+                SDL_FreeSurface(font_glyph_surface);
+                SDL_FreeSurface(glyph_surface);
+                return Err(sdl_error());
+            }
+
+            texture
+        };
+
+        let src_rect = SDL_Rect {
+            x: 0,
+            y: 0,
+            w: width,
+            h: height,
+        };
+        let dest_rect = SDL_Rect {
+            x: origin.x,
+            y: origin.y,
+            w: width,
+            h: height,
+        };
+
+        let ok = SDL_RenderCopy(self.renderer, glyph_texture, &src_rect, &dest_rect) == 0;
+
+        SDL_DestroyTexture(glyph_texture);
+        SDL_FreeSurface(font_glyph_surface);
+        SDL_FreeSurface(glyph_surface);
+
+        if !ok {
+            return Err(sdl_error());
+        }
+        Ok(())
+    }
+
     fn create_raw_sdl_target_texture(&mut self, w: u32, h: u32) -> Result<*mut SDL_Texture> {
         const ZEROES: &[u8] = &[0_u8; 4 * 2048 * 2048];
 
@@ -152,7 +291,7 @@ impl Backend for BackendSDL2 {
             | WindowConfig::Borderless(physical_size) => {
                 (physical_size.width, physical_size.height)
             }
-            WindowConfig::Fullscreen { .. } => (0, 0),
+            WindowConfig::Fullscreen => (0, 0),
         };
 
         unsafe {
@@ -173,7 +312,7 @@ impl Backend for BackendSDL2 {
                     SDL_SetWindowSize(self.window, window_width as c_int, window_height as c_int);
                     SDL_SetWindowBordered(self.window, SDL_bool::SDL_FALSE);
                 }
-                WindowConfig::Fullscreen { .. } => {
+                WindowConfig::Fullscreen => {
                     SDL_SetWindowFullscreen(
                         self.window,
                         SDL_WindowFlags::SDL_WINDOW_FULLSCREEN as u32,
@@ -259,27 +398,18 @@ impl Backend for BackendSDL2 {
             return Err(String::from("File does not exist."));
         }
 
-        let c_str = CString::new(path).map_err(|e| e.to_string())?;
-        let c_str_ptr = c_str.as_ptr();
-
-        let (font, height) = unsafe {
-            let font = ttf::TTF_OpenFont(c_str_ptr, scale as i32);
-            if (font as *mut ()).is_null() {
-                return Err(sdl_error());
-            }
-
-            let height = ttf::TTF_FontHeight(font) as u32;
-            (font, height)
-        };
-
-        drop(c_str);
+        let file = std::fs::read(path).map_err(|e| e.to_string())?;
+        self.open_font(file.into(), scale)
+    }
 
-        let id = self.fonts.len();
-        self.fonts.push(Some(font));
-        Ok(FontData {
-            id: FontId(id as u32),
-            glyphs_height: height,
-        })
+    fn font_load_size(&mut self, font: FontId, scale: u8) -> Result<FontData> {
+        let file = self
+            .font_files
+            .get(font.0 as usize)
+            .ok_or(String::from("Font was never registered"))?
+            .clone()
+            .ok_or(String::from("Font was already deleted."))?;
+        self.open_font(file, scale)
     }
 
     fn font_destroy(&mut self, id: FontId) -> Result {
@@ -290,16 +420,31 @@ impl Backend for BackendSDL2 {
             return Ok(());
         };
         unsafe { ttf::TTF_CloseFont(font) };
+        self.font_files[id.0 as usize].take();
         Ok(())
     }
 
-    fn font_glyph_metrics(&mut self, font: FontId, glyph: char) -> Result<GlyphMetrics> {
+    fn font_has_glyph(&mut self, font: FontId, glyph: char) -> Result<bool> {
         let font = self
             .fonts
             .get(font.0 as usize)
             .ok_or(String::from("Font was never registered"))?;
         let font = font.ok_or(String::from("Font was already deleted."))?;
 
+        Ok(unsafe { TTF_GlyphIsProvided32(font, u32::from(glyph)) } != 0)
+    }
+
+    fn font_glyph_metrics(&mut self, font_id: FontId, glyph: char) -> Result<GlyphMetrics> {
+        // the 16-bit glyph API cannot reach chars beyond the basic multilingual plane
+        let Ok(glyph) = u16::try_from(u32::from(glyph)) else {
+            return self.font_cluster_metrics(font_id, glyph.encode_utf8(&mut [0; 4]));
+        };
+        let font = self
+            .fonts
+            .get(font_id.0 as usize)
+            .ok_or(String::from("Font was never registered"))?;
+        let font = font.ok_or(String::from("Font was already deleted."))?;
+
         let mut min_x = 0;
         let mut max_x = 0;
         let mut min_y = 0;
@@ -309,7 +454,7 @@ impl Backend for BackendSDL2 {
         let ret = unsafe {
             ttf::TTF_GlyphMetrics(
                 font,
-                glyph as u16,
+                glyph,
                 &mut min_x,
                 &mut max_x,
                 &mut min_y,
@@ -331,6 +476,37 @@ impl Backend for BackendSDL2 {
         })
     }
 
+    fn font_cluster_metrics(&mut self, font: FontId, cluster: &str) -> Result<GlyphMetrics> {
+        let font = self.font(font)?;
+        let cluster = CString::new(cluster).map_err(|e| e.to_string())?;
+
+        let mut width = 0;
+        let mut height = 0;
+        if unsafe { ttf::TTF_SizeUTF8(font, cluster.as_ptr(), &mut width, &mut height) } != 0 {
+            return Err(String::from("Unable to calculate cluster metrics."));
+        }
+
+        Ok(GlyphMetrics {
+            min_x: 0,
+            max_x: width,
+            min_y: 0,
+            max_y: height,
+            advance: width as u32,
+        })
+    }
+
+    fn font_glyph_kerning(&mut self, font: FontId, previous: char, glyph: char) -> Result<i32> {
+        let font = self
+            .fonts
+            .get(font.0 as usize)
+            .ok_or(String::from("Font was never registered"))?;
+        let font = font.ok_or(String::from("Font was already deleted."))?;
+
+        let kerning =
+            unsafe { TTF_GetFontKerningSizeGlyphs32(font, u32::from(previous), u32::from(glyph)) };
+        Ok(kerning)
+    }
+
     fn render_set_logical_size(&mut self, w: u32, h: u32) -> Result {
         unsafe {
             if SDL_RenderSetLogicalSize(self.renderer, w as i32, h as i32) != 0 {
@@ -348,8 +524,7 @@ impl Backend for BackendSDL2 {
                     .textures
                     .get(index)
                     .ok_or(String::from("Texture was never created."))?;
-                let texture = texture
-                    .clone()
+                let texture = (*texture)
                     .ok_or(String::from("Texture was already deleted."))?;
                 unsafe {
                     if SDL_SetRenderTarget(self.renderer, texture) != 0 {
@@ -394,8 +569,7 @@ impl Backend for BackendSDL2 {
             .textures
             .get(texture.0 as usize)
             .ok_or(String::from("Texture was never created."))?;
-        let texture = texture
-            .clone()
+        let texture = (*texture)
             .ok_or(String::from("Texture was already deleted."))?;
         let src = options.src.map(rect_to_sdl_rect);
         let src = src
@@ -469,102 +643,42 @@ impl Backend for BackendSDL2 {
         Ok(())
     }
 
-    fn render_font_glyph(&mut self, font: FontId, glyph: char, origin: Point) -> Result {
+    fn render_clear_rect(&mut self, rect: Rect) -> Result {
+        let rect = rect_to_sdl_rect(rect);
+        self.render_set_draw_color(Color::new(0, 0, 0, 0))?;
         unsafe {
-            let font = self
-                .fonts
-                .get_mut(font.0 as usize)
-                .ok_or(String::from("Font was never created."))?
-                .ok_or(String::from("Font was already deleted."))?;
-
-            let font_glyph_surface = ttf::TTF_RenderGlyph_Blended(
-                font,
-                glyph as u16,
-                SDL_Color {
-                    r: 255,
-                    g: 255,
-                    b: 255,
-                    a: 255,
-                },
-            );
-
-            if (font_glyph_surface as *mut ()).is_null() {
+            if SDL_SetRenderDrawBlendMode(self.renderer, SDL_BlendMode::SDL_BLENDMODE_NONE) != 0 {
                 return Err(sdl_error());
             }
-
-            let glyph_surface = SDL_ConvertSurfaceFormat(
-                font_glyph_surface,
-                SDL_PixelFormatEnum::SDL_PIXELFORMAT_RGBA8888 as u32,
-                0u32,
-            );
-
-            if (glyph_surface as *mut ()).is_null() {
-                SDL_FreeSurface(font_glyph_surface);
+            let filled = SDL_RenderFillRect(self.renderer, &rect);
+            if SDL_SetRenderDrawBlendMode(self.renderer, SDL_BlendMode::SDL_BLENDMODE_BLEND) != 0
+                || filled != 0
+            {
                 return Err(sdl_error());
             }
+        }
+        Ok(())
+    }
 
-            let width = (*glyph_surface).w;
-            let height = (*glyph_surface).h;
-
-            // we create a surface with the equal dimensions (w == h) and copy the contents from
-            // the glyph to it. SDL might stretch the surface into a texture with width == height
-            // if it's too small.
-            let glyph_texture = {
-                let dimensions = width.max(height) as u32;
-                let pixels = (*glyph_surface).pixels;
-                let pitch = (*glyph_surface).pitch;
-
-                let texture = match self.create_raw_sdl_target_texture(dimensions, dimensions) {
-                    Ok(texture) => texture,
-                    Err(err) => {
-                        SDL_FreeSurface(font_glyph_surface);
-                        SDL_FreeSurface(glyph_surface);
-                        return Err(err);
-                    }
-                };
-
-                let rect = SDL_Rect {
-                    x: 0,
-                    y: 0,
-                    w: width,
-                    h: height,
-                };
-
-                if SDL_SetTextureBlendMode(texture, SDL_BlendMode::SDL_BLENDMODE_NONE) != 0
-                    || SDL_UpdateTexture(texture, &rect, pixels, pitch) != 0
-                {
-                    SDL_FreeSurface(font_glyph_surface);
-                    SDL_FreeSurface(glyph_surface);
-                    return Err(sdl_error());
-                }
-
-                texture
-            };
-
-            let src_rect = SDL_Rect {
-                x: 0,
-                y: 0,
-                w: width,
-                h: height,
-            };
-            let dest_rect = SDL_Rect {
-                x: origin.x,
-                y: origin.y,
-                w: width,
-                h: height,
-            };
-
-            let ok = SDL_RenderCopy(self.renderer, glyph_texture, &src_rect, &dest_rect) == 0;
-
-            SDL_DestroyTexture(glyph_texture);
-            SDL_FreeSurface(font_glyph_surface);
-
-            if !ok {
-                return Err(sdl_error());
-            }
+    fn render_font_glyph(&mut self, font_id: FontId, glyph: char, origin: Point) -> Result {
+        // as in `font_glyph_metrics`
+        let Ok(glyph) = u16::try_from(u32::from(glyph)) else {
+            return self.render_font_cluster(font_id, glyph.encode_utf8(&mut [0; 4]), origin);
+        };
+        let font = self.font(font_id)?;
+        unsafe {
+            let surface = ttf::TTF_RenderGlyph_Blended(font, glyph, WHITE);
+            self.render_text_surface(surface, origin)
         }
+    }
 
-        Ok(())
+    fn render_font_cluster(&mut self, font: FontId, cluster: &str, origin: Point) -> Result {
+        let font = self.font(font)?;
+        let cluster = CString::new(cluster).map_err(|e| e.to_string())?;
+        unsafe {
+            let surface = ttf::TTF_RenderUTF8_Blended(font, cluster.as_ptr(), WHITE);
+            self.render_text_surface(surface, origin)
+        }
     }
 
     fn events_pump(&mut self, events: &mut Vec<Event>) {
@@ -654,15 +768,15 @@ unsafe fn sdl_error() -> String {
 
 fn key_sym_to_key(keycode: u32) -> Option<Key> {
     if keycode == SDL_KeyCode::SDLK_a as u32 {
-        return Some(Key::A);
+        Some(Key::A)
     } else if keycode == SDL_KeyCode::SDLK_d as u32 {
-        return Some(Key::D);
+        Some(Key::D)
     } else if keycode == SDL_KeyCode::SDLK_w as u32 {
-        return Some(Key::W);
+        Some(Key::W)
     } else if keycode == SDL_KeyCode::SDLK_s as u32 {
-        return Some(Key::S);
+        Some(Key::S)
     } else {
-        return Option::None;
+        Option::None
     }
 }
 
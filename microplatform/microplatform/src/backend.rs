@@ -10,8 +10,16 @@ pub trait Backend {
     fn texture_destroy(&mut self, id: TextureId) -> Result;
 
     fn font_load(&mut self, path: &str, scale: u8) -> Result<FontData>;
+    /// Loads `font` again at another size as a font of its own, sharing its data.
+    fn font_load_size(&mut self, font: FontId, scale: u8) -> Result<FontData>;
     fn font_destroy(&mut self, id: FontId) -> Result;
+    fn font_has_glyph(&mut self, font: FontId, glyph: char) -> Result<bool>;
     fn font_glyph_metrics(&mut self, font: FontId, glyph: char) -> Result<GlyphMetrics>;
+    /// The metrics of a grapheme cluster of several chars, e.g. an emoji sequence, drawn as one.
+    fn font_cluster_metrics(&mut self, font: FontId, cluster: &str) -> Result<GlyphMetrics>;
+    /// The adjustment of the advance of `previous` when it is followed by `glyph`, e.g. negative
+    /// for "AV".
+    fn font_glyph_kerning(&mut self, font: FontId, previous: char, glyph: char) -> Result<i32>;
 
     fn render_set_logical_size(&mut self, w: u32, h: u32) -> Result;
     fn render_set_target(&mut self, target: Option<TextureId>) -> Result;
@@ -21,7 +29,10 @@ pub trait Backend {
     fn render_copy_texture(&mut self, texture: TextureId, options: CopyTextureOptions) -> Result;
     fn render_fill_rect(&mut self, rect: Option<Rect>, color: Color) -> Result;
     fn render_draw_rect(&mut self, rect: Option<Rect>, color: Color) -> Result;
+    /// Makes the pixels of `rect` transparent, replacing them rather than blending over them.
+    fn render_clear_rect(&mut self, rect: Rect) -> Result;
     fn render_font_glyph(&mut self, font: FontId, glyph: char, origin: Point) -> Result;
+    fn render_font_cluster(&mut self, font: FontId, cluster: &str, origin: Point) -> Result;
 
     fn events_pump(&mut self, events: &mut Vec<Event>);
 
@@ -0,0 +1,328 @@
+use crate::text::{graphemes, Line};
+use alloc::vec::Vec;
+
+/// Text with the embedding levels of its grapheme clusters resolved by the implicit rules of the
+/// Unicode bidirectional algorithm (UAX #9), to draw lines of it in visual order.
+///
+/// Every paragraph, ended by `'\n'`, takes its direction from its first strong char. Explicit
+/// embeddings, overrides and isolates are not supported, their formatting chars being neutrals,
+/// and the class of a char is approximated from its Unicode block.
+pub struct Bidi<'a> {
+    // the byte index, cluster and level of every cluster
+    clusters: Vec<(usize, &'a str, u8)>,
+    // the byte index and level of every paragraph
+    paragraphs: Vec<(usize, u8)>,
+}
+
+impl<'a> Bidi<'a> {
+    pub fn new(text: &'a str) -> Self {
+        let clusters = graphemes(text).collect::<Vec<_>>();
+        let mut bidi = Self {
+            clusters: Vec::with_capacity(clusters.len()),
+            paragraphs: Vec::new(),
+        };
+        for paragraph in clusters.split_inclusive(|(_, c)| c.ends_with('\n')) {
+            let classes = paragraph
+                .iter()
+                .map(|(_, c)| class(c.chars().next().unwrap()))
+                .collect::<Vec<_>>();
+            let level = match classes.iter().find(|c| matches!(c, L | R | AL)) {
+                Some(R | AL) => 1,
+                _ => 0,
+            };
+            bidi.paragraphs.push((paragraph[0].0, level));
+            let levels = resolve_levels(classes, level);
+            bidi.clusters.extend(
+                paragraph
+                    .iter()
+                    .zip(levels)
+                    .map(|(&(i, c), level)| (i, c, level)),
+            );
+        }
+        bidi
+    }
+
+    /// True if the paragraph containing the byte index `index` is right to left.
+    pub fn is_rtl(&self, index: usize) -> bool {
+        self.paragraph_level(index) % 2 == 1
+    }
+
+    /// True if the cluster at the byte index `index` is drawn right to left.
+    pub fn is_rtl_cluster(&self, index: usize) -> bool {
+        let cluster = self.clusters.partition_point(|c| c.0 <= index);
+        cluster
+            .checked_sub(1)
+            .is_some_and(|c| self.clusters[c].2 % 2 == 1)
+    }
+
+    fn paragraph_level(&self, index: usize) -> u8 {
+        let paragraph = self
+            .paragraphs
+            .partition_point(|&(start, _)| start <= index);
+        paragraph.checked_sub(1).map_or(0, |p| self.paragraphs[p].1)
+    }
+
+    /// The clusters of `line`, a line of the text, from left to right with their byte indexes.
+    /// Brackets in right to left runs are mirrored. The hyphen of a hyphenated line is put at its
+    /// end in the direction of the paragraph, with the byte index of the last cluster.
+    pub fn visual_order(&self, line: &Line<'a>) -> Vec<(usize, &'a str)> {
+        let end = line.start + line.text.len();
+        let first = self.clusters.partition_point(|c| c.0 < line.start);
+        let last = self.clusters.partition_point(|c| c.0 < end);
+        let level = self.paragraph_level(line.start);
+
+        let mut clusters = self.clusters[first..last].to_vec();
+        // trailing whitespace takes the paragraph level (L1)
+        for cluster in clusters.iter_mut().rev() {
+            if !cluster.1.starts_with(char::is_whitespace) {
+                break;
+            }
+            cluster.2 = level;
+        }
+        if let Some(&(index, ..)) = clusters.last().filter(|_| line.hyphenated) {
+            clusters.push((index, "-", level));
+        }
+
+        // reverse every run at or above each odd level, from the highest down (L2)
+        let highest = clusters.iter().map(|c| c.2).max().unwrap_or(0);
+        let lowest_odd = clusters.iter().map(|c| c.2 | 1).min().unwrap_or(1);
+        for level in (lowest_odd..=highest).rev() {
+            let mut i = 0;
+            while i < clusters.len() {
+                let run = clusters[i..].iter().take_while(|c| c.2 >= level).count();
+                clusters[i..i + run].reverse();
+                i += run.max(1);
+            }
+        }
+
+        clusters
+            .into_iter()
+            .map(|(i, c, level)| match level % 2 {
+                1 => (i, mirror(c)),
+                _ => (i, c),
+            })
+            .collect()
+    }
+}
+
+/// The bidirectional character types the implicit rules need.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Class {
+    L,
+    R,
+    AL,
+    EN,
+    ES,
+    ET,
+    AN,
+    CS,
+    WS,
+    ON,
+}
+
+use Class::*;
+
+fn class(c: char) -> Class {
+    match c as u32 {
+        0x30..=0x39 | 0xB2 | 0xB3 | 0xB9 | 0x6F0..=0x6F9 => EN,
+        0x660..=0x669 | 0x66B | 0x66C => AN,
+        0x2B | 0x2D => ES,
+        0x23..=0x25 | 0xA2..=0xA5 | 0xB0 | 0xB1 | 0x20A0..=0x20CF => ET,
+        0x2C | 0x2E | 0x2F | 0x3A | 0xA0 => CS,
+        0x590..=0x5FF | 0x7C0..=0x85F | 0xFB1D..=0xFB4F | 0x10800..=0x10FFF => R,
+        0x1E800..=0x1EFFF => R,
+        0x600..=0x7BF | 0x860..=0x8FF | 0xFB50..=0xFDFF | 0xFE70..=0xFEFF => AL,
+        _ if c.is_whitespace() => WS,
+        _ if c.is_alphanumeric() => L,
+        _ => ON,
+    }
+}
+
+/// Resolves the levels of a paragraph of `classes` at the paragraph `level` (W1-W7, N1, N2, I1
+/// and I2).
+fn resolve_levels(mut classes: Vec<Class>, level: u8) -> Vec<u8> {
+    let embedding = match level % 2 {
+        1 => R,
+        _ => L,
+    };
+
+    // W2, W3
+    let mut strong = embedding;
+    for class in classes.iter_mut() {
+        match *class {
+            L | R => strong = *class,
+            AL => {
+                strong = AL;
+                *class = R;
+            }
+            EN if strong == AL => *class = AN,
+            _ => {}
+        }
+    }
+
+    // W4
+    for i in 1..classes.len().saturating_sub(1) {
+        let (before, after) = (classes[i - 1], classes[i + 1]);
+        classes[i] = match (before, classes[i], after) {
+            (EN, ES, EN) => EN,
+            (EN, CS, EN) => EN,
+            (AN, CS, AN) => AN,
+            (_, class, _) => class,
+        };
+    }
+
+    // W5, W6
+    let mut i = 0;
+    while i < classes.len() {
+        let run = classes[i..].iter().take_while(|&&c| c == ET).count();
+        if run > 0 {
+            let next_to_number =
+                (i > 0 && classes[i - 1] == EN) || classes.get(i + run) == Some(&EN);
+            let class = if next_to_number { EN } else { ON };
+            classes[i..i + run].fill(class);
+        } else if matches!(classes[i], ES | CS) {
+            classes[i] = ON;
+        }
+        i += run.max(1);
+    }
+
+    // W7
+    let mut strong = embedding;
+    for class in classes.iter_mut() {
+        match *class {
+            L | R => strong = *class,
+            EN if strong == L => *class = L,
+            _ => {}
+        }
+    }
+
+    // N1, N2, where numbers count as right to left
+    let direction = |class: Class| match class {
+        L => Some(L),
+        R | EN | AN => Some(R),
+        _ => None,
+    };
+    let mut i = 0;
+    while i < classes.len() {
+        let run = classes[i..]
+            .iter()
+            .take_while(|&&c| direction(c).is_none())
+            .count();
+        if run > 0 {
+            let before = i
+                .checked_sub(1)
+                .map_or(embedding, |b| direction(classes[b]).unwrap());
+            let after = classes
+                .get(i + run)
+                .map_or(embedding, |&c| direction(c).unwrap());
+            let class = if before == after { before } else { embedding };
+            classes[i..i + run].fill(class);
+        }
+        i += run.max(1);
+    }
+
+    // I1, I2
+    classes
+        .into_iter()
+        .map(|class| match (level % 2, class) {
+            (0, R) => level + 1,
+            (0, EN | AN) => level + 2,
+            (1, L | EN | AN) => level + 1,
+            _ => level,
+        })
+        .collect()
+}
+
+/// The pair of clusters `previous` and `glyph` in the order they are drawn, from left to right,
+/// mirrored if `glyph` is drawn right to left.
+pub(crate) fn visual_pair<'t>(previous: &'t str, glyph: &'t str, rtl: bool) -> (&'t str, &'t str) {
+    match rtl {
+        true => (mirror(glyph), mirror(previous)),
+        false => (previous, glyph),
+    }
+}
+
+pub(crate) fn mirror(cluster: &str) -> &str {
+    match cluster {
+        "(" => ")",
+        ")" => "(",
+        "[" => "]",
+        "]" => "[",
+        "{" => "}",
+        "}" => "{",
+        "<" => ">",
+        ">" => "<",
+        "«" => "»",
+        "»" => "«",
+        _ => cluster,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn visual(text: &str) -> String {
+        let line = Line {
+            text,
+            start: 0,
+            width: 0,
+            hyphenated: false,
+        };
+        Bidi::new(text)
+            .visual_order(&line)
+            .into_iter()
+            .map(|(_, c)| c)
+            .collect()
+    }
+
+    #[test]
+    fn keeps_left_to_right_text() {
+        assert_eq!(visual("hello, world"), "hello, world");
+    }
+
+    #[test]
+    fn reverses_right_to_left_text() {
+        assert_eq!(visual("אבג דהו"), "והד גבא");
+        assert!(Bidi::new("אבג").is_rtl(0));
+        assert!(!Bidi::new("abc אבג").is_rtl(0));
+    }
+
+    #[test]
+    fn reorders_mixed_lines() {
+        assert_eq!(visual("abc אבג def"), "abc גבא def");
+        assert_eq!(visual("אבג abc דהו"), "והד abc גבא");
+    }
+
+    #[test]
+    fn keeps_numbers_left_to_right() {
+        assert_eq!(visual("אבג 123 דה"), "הד 123 גבא");
+        assert_eq!(visual("א 1.5"), "1.5 א");
+    }
+
+    #[test]
+    fn mirrors_brackets() {
+        assert_eq!(visual("אב (גד)"), "(דג) בא");
+    }
+
+    #[test]
+    fn resolves_paragraphs_separately() {
+        let text = "אב\nab";
+        let bidi = Bidi::new(text);
+        assert!(bidi.is_rtl(0));
+        assert!(!bidi.is_rtl(text.find('a').unwrap()));
+    }
+
+    #[test]
+    fn puts_hyphens_at_the_end_of_the_line() {
+        let text = "אבג";
+        let line = Line {
+            text,
+            start: 0,
+            width: 0,
+            hyphenated: true,
+        };
+        let visual = Bidi::new(text).visual_order(&line);
+        assert_eq!(visual.first(), Some(&(4, "-")));
+    }
+}
@@ -1,9 +1,10 @@
 use crate::font::Font;
+use crate::rich_text::{self, FontFamily, RichText, RichTextOptions};
 use crate::texture::Texture;
 use crate::types::CopyTextureOptions;
 use crate::{
-    BackendRef, Color, FontId, GlyphMetrics, Point, Rect, Result, TextAlign, TextCrossAlign,
-    TextPadding,
+    BackendRef, Color, Dimensions, FontId, GlyphMetrics, Point, Rect, Result, TextBounds,
+    TextOptions, TextSpacing,
 };
 use alloc::rc::Rc;
 use alloc::string::String;
@@ -11,15 +12,24 @@ use alloc::string::String;
 pub struct Canvas<'a> {
     backend: BackendRef,
     target: Option<&'a mut Texture>,
+    frame: u64,
 }
 
 impl<'a> Canvas<'a> {
-    pub(crate) fn new(backend: &BackendRef, target: Option<&'a mut Texture>) -> Result<Self> {
+    pub(crate) fn new(
+        backend: &BackendRef,
+        target: Option<&'a mut Texture>,
+        frame: u64,
+    ) -> Result<Self> {
         let backend = Rc::clone(backend);
         backend
             .borrow_mut()
             .render_set_target(target.as_ref().map(|t| t.id))?;
-        Ok(Self { target, backend })
+        Ok(Self {
+            target,
+            backend,
+            frame,
+        })
     }
 
     pub fn clear(&self, color: Color) -> Result {
@@ -31,7 +41,7 @@ impl<'a> Canvas<'a> {
         target: Option<&mut Texture>,
         cb: impl FnOnce(&Canvas) -> Result,
     ) -> Result {
-        let canvas = Canvas::new(&self.backend, target)?;
+        let canvas = Canvas::new(&self.backend, target, self.frame)?;
         cb(&canvas)?;
         self.backend
             .borrow_mut()
@@ -51,21 +61,79 @@ impl<'a> Canvas<'a> {
             .render_draw_rect(rect, color)
     }
 
-    pub fn draw_text(&self, font: &Font, text: &str, position: Point, color: Color) -> Result {
-        font.draw_text(self, text, position, color)
+    pub fn fill_rect(&self, rect: Option<Rect>, color: Color) -> Result {
+        self.backend.borrow_mut().render_fill_rect(rect, color)
     }
 
+    /// Makes `rect` transparent, unlike `fill_rect` with a transparent color.
+    pub(crate) fn clear_rect(&self, rect: Rect) -> Result {
+        self.backend.borrow_mut().render_clear_rect(rect)
+    }
+
+    /// Draws `text` on a single line, its clusters spaced by `spacing.letter_spacing`, returning
+    /// the rect it covers.
+    pub fn draw_text(
+        &self,
+        font: &Font,
+        text: &str,
+        position: Point,
+        color: Color,
+        spacing: TextSpacing,
+    ) -> Result<Rect> {
+        font.draw_text(self, text, position, color, spacing)
+    }
+
+    /// Like `draw_text`, with the baseline of the text at `baseline` rather than its top, to line
+    /// up text in different fonts.
+    pub fn draw_text_baseline(
+        &self,
+        font: &Font,
+        text: &str,
+        baseline: Point,
+        color: Color,
+        spacing: TextSpacing,
+    ) -> Result<Rect> {
+        let position = Point::new(baseline.x, baseline.y - font.metrics().ascent);
+        font.draw_text(self, text, position, color, spacing)
+    }
+
+    /// Draws `text` with its glyphs scaled by `scale`.
+    pub fn draw_text_scaled(
+        &self,
+        font: &Font,
+        text: &str,
+        position: Point,
+        color: Color,
+        scale: f32,
+        spacing: TextSpacing,
+    ) -> Result<Rect> {
+        font.draw_text_scaled(self, text, position, color, scale, spacing)
+    }
+
+    /// Draws `text` into `rect`, lines wider than it wrapped or cut as `options.overflow` tells,
+    /// returning the rects its lines cover.
     pub fn draw_text_bounded(
         &self,
         font: &Font,
         text: &str,
         color: Color,
         rect: Rect,
-        align: TextAlign,
-        cross_align: TextCrossAlign,
-        padding: TextPadding,
-    ) -> Result {
-        font.draw_text_bounded(self, text, color, rect, align, cross_align, padding)
+        options: TextOptions,
+    ) -> Result<TextBounds> {
+        font.draw_text_bounded(self, text, color, rect, &options)
+    }
+
+    /// Like `draw_text_bounded`, with the color and font of every span of `text` picked by its
+    /// style.
+    pub fn draw_rich_text_bounded(
+        &self,
+        fonts: FontFamily,
+        text: &RichText,
+        color: Color,
+        rect: Rect,
+        options: RichTextOptions,
+    ) -> Result<TextBounds> {
+        rich_text::draw_rich_text_bounded(self, fonts, text, color, rect, &options)
     }
 
     pub fn copy_font_atlas(
@@ -80,22 +148,64 @@ impl<'a> Canvas<'a> {
             .render_copy_texture(atlas_id, options)
     }
 
-    pub fn text_width(&self, font: &Font, text: &str) -> Result<u32> {
-        font.line_width(text, self)
+    /// The width of `text` as drawn by `draw_text` with `spacing`.
+    pub fn text_width(&self, font: &Font, text: &str, spacing: TextSpacing) -> Result<u32> {
+        font.line_width(text, self, spacing)
+    }
+
+    /// The size of `text` as drawn by `draw_text_bounded` with `options` into a rect `max_width`
+    /// wide once the padding is removed, or on a single line (besides newlines) if `max_width` is
+    /// `None`.
+    pub fn measure_text(
+        &self,
+        font: &Font,
+        text: &str,
+        max_width: Option<u32>,
+        options: TextOptions,
+    ) -> Result<Dimensions> {
+        let max_width = max_width.unwrap_or(u32::MAX);
+        font.measure_text(self, text, max_width, &options)
     }
 
     pub fn register_text(&self, font: &Font, text: &str) -> Result {
         font.register_text(text, self)
     }
 
-    pub(crate) fn render_glyph(&self, font_id: FontId, glyph: char, position: Point) -> Result {
-        self.backend
-            .borrow_mut()
-            .render_font_glyph(font_id, glyph, position)
+    /// Renders a grapheme cluster, through the glyph API if it is a single char.
+    pub(crate) fn render_cluster(&self, font_id: FontId, cluster: &str, position: Point) -> Result {
+        let mut backend = self.backend.borrow_mut();
+        match single_char(cluster) {
+            Some(glyph) => backend.render_font_glyph(font_id, glyph, position),
+            None => backend.render_font_cluster(font_id, cluster, position),
+        }
     }
 
-    pub(crate) fn glyph_metrics(&self, font_id: FontId, glyph: char) -> Result<GlyphMetrics> {
-        self.backend.borrow_mut().font_glyph_metrics(font_id, glyph)
+    pub(crate) fn cluster_metrics(&self, font_id: FontId, cluster: &str) -> Result<GlyphMetrics> {
+        let mut backend = self.backend.borrow_mut();
+        match single_char(cluster) {
+            Some(glyph) => backend.font_glyph_metrics(font_id, glyph),
+            None => backend.font_cluster_metrics(font_id, cluster),
+        }
+    }
+
+    /// The number of the frame being drawn.
+    pub(crate) fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub(crate) fn has_glyph(&self, font_id: FontId, glyph: char) -> Result<bool> {
+        self.backend.borrow_mut().font_has_glyph(font_id, glyph)
+    }
+
+    pub(crate) fn glyph_kerning(
+        &self,
+        font_id: FontId,
+        previous: char,
+        glyph: char,
+    ) -> Result<i32> {
+        self.backend
+            .borrow_mut()
+            .font_glyph_kerning(font_id, previous, glyph)
     }
 }
 
@@ -106,3 +216,8 @@ impl<'a> Drop for Canvas<'a> {
         }
     }
 }
+
+fn single_char(cluster: &str) -> Option<char> {
+    let mut chars = cluster.chars();
+    chars.next().filter(|_| chars.next().is_none())
+}
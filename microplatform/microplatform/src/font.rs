@@ -1,18 +1,23 @@
+use crate::bidi::{self, Bidi};
 use crate::canvas::Canvas;
-use crate::text::BoundedLines;
+use crate::text::{graphemes, BoundedLines, Line};
 use crate::types::{FontId, GlyphMetrics};
 use crate::{
-    BackendRef, BackendWeakRef, Color, CopyTextureOptions, FontData, Point, Rect, Result,
-    TextAlign, TextCrossAlign, TextPadding, Texture, TextureId,
+    BackendRef, BackendWeakRef, Color, CopyTextureOptions, Dimensions, FontData, FontMetrics,
+    Point, Rect, Result, TextAlign, TextBounds, TextOptions, TextOverflow, TextSpacing, TextWrap,
+    Texture, TextureId,
 };
 use alloc::rc::Rc;
+use alloc::string::String;
 use alloc::vec::Vec;
 use core::cell::RefCell;
-use core::str::Chars;
+use core::ops::{Range, RangeInclusive};
 use hashbrown::HashMap;
 
 const ATLAS_WIDTH: u32 = 1024;
 const ATLAS_HEIGHT: u32 = 1024;
+const DEFAULT_ATLAS_LIMIT: usize = 4;
+const ELLIPSIS: &str = "…";
 
 pub struct Font(RefCell<FontInner>);
 
@@ -27,8 +32,25 @@ impl Font {
         text: &str,
         position: Point,
         color: Color,
-    ) -> Result {
-        self.0.borrow_mut().draw_text(canvas, text, position, color)
+        spacing: TextSpacing,
+    ) -> Result<Rect> {
+        self.0
+            .borrow_mut()
+            .draw_text(canvas, text, position, color, 1.0, spacing)
+    }
+
+    pub(crate) fn draw_text_scaled(
+        &self,
+        canvas: &Canvas,
+        text: &str,
+        position: Point,
+        color: Color,
+        scale: f32,
+        spacing: TextSpacing,
+    ) -> Result<Rect> {
+        self.0
+            .borrow_mut()
+            .draw_text(canvas, text, position, color, scale, spacing)
     }
 
     pub(crate) fn draw_text_bounded(
@@ -37,19 +59,11 @@ impl Font {
         text: &str,
         color: Color,
         rect: Rect,
-        align: TextAlign,
-        cross_align: TextCrossAlign,
-        padding: TextPadding,
-    ) -> Result {
-        self.0.borrow_mut().draw_text_bounded(
-            canvas,
-            text,
-            color,
-            rect,
-            align,
-            cross_align,
-            padding,
-        )
+        options: &TextOptions,
+    ) -> Result<TextBounds> {
+        self.0
+            .borrow_mut()
+            .draw_text_bounded(canvas, text, color, rect, options)
     }
 
     pub(crate) fn atlas(&self, index: usize) -> Option<TextureId> {
@@ -60,23 +74,139 @@ impl Font {
         self.0.borrow_mut().register_glyphs(text, canvas)
     }
 
-    pub(crate) fn line_width(&self, text: &str, canvas: &Canvas) -> Result<u32> {
-        self.0.borrow_mut().line_width(text, canvas)
+    pub(crate) fn line_width(
+        &self,
+        text: &str,
+        canvas: &Canvas,
+        spacing: TextSpacing,
+    ) -> Result<u32> {
+        self.0.borrow_mut().line_width(text, canvas, spacing)
+    }
+
+    /// Appends `fallback` to the fonts that glyphs missing from this one are taken from, in the
+    /// order they were added. Glyphs no font provides are drawn as a box.
+    pub fn add_fallback(&self, fallback: Font) {
+        let mut inner = self.0.borrow_mut();
+        let id = fallback.0.borrow().id;
+        inner.fonts.push(id);
+        inner.fallbacks.push(fallback);
+    }
+
+    /// This font at another size in px, sharing the data of the font with the backend but with
+    /// glyphs and atlases of its own. The fallbacks and atlas limit are carried over. The handle
+    /// is destroyed with the returned font.
+    pub fn with_size(&self, scale: u8) -> Result<Font> {
+        let inner = self.0.borrow();
+        let backend = inner.backend.upgrade().unwrap();
+        let data = backend.borrow_mut().font_load_size(inner.id, scale)?;
+        let mut sized = FontInner::with_data(&backend, data, scale)?;
+        sized.atlas_limit = inner.atlas_limit;
+        let sized = Font(RefCell::new(sized));
+        for fallback in inner.fallbacks.iter() {
+            sized.add_fallback(fallback.with_size(scale)?);
+        }
+        Ok(sized)
+    }
+
+    /// Adds the glyphs of `text` to the atlases ahead of drawing it, e.g. during a loading
+    /// screen, rather than the first frame they are drawn in.
+    pub fn preload_str(&self, canvas: &Canvas, text: &str) -> Result {
+        self.0.borrow_mut().register_glyphs(text, canvas)
+    }
+
+    /// Like `preload_str`, for every char in `range` this font or one of its fallbacks has.
+    pub fn preload_range(&self, canvas: &Canvas, range: RangeInclusive<char>) -> Result {
+        self.0.borrow_mut().preload_range(range, canvas)
+    }
+
+    /// Sets how many atlases the glyphs are kept in before the least recently used glyphs are
+    /// evicted, 4 by default. More atlases are only added past the limit for glyphs all drawn in
+    /// the same frame.
+    pub fn set_atlas_limit(&self, limit: usize) {
+        self.0.borrow_mut().atlas_limit = limit.max(1);
+    }
+
+    /// Draws clusters registered beforehand from left to right, returning where they end.
+    pub(crate) fn draw_clusters<'t>(
+        &self,
+        canvas: &Canvas,
+        clusters: impl IntoIterator<Item = &'t str>,
+        position: Point,
+        color: Color,
+        letter_spacing: i32,
+    ) -> Result<i32> {
+        let mut inner = self.0.borrow_mut();
+        inner.draw_clusters(position, clusters, canvas, color, 1.0, letter_spacing)
+    }
+
+    /// The advance of a registered cluster, see `FontInner::advance`.
+    pub(crate) fn advance(
+        &self,
+        previous: Option<&str>,
+        glyph: &str,
+        rtl: bool,
+        letter_spacing: i32,
+    ) -> u32 {
+        self.0
+            .borrow()
+            .advance(previous, glyph, rtl, letter_spacing)
+    }
+
+    pub(crate) fn measure_text(
+        &self,
+        canvas: &Canvas,
+        text: &str,
+        max_width: u32,
+        options: &TextOptions,
+    ) -> Result<Dimensions> {
+        self.0
+            .borrow_mut()
+            .measure_text(canvas, text, max_width, options)
+    }
+
+    pub(crate) fn glyphs_height(&self) -> u32 {
+        self.0.borrow().glyphs_height
+    }
+
+    pub fn metrics(&self) -> FontMetrics {
+        self.0.borrow().metrics
     }
 }
 
 struct FontInner {
     id: FontId,
+    // the fonts glyphs are looked up in, this one first
+    fonts: Vec<FontId>,
+    fallbacks: Vec<Font>,
     _scale: u8,
     glyphs_height: u32,
+    metrics: FontMetrics,
     backend: BackendWeakRef,
     atlases: Vec<FontAtlas>,
-    entries: HashMap<char, FontGlyphEntry>,
+    atlas_limit: usize,
+    // the atlas and row glyphs are added to
+    open_row: (usize, usize),
+    // keyed by grapheme cluster
+    entries: HashMap<String, FontGlyphEntry>,
+    kerning: HashMap<(char, char), i32>,
+    // the layouts of every text for each width and options it was laid out with
+    layouts: HashMap<String, Vec<TextLayout>>,
+    // the frame layouts were last dropped in
+    layouts_frame: u64,
 }
 
 impl FontInner {
     fn new(backend: &BackendRef, path: &str, scale: u8) -> Result<Self> {
-        let FontData { id, glyphs_height } = backend.borrow_mut().font_load(path, scale)?;
+        let data = backend.borrow_mut().font_load(path, scale)?;
+        Self::with_data(backend, data, scale)
+    }
+
+    fn with_data(backend: &BackendRef, data: FontData, scale: u8) -> Result<Self> {
+        let FontData {
+            id,
+            glyphs_height,
+            metrics,
+        } = data;
         let backend = Rc::downgrade(backend);
         let atlases = vec![FontAtlas::new(
             &backend,
@@ -86,18 +216,45 @@ impl FontInner {
         )?];
         Ok(Self {
             id,
+            fonts: vec![id],
+            fallbacks: Vec::new(),
             _scale: scale,
             glyphs_height,
+            metrics,
             backend,
             atlases,
+            atlas_limit: DEFAULT_ATLAS_LIMIT,
+            open_row: (0, 0),
             entries: HashMap::new(),
+            kerning: HashMap::new(),
+            layouts: HashMap::new(),
+            layouts_frame: 0,
         })
     }
 
-    fn draw_text(&mut self, canvas: &Canvas, text: &str, position: Point, color: Color) -> Result {
+    fn draw_text(
+        &mut self,
+        canvas: &Canvas,
+        text: &str,
+        position: Point,
+        color: Color,
+        scale: f32,
+        spacing: TextSpacing,
+    ) -> Result<Rect> {
         self.register_glyphs(text, canvas)?;
-        self.draw_text_line(position, text, canvas, color)?;
-        Ok(())
+        let line = Line {
+            text,
+            start: 0,
+            width: 0,
+            hyphenated: false,
+        };
+        let clusters = Bidi::new(text).visual_order(&line);
+        let clusters = clusters.into_iter().map(|(_, c)| c);
+        let letter_spacing = spacing.letter_spacing;
+        let end = self.draw_clusters(position, clusters, canvas, color, scale, letter_spacing)?;
+        let width = (end - position.x) as u32;
+        let height = (self.glyphs_height as f32 * scale) as u32;
+        Ok(Rect::new(position.x, position.y, width, height))
     }
 
     fn draw_text_bounded(
@@ -106,183 +263,1038 @@ impl FontInner {
         text: &str,
         color: Color,
         rect: Rect,
-        align: TextAlign,
-        cross_align: TextCrossAlign,
-        padding: TextPadding,
-    ) -> Result {
-        self.register_glyphs(text, canvas)?;
-
+        options: &TextOptions,
+    ) -> Result<TextBounds> {
+        let TextOptions { align, padding, .. } = *options;
         let inner_rect = Rect {
             x: rect.x + padding.left as i32,
             y: rect.y + padding.top as i32,
-            w: rect.w - padding.left as u32 - padding.right as u32,
-            h: rect.h - padding.top as u32 - padding.bottom as u32,
+            w: rect
+                .w
+                .saturating_sub(padding.left as u32 + padding.right as u32),
+            h: rect
+                .h
+                .saturating_sub(padding.top as u32 + padding.bottom as u32),
         };
 
-        let lines = text
-            .bounded_lines(inner_rect.w, |c| {
-                self.entries.get(&c).unwrap().metrics.advance
-            })
-            .collect::<Vec<_>>();
+        self.prepare_layout(canvas, text, inner_rect.w, options)?;
+        let layout = self.layout(text, inner_rect.w, options);
+        let line_height = options.spacing.line_height(self.glyphs_height);
 
         let mut y_cursor = inner_rect.y;
-        let x = inner_rect.x;
+        let mut lines = Vec::with_capacity(layout.lines.len());
+
+        for line in layout.lines.iter() {
+            let glyphs = &layout.glyphs[line.glyphs.clone()];
+            let justify = matches!(align, TextAlign::Justified) && !line.last;
+            let (x, width) = match justify && !line.gaps.is_empty() {
+                true => {
+                    let space = inner_rect.w.saturating_sub(line.width);
+                    let glyphs = justify_glyphs(glyphs, &line.gaps, space);
+                    let position = Point::new(inner_rect.x, y_cursor);
+                    self.copy_glyphs(canvas, &glyphs, position, color, 1.0)?;
+                    (inner_rect.x, line.width + space)
+                }
+                false => {
+                    let x = inner_rect.x + align.offset(line.rtl, inner_rect.w, line.width);
+                    self.copy_glyphs(canvas, glyphs, Point::new(x, y_cursor), color, 1.0)?;
+                    (x, line.width)
+                }
+            };
+            lines.push(Rect::new(x, y_cursor, width, self.glyphs_height));
+            y_cursor += line_height as i32;
+        }
+
+        Ok(TextBounds::new(inner_rect.point(), lines))
+    }
 
-        for (line, _) in lines.iter() {
-            self.draw_text_line(Point::new(x, y_cursor), line, canvas, color)?;
-            y_cursor += self.glyphs_height as i32;
+    fn measure_text(
+        &mut self,
+        canvas: &Canvas,
+        text: &str,
+        max_width: u32,
+        options: &TextOptions,
+    ) -> Result<Dimensions> {
+        self.prepare_layout(canvas, text, max_width, options)?;
+        let lines = &self.layout(text, max_width, options).lines;
+        let line_height = options.spacing.line_height(self.glyphs_height);
+        Ok(Dimensions {
+            width: lines.iter().map(|line| line.width).max().unwrap_or(0),
+            height: match lines.len() as u32 {
+                0 => 0,
+                count => (count - 1) * line_height + self.glyphs_height,
+            },
+        })
+    }
+
+    /// Lays out `text` into lines no wider than `max_width` for `layout`, reusing the layout of
+    /// previous calls unless glyphs it places were evicted since. Layouts not used in this or
+    /// the previous frame are dropped.
+    fn prepare_layout(
+        &mut self,
+        canvas: &Canvas,
+        text: &str,
+        max_width: u32,
+        options: &TextOptions,
+    ) -> Result {
+        let frame = canvas.frame();
+        if self.layouts_frame != frame {
+            self.layouts_frame = frame;
+            self.layouts.retain(|_, layouts| {
+                layouts.retain(|layout| layout.last_used + 1 >= frame);
+                !layouts.is_empty()
+            });
+        }
+
+        let atlases = &mut self.atlases;
+        let cached = self
+            .layouts
+            .get_mut(text)
+            .and_then(|layouts| layouts.iter_mut().find(|l| l.is(max_width, options)))
+            .filter(|layout| {
+                let evicted = |&(atlas, row, evictions): &(usize, usize, u64)| {
+                    atlases[atlas].rows[row].evictions != evictions
+                };
+                !layout.rows.iter().any(evicted)
+            });
+        if let Some(layout) = cached {
+            layout.last_used = frame;
+            for &(atlas, row, _) in layout.rows.iter() {
+                atlases[atlas].rows[row].last_used = frame;
+            }
+            return Ok(());
         }
 
+        let layout = self.lay_out(canvas, text, max_width, options)?;
+        let layouts = self.layouts.entry_ref(text).or_default();
+        layouts.retain(|l| !l.is(max_width, options));
+        layouts.push(layout);
         Ok(())
     }
 
-    fn draw_text_line(
+    /// The layout made by `prepare_layout`.
+    fn layout(&self, text: &str, max_width: u32, options: &TextOptions) -> &TextLayout {
+        self.layouts[text]
+            .iter()
+            .find(|layout| layout.is(max_width, options))
+            .unwrap()
+    }
+
+    fn lay_out(
         &mut self,
-        position: Point,
+        canvas: &Canvas,
         text: &str,
+        max_width: u32,
+        options: &TextOptions,
+    ) -> Result<TextLayout> {
+        let lines = self.lines(canvas, text, max_width, options)?;
+        let bidi = Bidi::new(text);
+        let letter_spacing = options.spacing.letter_spacing;
+        let mut layout = TextLayout {
+            max_width,
+            wrap: options.wrap,
+            overflow: options.overflow,
+            letter_spacing,
+            lines: Vec::with_capacity(lines.len()),
+            glyphs: Vec::new(),
+            rows: Vec::new(),
+            last_used: canvas.frame(),
+        };
+        for (line, cut) in lines.iter() {
+            let start = layout.glyphs.len();
+            let rtl = bidi.is_rtl(line.start);
+            let mut clusters = bidi.visual_order(line);
+            if *cut && options.overflow == TextOverflow::Ellipsis {
+                // at the end of the line in the direction of its paragraph
+                let ellipsis = (line.start + line.text.len(), ELLIPSIS);
+                match rtl {
+                    true => clusters.insert(0, ellipsis),
+                    false => clusters.push(ellipsis),
+                }
+            }
+            let space = |i: usize| clusters[i].1.starts_with(char::is_whitespace);
+            let gaps = (1..clusters.len())
+                .filter(|&i| space(i - 1) && !space(i) && (0..i).any(|j| !space(j)))
+                .collect();
+            let rest = &text[line.start + line.text.len()..];
+            let rest = rest.trim_start_matches(|c: char| c.is_whitespace() && c != '\n');
+            let clusters = clusters.iter().map(|&(_, c)| c);
+            self.place(clusters, &mut layout.glyphs, letter_spacing);
+            layout.lines.push(LayoutLine {
+                glyphs: start..layout.glyphs.len(),
+                width: line.width,
+                rtl,
+                gaps,
+                last: *cut || rest.is_empty() || rest.starts_with('\n'),
+            });
+        }
+
+        let mut rows = layout
+            .glyphs
+            .iter()
+            .map(|glyph| (glyph.atlas_index, glyph.row))
+            .collect::<Vec<_>>();
+        rows.sort_unstable();
+        rows.dedup();
+        layout.rows = rows
+            .into_iter()
+            .map(|(atlas, row)| (atlas, row, self.atlases[atlas].rows[row].evictions))
+            .collect();
+        Ok(layout)
+    }
+
+    /// Registers the glyphs of `text` and splits it into lines, wrapped at `max_width` for
+    /// `TextOverflow::Wrap`, or at newlines only and then cut to `max_width` for the other
+    /// overflows. Each line comes with whether it was cut.
+    fn lines<'t>(
+        &mut self,
+        canvas: &Canvas,
+        text: &'t str,
+        max_width: u32,
+        options: &TextOptions,
+    ) -> Result<Vec<(Line<'t>, bool)>> {
+        let TextOptions { wrap, overflow, .. } = *options;
+        let letter_spacing = options.spacing.letter_spacing;
+        let bidi = Bidi::new(text);
+        self.register_glyphs(text, canvas)?;
+        let hyphenate = matches!(wrap, TextWrap::Hyphenate);
+        if hyphenate {
+            self.register_glyphs("-", canvas)?;
+        }
+        if overflow == TextOverflow::Ellipsis {
+            self.register_glyphs(ELLIPSIS, canvas)?;
+            // any cluster may end up before the ellipsis
+            for (i, c) in graphemes(text) {
+                let (left, right) = bidi::visual_pair(c, ELLIPSIS, bidi.is_rtl(i));
+                self.register_pair(left, right, canvas)?;
+            }
+        }
+        let line_width = match overflow {
+            TextOverflow::Wrap => max_width,
+            TextOverflow::Clip | TextOverflow::Ellipsis => u32::MAX,
+        };
+        let lines = text
+            .bounded_lines(line_width, hyphenate, |i, previous, c| {
+                self.advance(previous, c, bidi.is_rtl_cluster(i), letter_spacing)
+            })
+            .map(|line| self.truncate(line, &bidi, max_width, overflow, letter_spacing))
+            .collect();
+        Ok(lines)
+    }
+
+    /// Cuts `line` to the clusters that fit in `max_width`, leaving room for an ellipsis after
+    /// them for `TextOverflow::Ellipsis`. True if the line was cut.
+    fn truncate<'t>(
+        &self,
+        line: Line<'t>,
+        bidi: &Bidi,
+        max_width: u32,
+        overflow: TextOverflow,
+        letter_spacing: i32,
+    ) -> (Line<'t>, bool) {
+        if line.width <= max_width {
+            return (line, false);
+        }
+        let rtl = bidi.is_rtl(line.start);
+        let ellipsis = |previous: Option<&str>| match overflow {
+            TextOverflow::Ellipsis => self.advance(previous, ELLIPSIS, rtl, letter_spacing),
+            _ => 0,
+        };
+        // the end and width of the line without trailing whitespace
+        let (mut end, mut width) = (0, ellipsis(None));
+        let mut content = 0;
+        let mut previous = None;
+        for (i, c) in graphemes(line.text) {
+            let rtl = bidi.is_rtl_cluster(line.start + i);
+            let advance = self.advance(previous, c, rtl, letter_spacing);
+            if content + advance + ellipsis(Some(c)) > max_width {
+                break;
+            }
+            previous = Some(c);
+            content += advance;
+            if !c.starts_with(char::is_whitespace) {
+                end = i + c.len();
+                width = content + ellipsis(Some(c));
+            }
+        }
+        let line = Line {
+            text: &line.text[..end],
+            width,
+            ..line
+        };
+        (line, true)
+    }
+
+    fn draw_clusters<'t>(
+        &mut self,
+        position: Point,
+        clusters: impl IntoIterator<Item = &'t str>,
         canvas: &Canvas<'_>,
         color: Color,
+        scale: f32,
+        letter_spacing: i32,
+    ) -> Result<i32> {
+        let mut glyphs = Vec::new();
+        let width = self.place(clusters, &mut glyphs, letter_spacing);
+        self.copy_glyphs(canvas, &glyphs, position, color, scale)?;
+        Ok(position.x + (width as f32 * scale) as i32)
+    }
+
+    /// Places registered clusters from left to right, returning where they end.
+    fn place<'t>(
+        &self,
+        clusters: impl IntoIterator<Item = &'t str>,
+        glyphs: &mut Vec<PlacedGlyph>,
+        letter_spacing: i32,
+    ) -> i32 {
+        let mut x_cursor = 0;
+        let mut previous = None;
+        for glyph in clusters {
+            if let Some(previous) = previous.replace(glyph) {
+                x_cursor += self.gap(previous, glyph, letter_spacing);
+            }
+            let entry = self.entries.get(glyph).unwrap();
+            glyphs.push(PlacedGlyph {
+                atlas_index: entry.atlas_index,
+                row: entry.row,
+                src: entry.rect,
+                x: x_cursor,
+            });
+            x_cursor += entry.metrics.advance as i32;
+        }
+        x_cursor
+    }
+
+    fn copy_glyphs(
+        &self,
+        canvas: &Canvas<'_>,
+        glyphs: &[PlacedGlyph],
+        position: Point,
+        color: Color,
+        scale: f32,
     ) -> Result {
-        let mut x_cursor = position.x;
-        Ok(for glyph in text.chars() {
-            let entry = self.entries.get(&glyph).unwrap();
-            let atlas = &self.atlases[entry.atlas_index];
+        let scaled = |value: i32| (value as f32 * scale) as i32;
+        for glyph in glyphs {
             canvas.copy_texture(
-                &atlas.texture,
+                &self.atlases[glyph.atlas_index].texture,
                 CopyTextureOptions {
-                    src: Some(entry.rect),
+                    src: Some(glyph.src),
                     dest: Some(Rect {
-                        x: x_cursor,
+                        x: position.x + scaled(glyph.x),
                         y: position.y,
-                        w: entry.metrics.advance,
-                        h: self.glyphs_height,
+                        w: scaled(glyph.src.w as i32) as u32,
+                        h: scaled(self.glyphs_height as i32) as u32,
                     }),
                     color_mod: Some(color),
                     ..Default::default()
                 },
             )?;
-            x_cursor += entry.metrics.advance as i32;
-        })
+        }
+        Ok(())
     }
 
-    fn line_width(&mut self, text: &str, canvas: &Canvas<'_>) -> Result<u32> {
+    fn line_width(&mut self, text: &str, canvas: &Canvas<'_>, spacing: TextSpacing) -> Result<u32> {
         self.register_glyphs(text, canvas)?;
-        let width = text
-            .chars()
-            .map(|c| self.entries.get(&c).unwrap().metrics.advance)
+        let bidi = Bidi::new(text);
+        let mut previous = None;
+        let width = graphemes(text)
+            .map(|(i, c)| {
+                let rtl = bidi.is_rtl_cluster(i);
+                self.advance(previous.replace(c), c, rtl, spacing.letter_spacing)
+            })
             .sum::<u32>();
         Ok(width)
     }
 
+    /// The advance of the cluster `glyph`, adjusted by its gap after `previous`, the cluster
+    /// before it in the text, which is drawn on its right if `rtl`.
+    fn advance(&self, previous: Option<&str>, glyph: &str, rtl: bool, letter_spacing: i32) -> u32 {
+        let advance = self.entries.get(glyph).unwrap().metrics.advance as i32;
+        let gap = previous.map_or(0, |previous| {
+            let (left, right) = bidi::visual_pair(previous, glyph, rtl);
+            self.gap(left, right, letter_spacing)
+        });
+        (advance + gap).max(0) as u32
+    }
+
+    /// The kerning and letter spacing between two clusters drawn from left to right.
+    fn gap(&self, left: &str, right: &str, letter_spacing: i32) -> i32 {
+        self.kerning(left, right) + letter_spacing
+    }
+
+    /// The kerning between the base chars of two clusters drawn from left to right.
+    fn kerning(&self, left: &str, right: &str) -> i32 {
+        self.kerning
+            .get(&(base_char(left), base_char(right)))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn preload_range(&mut self, range: RangeInclusive<char>, canvas: &Canvas<'_>) -> Result {
+        let mut buffer = [0; 4];
+        for glyph in range.filter(|c| !c.is_control()) {
+            let mut provided = false;
+            for &font in &self.fonts {
+                provided = provided || canvas.has_glyph(font, glyph)?;
+            }
+            if provided {
+                self.register_text(glyph.encode_utf8(&mut buffer), canvas)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Registers the glyphs of `text`, and the mirrored brackets right to left text may need.
     fn register_glyphs(&mut self, text: &str, canvas: &Canvas<'_>) -> Result {
-        let mut glyphs = text.chars();
-        let mut atlas_index = self.atlases.len() - 1;
-        let mut atlas = &mut self.atlases[atlas_index];
-        loop {
-            if register_glyphs(
-                self.id,
-                atlas_index,
-                atlas,
-                canvas,
-                &mut self.entries,
-                &mut glyphs,
-            )? {
-                break;
-            } else {
+        let mirrored = graphemes(text)
+            .map(|(_, c)| (c, bidi::mirror(c)))
+            .filter(|&(c, mirror)| mirror != c && !self.entries.contains_key(mirror))
+            .map(|(_, mirror)| mirror)
+            .collect::<Vec<_>>();
+        // one at a time, to not measure kerning between brackets that are never next to each
+        // other
+        for mirror in mirrored {
+            self.register_text(mirror, canvas)?;
+        }
+        self.register_text(text, canvas)
+    }
+
+    fn register_text(&mut self, text: &str, canvas: &Canvas<'_>) -> Result {
+        let frame = canvas.frame();
+        for (_, glyph) in graphemes(text) {
+            if let Some(entry) = self.entries.get(glyph) {
+                self.atlases[entry.atlas_index].rows[entry.row].last_used = frame;
+                continue;
+            }
+            // the font of the first char draws the whole cluster
+            let mut font = None;
+            for &candidate in &self.fonts {
+                if canvas.has_glyph(candidate, base_char(glyph))? {
+                    font = Some(candidate);
+                    break;
+                }
+            }
+            let metrics = match font {
+                Some(font) => canvas.cluster_metrics(font, glyph)?,
+                None => tofu_metrics(self.glyphs_height),
+            };
+
+            let (atlas_index, row) = self.allocate(canvas, metrics.advance)?;
+            let atlas = &mut self.atlases[atlas_index];
+            let glyph_height = atlas.glyph_height;
+            let rect = Rect::new(
+                atlas.rows[row].x_cursor as i32,
+                (row as u32 * glyph_height) as i32,
+                metrics.advance,
+                glyph_height,
+            );
+            atlas.rows[row].x_cursor += metrics.advance;
+            atlas.rows[row].last_used = frame;
+
+            // render the glyph to the target texture of its atlas
+            canvas.with_target(Some(&mut atlas.texture), |canvas| match font {
+                Some(font) => canvas.render_cluster(font, glyph, rect.point()),
+                None => {
+                    let tofu = Rect::new(
+                        rect.x + 1,
+                        rect.y + 1,
+                        metrics.advance.saturating_sub(2),
+                        glyph_height.saturating_sub(2),
+                    );
+                    canvas.draw_rect(Some(tofu), Color::WHITE)
+                }
+            })?;
+
+            self.entries.insert(
+                String::from(glyph),
+                FontGlyphEntry {
+                    atlas_index,
+                    row,
+                    rect,
+                    metrics,
+                    font,
+                },
+            );
+        }
+        self.register_kerning(text, canvas)
+    }
+
+    /// Returns the atlas and row to put a glyph `width` wide in, filling rows in order. Once
+    /// all rows are used and there are as many atlases as the limit, the least recently used row
+    /// is evicted, or a new atlas is added if every row was used in this frame.
+    fn allocate(&mut self, canvas: &Canvas<'_>, width: u32) -> Result<(usize, usize)> {
+        let (atlas_index, row) = self.open_row;
+        let atlas = &self.atlases[atlas_index];
+        if atlas.rows[row].x_cursor + width <= atlas.texture.width() {
+            return Ok(self.open_row);
+        }
+
+        let empty = self.atlases.iter().enumerate().find_map(|(i, atlas)| {
+            let row = atlas.rows.iter().position(|row| row.x_cursor == 0)?;
+            Some((i, row))
+        });
+        let least_recently_used = self
+            .atlases
+            .iter()
+            .enumerate()
+            .flat_map(|(i, atlas)| {
+                atlas
+                    .rows
+                    .iter()
+                    .enumerate()
+                    .map(move |(r, row)| (i, r, row))
+            })
+            .min_by_key(|(_, _, row)| row.last_used)
+            .filter(|(_, _, row)| row.last_used != canvas.frame())
+            .map(|(i, r, _)| (i, r));
+
+        self.open_row = match (empty, least_recently_used) {
+            (Some(empty), _) => empty,
+            (None, Some(evicted)) if self.atlases.len() >= self.atlas_limit => {
+                self.evict(canvas, evicted)?;
+                evicted
+            }
+            _ => {
                 self.atlases.push(FontAtlas::new(
                     &self.backend,
                     ATLAS_WIDTH,
                     ATLAS_HEIGHT,
                     self.glyphs_height,
                 )?);
-                atlas_index += 1;
-                atlas = &mut self.atlases[atlas_index];
+                (self.atlases.len() - 1, 0)
             }
+        };
+        Ok(self.open_row)
+    }
+
+    /// Removes the glyphs of a row and clears it to be filled again.
+    fn evict(&mut self, canvas: &Canvas<'_>, (atlas_index, row): (usize, usize)) -> Result {
+        self.entries
+            .retain(|_, entry| (entry.atlas_index, entry.row) != (atlas_index, row));
+        let atlas = &mut self.atlases[atlas_index];
+        atlas.rows[row].x_cursor = 0;
+        atlas.rows[row].evictions += 1;
+        let rect = Rect::new(
+            0,
+            (row as u32 * atlas.glyph_height) as i32,
+            atlas.texture.width(),
+            atlas.glyph_height,
+        );
+        canvas.with_target(Some(&mut atlas.texture), |canvas| canvas.clear_rect(rect))
+    }
+
+    /// Registers the kerning of the pairs of registered clusters next to each other in `text`,
+    /// in the order they are drawn.
+    fn register_kerning(&mut self, text: &str, canvas: &Canvas<'_>) -> Result {
+        let bidi = Bidi::new(text);
+        let clusters = graphemes(text).collect::<Vec<_>>();
+        for pair in clusters.windows(2) {
+            let ((_, previous), (i, glyph)) = (pair[0], pair[1]);
+            let (left, right) = bidi::visual_pair(previous, glyph, bidi.is_rtl_cluster(i));
+            self.register_pair(left, right, canvas)?;
         }
         Ok(())
     }
+
+    /// Registers the kerning of two registered clusters drawn from left to right, queried from
+    /// the font drawing both.
+    fn register_pair(&mut self, left: &str, right: &str, canvas: &Canvas<'_>) -> Result {
+        let pair = (base_char(left), base_char(right));
+        if self.kerning.contains_key(&pair) {
+            return Ok(());
+        }
+        let font = |cluster: &str| self.entries.get(cluster).and_then(|entry| entry.font);
+        let kerning = match (font(left), font(right)) {
+            (Some(left), Some(right)) if left.0 == right.0 => {
+                canvas.glyph_kerning(left, pair.0, pair.1)?
+            }
+            // glyphs of different fonts and boxes are not kerned
+            _ => 0,
+        };
+        self.kerning.insert(pair, kerning);
+        Ok(())
+    }
+}
+
+impl Drop for FontInner {
+    fn drop(&mut self) {
+        if let Some(backend) = self.backend.upgrade() {
+            let _ = backend.borrow_mut().font_destroy(self.id);
+        }
+    }
 }
 
 struct FontGlyphEntry {
     atlas_index: usize,
+    row: usize,
     rect: Rect,
     metrics: GlyphMetrics,
+    // the font drawing the glyph, none for a box
+    font: Option<FontId>,
+}
+
+struct TextLayout {
+    max_width: u32,
+    wrap: TextWrap,
+    overflow: TextOverflow,
+    letter_spacing: i32,
+    lines: Vec<LayoutLine>,
+    glyphs: Vec<PlacedGlyph>,
+    // the atlas rows of the glyphs, with the evictions of each when the text was laid out
+    rows: Vec<(usize, usize, u64)>,
+    last_used: u64,
+}
+
+impl TextLayout {
+    /// True if this layout is the one of `options` in `max_width`. The other options are only
+    /// used when drawing.
+    fn is(&self, max_width: u32, options: &TextOptions) -> bool {
+        self.max_width == max_width
+            && self.wrap == options.wrap
+            && self.overflow == options.overflow
+            && self.letter_spacing == options.spacing.letter_spacing
+    }
+}
+
+struct LayoutLine {
+    glyphs: Range<usize>,
+    width: u32,
+    rtl: bool,
+    // the index in the line of the first glyph of every word after the first
+    gaps: Vec<usize>,
+    // true if the line ends its paragraph
+    last: bool,
+}
+
+/// `glyphs` with `space` spread across the word gaps before the glyphs at `gaps`, the first gaps
+/// taking the remainder.
+fn justify_glyphs(glyphs: &[PlacedGlyph], gaps: &[usize], space: u32) -> Vec<PlacedGlyph> {
+    let mut glyphs = glyphs.to_vec();
+    let count = gaps.len() as u32;
+    for (n, &gap) in gaps.iter().enumerate() {
+        let extra = space / count + u32::from((n as u32) < space % count);
+        for glyph in glyphs[gap..].iter_mut() {
+            glyph.x += extra as i32;
+        }
+    }
+    glyphs
+}
+
+/// A glyph of a line, `x` from where the line starts.
+#[derive(Copy, Clone)]
+struct PlacedGlyph {
+    atlas_index: usize,
+    row: usize,
+    src: Rect,
+    x: i32,
 }
 
 struct FontAtlas {
     texture: Texture,
     glyph_height: u32,
+    rows: Vec<AtlasRow>,
+}
+
+struct AtlasRow {
     x_cursor: u32,
-    y_cursor: u32,
+    // the frame a glyph of the row was last used in
+    last_used: u64,
+    // how many times the row was evicted, which invalidates the layouts placing its glyphs
+    evictions: u64,
 }
 
 impl FontAtlas {
     fn new(backend: &BackendWeakRef, width: u32, height: u32, glyph_height: u32) -> Result<Self> {
         let backend = backend.upgrade().unwrap();
         let texture = Texture::new_target(&backend, width, height)?;
+        let rows = (0..height / glyph_height)
+            .map(|_| AtlasRow {
+                x_cursor: 0,
+                last_used: 0,
+                evictions: 0,
+            })
+            .collect();
         Ok(Self {
             texture,
             glyph_height,
-            x_cursor: 0,
-            y_cursor: 0,
+            rows,
         })
     }
 }
 
-/// Returns true if all glyphs were successfully registered inside the `FontAtlas`.
-fn register_glyphs(
-    font_id: FontId,
-    atlas_index: usize,
-    atlas: &mut FontAtlas,
-    canvas: &Canvas,
-    entries: &mut HashMap<char, FontGlyphEntry>,
-    glyphs: &mut Chars,
-) -> Result<bool> {
-    let mut finished = false;
-    let atlas_width = atlas.texture.width();
-    let atlas_height = atlas.texture.height();
-    canvas.with_target(Some(&mut atlas.texture), |canvas| {
-        while let Some(glyph) = glyphs.next() {
-            if entries.contains_key(&glyph) {
-                continue;
-            }
-            let metrics = canvas.glyph_metrics(font_id, glyph)?;
-
-            if atlas.x_cursor + metrics.advance > atlas_width {
-                // go to next line
-                atlas.x_cursor = 0;
-                atlas.y_cursor += atlas.glyph_height;
-                if atlas.y_cursor + atlas.glyph_height > atlas_height {
-                    // atlas is full
-                    return Ok(());
-                }
-            }
+/// The metrics of the box drawn for glyphs no font provides.
+fn tofu_metrics(glyph_height: u32) -> GlyphMetrics {
+    let advance = (glyph_height / 2).max(3);
+    GlyphMetrics {
+        min_x: 0,
+        max_x: advance as i32,
+        min_y: 0,
+        max_y: glyph_height as i32,
+        advance,
+    }
+}
 
-            // render the glyph to this target texture...
-            canvas.render_glyph(
-                font_id,
-                glyph,
-                Point::new(atlas.x_cursor as i32, atlas.y_cursor as i32),
-            )?;
+fn base_char(cluster: &str) -> char {
+    cluster.chars().next().unwrap()
+}
 
-            entries.insert(
-                glyph,
-                FontGlyphEntry {
-                    atlas_index,
-                    rect: Rect::new(
-                        atlas.x_cursor as i32,
-                        atlas.y_cursor as i32,
-                        metrics.advance,
-                        atlas.glyph_height,
-                    ),
-                    metrics,
-                },
-            );
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{canvas, mock};
+    use crate::types::*;
+
+    /// The atlas and row of every registered glyph of `glyphs`.
+    fn rows(font: &Font, glyphs: &str) -> Vec<Option<(usize, usize)>> {
+        let inner = font.0.borrow();
+        graphemes(glyphs)
+            .map(|(_, c)| inner.entries.get(c).map(|e| (e.atlas_index, e.row)))
+            .collect()
+    }
 
-            atlas.x_cursor += metrics.advance;
+    fn lines(text: &str, max_width: u32, overflow: TextOverflow) -> Vec<(String, u32, bool)> {
+        let (_, backend, font) = mock(16);
+        let canvas = canvas(&backend, 1);
+        let options = TextOptions {
+            overflow,
+            ..Default::default()
+        };
+        let mut inner = font.0.borrow_mut();
+        let lines = inner.lines(&canvas, text, max_width, &options).unwrap();
+        lines
+            .into_iter()
+            .map(|(line, cut)| (String::from(line.text), line.width, cut))
+            .collect()
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_row() {
+        // 4 rows of 255 px in an atlas
+        let (mock, backend, font) = mock(255);
+        font.set_atlas_limit(1);
+        for (frame, glyph) in ["A", "B", "C", "D", "A"].into_iter().enumerate() {
+            font.register_text(glyph, &canvas(&backend, frame as u64 + 1))
+                .unwrap();
         }
-        finished = true;
-        Ok(())
-    })?;
-    Ok(finished)
+        font.register_text("E", &canvas(&backend, 6)).unwrap();
+        assert_eq!(
+            rows(&font, "ABCDE"),
+            [Some((0, 0)), None, Some((0, 2)), Some((0, 3)), Some((0, 1))]
+        );
+        assert_eq!(font.0.borrow().atlases[0].rows[1].evictions, 1);
+        // the pixels of the evicted glyphs are cleared, not blended over
+        let clears = mock
+            .borrow()
+            .clears
+            .iter()
+            .map(|r| (r.x, r.y, r.w, r.h))
+            .collect::<Vec<_>>();
+        assert_eq!(clears, [(0, 255, ATLAS_WIDTH, 255)]);
+    }
+
+    #[test]
+    fn adds_atlases_past_the_limit_for_glyphs_of_the_same_frame() {
+        let (_, backend, font) = mock(255);
+        font.set_atlas_limit(1);
+        font.register_text("ABCDE", &canvas(&backend, 1)).unwrap();
+        assert_eq!(font.0.borrow().atlases.len(), 2);
+        assert!(rows(&font, "ABCDE").iter().all(Option::is_some));
+        // once the rows are filled, later frames evict rather than adding another atlas
+        font.register_text("FGH", &canvas(&backend, 2)).unwrap();
+        font.register_text("I", &canvas(&backend, 3)).unwrap();
+        assert_eq!(font.0.borrow().atlases.len(), 2);
+        assert_eq!(rows(&font, "AI"), [None, Some((0, 0))]);
+    }
+
+    #[test]
+    fn reuses_layouts_until_their_glyphs_are_evicted() {
+        let (_, backend, font) = mock(16);
+        let options = TextOptions::default();
+        let canvas = canvas(&backend, 1);
+        let mut inner = font.0.borrow_mut();
+        inner.prepare_layout(&canvas, "ab", 100, &options).unwrap();
+        // a layout laid out again would place its glyphs anew
+        inner.layouts.get_mut("ab").unwrap()[0].glyphs.clear();
+        inner.prepare_layout(&canvas, "ab", 100, &options).unwrap();
+        assert!(inner.layout("ab", 100, &options).glyphs.is_empty());
+
+        inner.evict(&canvas, (0, 0)).unwrap();
+        inner.prepare_layout(&canvas, "ab", 100, &options).unwrap();
+        let layout = inner.layout("ab", 100, &options);
+        assert_eq!(layout.glyphs.len(), 2);
+        assert_eq!(layout.rows, [(0, 0, 1)]);
+    }
+
+    #[test]
+    fn keeps_layouts_per_width_and_options() {
+        let (_, backend, font) = mock(16);
+        let clip = TextOptions {
+            overflow: TextOverflow::Clip,
+            ..Default::default()
+        };
+        let canvas = canvas(&backend, 1);
+        let mut inner = font.0.borrow_mut();
+        inner.prepare_layout(&canvas, "ab cd", 100, &clip).unwrap();
+        inner.prepare_layout(&canvas, "ab cd", 20, &clip).unwrap();
+        inner
+            .prepare_layout(&canvas, "ab cd", 20, &TextOptions::default())
+            .unwrap();
+        assert_eq!(inner.layouts["ab cd"].len(), 3);
+        assert_eq!(inner.layout("ab cd", 20, &clip).lines.len(), 1);
+        assert_eq!(
+            inner
+                .layout("ab cd", 20, &TextOptions::default())
+                .lines
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn drops_layouts_unused_in_the_previous_frame() {
+        let (_, backend, font) = mock(16);
+        let options = TextOptions::default();
+        let mut inner = font.0.borrow_mut();
+        inner
+            .prepare_layout(&canvas(&backend, 1), "a", 100, &options)
+            .unwrap();
+        inner
+            .prepare_layout(&canvas(&backend, 1), "b", 100, &options)
+            .unwrap();
+        inner
+            .prepare_layout(&canvas(&backend, 2), "a", 100, &options)
+            .unwrap();
+        assert!(inner.layouts.contains_key("b"));
+        inner
+            .prepare_layout(&canvas(&backend, 3), "a", 100, &options)
+            .unwrap();
+        assert!(!inner.layouts.contains_key("b"));
+        assert!(inner.layouts.contains_key("a"));
+    }
+
+    #[test]
+    fn finds_the_word_gaps_of_lines() {
+        let (_, backend, font) = mock(16);
+        let canvas = canvas(&backend, 1);
+        let mut inner = font.0.borrow_mut();
+        let layout = inner
+            .lay_out(&canvas, " ab  c d\ne", 100, &TextOptions::default())
+            .unwrap();
+        let gaps = layout
+            .lines
+            .iter()
+            .map(|l| l.gaps.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(gaps, [vec![5, 7], vec![]]);
+        let last = layout.lines.iter().map(|l| l.last).collect::<Vec<_>>();
+        assert_eq!(last, [true, true]);
+    }
+
+    #[test]
+    fn spreads_space_over_the_gaps() {
+        let glyph = |x| PlacedGlyph {
+            atlas_index: 0,
+            row: 0,
+            src: Rect::default(),
+            x,
+        };
+        let glyphs = [glyph(0), glyph(10), glyph(20), glyph(30)];
+        let justified = justify_glyphs(&glyphs, &[1, 3], 5);
+        let xs = justified.iter().map(|g| g.x).collect::<Vec<_>>();
+        assert_eq!(xs, [0, 13, 23, 35]);
+    }
+
+    #[test]
+    fn justifies_lines_but_the_last_of_paragraphs() {
+        let (mock, backend, font) = mock(16);
+        let options = TextOptions {
+            align: TextAlign::Justified,
+            ..Default::default()
+        };
+        let canvas = canvas(&backend, 1);
+        let rect = Rect::new(0, 0, 40, 100);
+        let bounds = font
+            .draw_text_bounded(&canvas, "a b c d", Color::WHITE, rect, &options)
+            .unwrap();
+        let xs = |y| {
+            let copies = mock.borrow().copies.clone();
+            copies
+                .iter()
+                .filter(|r| r.y == y && r.w != 4)
+                .map(|r| r.x)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(xs(0), [0, 15, 30]);
+        assert_eq!(xs(16), [0]);
+        let widths = bounds.lines.iter().map(|l| l.w).collect::<Vec<_>>();
+        assert_eq!(widths, [40, 10]);
+    }
+
+    #[test]
+    fn measures_spaced_text_as_it_is_drawn() {
+        let (mock, backend, font) = mock(16);
+        let canvas = canvas(&backend, 1);
+        let spacing = TextSpacing {
+            letter_spacing: 3,
+            line_height: Some(30),
+        };
+        let options = TextOptions {
+            spacing,
+            ..Default::default()
+        };
+        // 44 wide, with 4 gaps between its clusters
+        let width = canvas.text_width(&font, "ab cd", spacing).unwrap();
+        assert_eq!(width, 56);
+        let drawn = canvas
+            .draw_text(&font, "ab cd", Point::new(0, 0), Color::WHITE, spacing)
+            .unwrap();
+        let extent = mock.borrow().copies.iter().map(|r| r.x + r.w as i32).max();
+        assert_eq!((drawn.w, extent), (width, Some(width as i32)));
+        let rect = Rect::new(0, 0, 100, 100);
+        let bounds = canvas
+            .draw_text_bounded(&font, "ab cd\nab", Color::WHITE, rect, options)
+            .unwrap();
+        let measured = canvas
+            .measure_text(&font, "ab cd\nab", None, options)
+            .unwrap();
+        assert_eq!((bounds.rect.w, bounds.rect.h), (width, 46));
+        assert_eq!((measured.width, measured.height), (width, 46));
+    }
+
+    #[test]
+    fn draws_in_rects_smaller_than_their_padding() {
+        let (_, backend, font) = mock(16);
+        let options = TextOptions {
+            padding: TextPadding {
+                left: 8,
+                right: 8,
+                top: 0,
+                bottom: 0,
+            },
+            ..Default::default()
+        };
+        let rect = Rect::new(0, 0, 10, 10);
+        let bounds = font
+            .draw_text_bounded(&canvas(&backend, 1), "ab", Color::WHITE, rect, &options)
+            .unwrap();
+        assert_eq!(bounds.lines.len(), 2);
+    }
+
+    #[test]
+    fn registers_the_mirrored_brackets() {
+        let (_, backend, font) = mock(16);
+        font.register_text("(a) <b>", &canvas(&backend, 1)).unwrap();
+        assert!(rows(&font, "()<>").iter().all(Option::is_some));
+        // only pairs of the text are kerned
+        let inner = font.0.borrow();
+        assert!(inner.kerning.contains_key(&('(', 'a')));
+        assert!(!inner.kerning.contains_key(&('a', '(')));
+        assert!(!inner.kerning.contains_key(&('b', '<')));
+    }
+
+    #[test]
+    fn kerns_pairs_as_they_are_drawn() {
+        let (mock, backend, font) = mock(16);
+        mock.borrow_mut().kerning = vec![(('A', 'V'), -2), (('ב', 'א'), -2)];
+        let canvas = canvas(&backend, 1);
+        assert_eq!(
+            canvas.text_width(&font, "AV", Default::default()).unwrap(),
+            2046
+        );
+        let drawn = canvas
+            .draw_text(
+                &font,
+                "AV",
+                Point::new(0, 0),
+                Color::WHITE,
+                Default::default(),
+            )
+            .unwrap();
+        assert_eq!(drawn.w, 2046);
+        assert_eq!(mock.borrow().copies[1].x, 1022);
+
+        // right to left, "בא" is drawn
+        assert_eq!(
+            canvas.text_width(&font, "אב", Default::default()).unwrap(),
+            18
+        );
+        let measured = canvas.measure_text(&font, "אב", None, Default::default());
+        assert_eq!(measured.unwrap().width, 18);
+        let inner = font.0.borrow();
+        assert!(!inner.kerning.contains_key(&('א', 'ב')));
+    }
+
+    #[test]
+    fn takes_missing_glyphs_from_the_fallbacks() {
+        let (mock, backend, font) = mock(16);
+        let fallback = Font::new(&backend, "fallback.ttf", 16).unwrap();
+        font.add_fallback(fallback);
+        mock.borrow_mut().missing = vec![(0, 'x'), (0, 'y'), (1, 'y'), (0, 'w'), (1, 'w')];
+        mock.borrow_mut().kerning = vec![(('a', 'x'), -2)];
+        let canvas = canvas(&backend, 1);
+        font.register_text("axy", &canvas).unwrap();
+
+        let inner = font.0.borrow();
+        let font_of = |glyph: &str| inner.entries[glyph].font.map(|font| font.0);
+        assert_eq!(font_of("a"), Some(0));
+        assert_eq!(font_of("x"), Some(1));
+        // missing from every font, drawn as a box
+        assert_eq!(font_of("y"), None);
+        let tofu = tofu_metrics(inner.glyphs_height);
+        let metrics = inner.entries["y"].metrics;
+        assert_eq!((metrics.advance, metrics.max_y), (tofu.advance, tofu.max_y));
+        // glyphs of different fonts are not kerned
+        assert_eq!(inner.kerning[&('a', 'x')], 0);
+        drop(inner);
+
+        // and not preloaded
+        font.preload_range(&canvas, 'v'..='w').unwrap();
+        assert!(rows(&font, "v")[0].is_some());
+        assert!(rows(&font, "w")[0].is_none());
+    }
+
+    #[test]
+    fn destroys_sized_fonts_and_their_fallbacks() {
+        let (mock, backend, font) = mock(16);
+        let fallback = Font::new(&backend, "fallback.ttf", 16).unwrap();
+        font.add_fallback(fallback);
+        let sized = font.with_size(32).unwrap();
+        assert_eq!(mock.borrow().fonts, [16, 16, 32, 32]);
+        assert_eq!(sized.glyphs_height(), 32);
+
+        drop(sized);
+        let mut destroyed = mock.borrow().destroyed_fonts.clone();
+        destroyed.sort_unstable();
+        assert_eq!(destroyed, [2, 3]);
+        drop(font);
+        assert_eq!(mock.borrow().destroyed_fonts.len(), 4);
+    }
+
+    #[test]
+    fn clips_lines_wider_than_the_max_width() {
+        assert_eq!(
+            lines("ab cdefg\nab", 50, TextOverflow::Clip),
+            [
+                (String::from("ab cd"), 44, true),
+                (String::from("ab"), 20, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_room_for_the_ellipsis() {
+        assert_eq!(
+            lines("ab cdefg", 50, TextOverflow::Ellipsis),
+            [(String::from("ab c"), 44, true)]
+        );
+        assert_eq!(
+            lines("abcdef", 50, TextOverflow::Ellipsis),
+            [(String::from("abcd"), 50, true)]
+        );
+        // without the whitespace the ellipsis follows
+        assert_eq!(
+            lines("ab cdefg", 35, TextOverflow::Ellipsis),
+            [(String::from("ab"), 30, true)]
+        );
+    }
 }
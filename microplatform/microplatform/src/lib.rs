@@ -2,9 +2,13 @@
 extern crate alloc;
 
 pub mod backend;
+mod bidi;
 pub mod canvas;
 pub mod font;
 pub mod input;
+#[cfg(test)]
+mod mock;
+pub mod rich_text;
 mod text;
 pub mod texture;
 pub mod types;
@@ -35,6 +39,7 @@ pub struct Context {
     input: InputState,
     events: Vec<Event>,
     quit: bool,
+    frame: u64,
 }
 
 impl Context {
@@ -44,6 +49,7 @@ impl Context {
             events: Vec::with_capacity(16),
             input: InputState::default(),
             quit: false,
+            frame: 0,
         }
     }
 
@@ -82,9 +88,10 @@ impl Context {
         Ok(())
     }
 
-    fn canvas(&self) -> Result<Canvas> {
+    fn canvas(&mut self) -> Result<Canvas<'_>> {
         self.backend.borrow_mut().render_clear()?;
-        Canvas::new(&self.backend, None)
+        self.frame += 1;
+        Canvas::new(&self.backend, None, self.frame)
     }
 
     fn millis(&self) -> Result<u64> {
@@ -0,0 +1,176 @@
+use crate::backend::Backend;
+use crate::canvas::Canvas;
+use crate::font::Font;
+use crate::types::*;
+use crate::{BackendRef, Result};
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+/// A backend recording the glyph copies and cleared rects. Glyphs of a font loaded at 16 px are
+/// 10 wide besides spaces, 4 wide, and scale with the font, but for uppercase letters, which
+/// fill a row of an atlas. Fonts have every glyph but the missing ones, and the kerning of pairs
+/// of glyphs is the same in every font.
+#[derive(Default)]
+pub(crate) struct Mock {
+    pub textures: u32,
+    // the scales of the loaded fonts, by id
+    pub fonts: Vec<u8>,
+    // the glyphs missing from the fonts, by id
+    pub missing: Vec<(u32, char)>,
+    pub destroyed_fonts: Vec<u32>,
+    pub kerning: Vec<((char, char), i32)>,
+    pub copies: Vec<Rect>,
+    pub clears: Vec<Rect>,
+}
+
+fn advance_of(glyph: char, scale: u8) -> u32 {
+    match glyph {
+        c if c.is_ascii_uppercase() => 1024,
+        ' ' => 4 * scale as u32 / 16,
+        _ => 10 * scale as u32 / 16,
+    }
+}
+
+impl Backend for Mock {
+    fn window_set_config(&mut self, _config: WindowConfig) -> Result {
+        Ok(())
+    }
+
+    fn texture_create(&mut self, w: u32, h: u32) -> Result<TextureData> {
+        self.textures += 1;
+        Ok(TextureData {
+            id: TextureId(self.textures),
+            width: w,
+            height: h,
+        })
+    }
+
+    fn texture_load(&mut self, _path: &str) -> Result<TextureData> {
+        self.texture_create(16, 16)
+    }
+
+    fn texture_destroy(&mut self, _id: TextureId) -> Result {
+        Ok(())
+    }
+
+    fn font_load(&mut self, _path: &str, scale: u8) -> Result<FontData> {
+        self.fonts.push(scale);
+        let scale = scale as i32;
+        Ok(FontData {
+            id: FontId(self.fonts.len() as u32 - 1),
+            glyphs_height: scale as u32,
+            metrics: FontMetrics {
+                ascent: scale * 3 / 4,
+                descent: -scale / 4,
+                line_gap: 0,
+            },
+        })
+    }
+
+    fn font_load_size(&mut self, _font: FontId, scale: u8) -> Result<FontData> {
+        self.font_load("", scale)
+    }
+
+    fn font_destroy(&mut self, id: FontId) -> Result {
+        self.destroyed_fonts.push(id.0);
+        Ok(())
+    }
+
+    fn font_has_glyph(&mut self, font: FontId, glyph: char) -> Result<bool> {
+        Ok(!self.missing.contains(&(font.0, glyph)))
+    }
+
+    fn font_glyph_metrics(&mut self, font: FontId, glyph: char) -> Result<GlyphMetrics> {
+        let advance = advance_of(glyph, self.fonts[font.0 as usize]);
+        Ok(GlyphMetrics {
+            min_x: 0,
+            max_x: advance as i32,
+            min_y: 0,
+            max_y: 16,
+            advance,
+        })
+    }
+
+    fn font_cluster_metrics(&mut self, font: FontId, cluster: &str) -> Result<GlyphMetrics> {
+        self.font_glyph_metrics(font, cluster.chars().next().unwrap())
+    }
+
+    fn font_glyph_kerning(&mut self, _font: FontId, previous: char, glyph: char) -> Result<i32> {
+        let kerning = self
+            .kerning
+            .iter()
+            .find(|(pair, _)| *pair == (previous, glyph));
+        Ok(kerning.map_or(0, |&(_, kerning)| kerning))
+    }
+
+    fn render_set_logical_size(&mut self, _w: u32, _h: u32) -> Result {
+        Ok(())
+    }
+
+    fn render_set_target(&mut self, _target: Option<TextureId>) -> Result {
+        Ok(())
+    }
+
+    fn render_set_draw_color(&mut self, _color: Color) -> Result {
+        Ok(())
+    }
+
+    fn render_clear(&mut self) -> Result {
+        Ok(())
+    }
+
+    fn render_present(&mut self) -> Result {
+        Ok(())
+    }
+
+    fn render_copy_texture(&mut self, _texture: TextureId, options: CopyTextureOptions) -> Result {
+        self.copies.extend(options.dest);
+        Ok(())
+    }
+
+    fn render_fill_rect(&mut self, _rect: Option<Rect>, _color: Color) -> Result {
+        Ok(())
+    }
+
+    fn render_draw_rect(&mut self, _rect: Option<Rect>, _color: Color) -> Result {
+        Ok(())
+    }
+
+    fn render_clear_rect(&mut self, rect: Rect) -> Result {
+        self.clears.push(rect);
+        Ok(())
+    }
+
+    fn render_font_glyph(&mut self, _font: FontId, _glyph: char, _origin: Point) -> Result {
+        Ok(())
+    }
+
+    fn render_font_cluster(&mut self, _font: FontId, _cluster: &str, _origin: Point) -> Result {
+        Ok(())
+    }
+
+    fn events_pump(&mut self, _events: &mut Vec<Event>) {}
+
+    fn input_mouse_position(&mut self) -> Result<(i32, i32)> {
+        Ok((0, 0))
+    }
+
+    fn system_get_millis(&mut self) -> Result<u64> {
+        Ok(0)
+    }
+
+    fn system_log(&self, _s: &str) {}
+}
+
+/// A mock backend and a font loaded at `scale` px on it.
+pub(crate) fn mock(scale: u8) -> (Rc<RefCell<Mock>>, BackendRef, Font) {
+    let mock = Rc::new(RefCell::new(Mock::default()));
+    let backend: BackendRef = mock.clone();
+    let font = Font::new(&backend, "font.ttf", scale).unwrap();
+    (mock, backend, font)
+}
+
+pub(crate) fn canvas(backend: &BackendRef, frame: u64) -> Canvas<'static> {
+    Canvas::new(backend, None, frame).unwrap()
+}
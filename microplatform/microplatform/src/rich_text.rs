@@ -0,0 +1,469 @@
+use crate::bidi::Bidi;
+use crate::canvas::Canvas;
+use crate::font::Font;
+use crate::text::BoundedLines;
+use crate::{Color, Point, Rect, Result, TextBounds, TextPadding, TextSpacing, TextWrap};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// The style of a span of `RichText`. `color` overrides the color the text is drawn with.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TextStyle {
+    pub color: Option<Color>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Span {
+    /// The byte range of the span in `RichText::text`.
+    pub range: Range<usize>,
+    pub style: TextStyle,
+}
+
+/// Text made of styled spans, built with `push` or parsed from markup.
+#[derive(Clone, Debug, Default)]
+pub struct RichText {
+    text: String,
+    spans: Vec<Span>,
+}
+
+impl RichText {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses text where `[b]`, `[i]`, `[u]` and `[color=#rrggbb]` (or `#rrggbbaa`) start bold,
+    /// italic, underlined and colored text, each ended by the matching `[/b]`, `[/i]`, `[/u]` or
+    /// `[/color]`. Tags may be nested. `[[` stands for `[`.
+    pub fn parse(markup: &str) -> Result<Self> {
+        let mut rich_text = Self::new();
+        let mut styles = vec![(String::new(), TextStyle::default())];
+        let mut rest = markup;
+        while let Some(open) = rest.find('[') {
+            let (style_name, style) = styles.last().unwrap().clone();
+            rich_text.push(&rest[..open], style);
+            rest = &rest[open + 1..];
+            if let Some(escaped) = rest.strip_prefix('[') {
+                rich_text.push("[", style);
+                rest = escaped;
+                continue;
+            }
+            let close = rest
+                .find(']')
+                .ok_or_else(|| String::from("Unterminated markup tag."))?;
+            let tag = &rest[..close];
+            rest = &rest[close + 1..];
+            match tag.strip_prefix('/') {
+                Some(name) if styles.len() > 1 && name == style_name => {
+                    styles.pop();
+                }
+                Some(name) => return Err(format!("Unexpected closing tag [/{name}].")),
+                None => {
+                    let (name, style) = apply_tag(tag, style)?;
+                    styles.push((String::from(name), style));
+                }
+            }
+        }
+        if let Some((name, _)) = styles.get(1) {
+            return Err(format!("Unclosed tag [{name}]."));
+        }
+        rich_text.push(rest, styles[0].1);
+        Ok(rich_text)
+    }
+
+    /// Appends `text` drawn with `style`.
+    pub fn push(&mut self, text: &str, style: TextStyle) -> &mut Self {
+        if text.is_empty() {
+            return self;
+        }
+        let start = self.text.len();
+        self.text.push_str(text);
+        match self.spans.last_mut() {
+            Some(span) if span.style == style => span.range.end = self.text.len(),
+            _ => self.spans.push(Span {
+                range: start..self.text.len(),
+                style,
+            }),
+        }
+        self
+    }
+
+    /// The text without markup.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The spans covering the whole text, in order.
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+}
+
+/// Finds the styles of a `RichText` at byte indices close to each other by walking its spans
+/// from the one of the last index.
+struct StyleCursor<'a> {
+    spans: &'a [Span],
+    span: usize,
+}
+
+impl<'a> StyleCursor<'a> {
+    fn new(text: &'a RichText) -> Self {
+        Self {
+            spans: text.spans(),
+            span: 0,
+        }
+    }
+
+    fn style_at(&mut self, index: usize) -> TextStyle {
+        while self.span > 0 && index < self.spans[self.span].range.start {
+            self.span -= 1;
+        }
+        while self.span + 1 < self.spans.len() && index >= self.spans[self.span].range.end {
+            self.span += 1;
+        }
+        self.spans
+            .get(self.span)
+            .filter(|span| span.range.contains(&index))
+            .map_or_else(TextStyle::default, |span| span.style)
+    }
+}
+
+/// Returns the name of `tag`, e.g. `color` for `color=#ff0000`, and `style` modified by it.
+fn apply_tag(tag: &str, mut style: TextStyle) -> Result<(&str, TextStyle)> {
+    let (name, value) = tag.split_once('=').unwrap_or((tag, ""));
+    match (name, value) {
+        ("b", "") => style.bold = true,
+        ("i", "") => style.italic = true,
+        ("u", "") => style.underline = true,
+        ("color", value) => style.color = Some(parse_color(value)?),
+        _ => return Err(format!("Unknown markup tag [{tag}].")),
+    }
+    Ok((name, style))
+}
+
+fn parse_color(value: &str) -> Result<Color> {
+    let error = || format!("Invalid color {value}, expected #rrggbb or #rrggbbaa.");
+    let hex = value.strip_prefix('#').ok_or_else(error)?;
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return Err(error());
+    }
+    let channel = |i: usize| match hex.get(i..i + 2) {
+        Some(channel) => u8::from_str_radix(channel, 16).map_err(|_| error()),
+        None => Ok(255),
+    };
+    Ok(Color::new(
+        channel(0)?,
+        channel(2)?,
+        channel(4)?,
+        channel(6)?,
+    ))
+}
+
+/// The fonts `RichText` is drawn with. Styles without a font of their own use `regular`, and
+/// bold italic text without `bold_italic` uses `bold`.
+#[derive(Copy, Clone)]
+pub struct FontFamily<'a> {
+    pub regular: &'a Font,
+    pub bold: Option<&'a Font>,
+    pub italic: Option<&'a Font>,
+    pub bold_italic: Option<&'a Font>,
+}
+
+impl<'a> FontFamily<'a> {
+    pub fn new(regular: &'a Font) -> Self {
+        Self {
+            regular,
+            bold: None,
+            italic: None,
+            bold_italic: None,
+        }
+    }
+
+    pub fn font(&self, style: TextStyle) -> &'a Font {
+        let font = match (style.bold, style.italic) {
+            (true, true) => self.bold_italic.or(self.bold).or(self.italic),
+            (true, false) => self.bold,
+            (false, true) => self.italic,
+            (false, false) => None,
+        };
+        font.unwrap_or(self.regular)
+    }
+}
+
+/// How `Canvas::draw_rich_text_bounded` lays out text in its rect. Lines wrap at its width and
+/// start on the side their paragraph starts.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RichTextOptions {
+    pub padding: TextPadding,
+    pub wrap: TextWrap,
+    /// Spaces the lines as the glyphs of `FontFamily::regular`.
+    pub spacing: TextSpacing,
+}
+
+/// See `Canvas::draw_rich_text_bounded`.
+pub(crate) fn draw_rich_text_bounded(
+    canvas: &Canvas,
+    fonts: FontFamily,
+    text: &RichText,
+    color: Color,
+    rect: Rect,
+    options: &RichTextOptions,
+) -> Result<TextBounds> {
+    let RichTextOptions {
+        padding,
+        wrap,
+        spacing,
+    } = *options;
+    let hyphenate = matches!(wrap, TextWrap::Hyphenate);
+    for span in text.spans() {
+        let font = fonts.font(span.style);
+        font.register_text(&text.text[span.range.clone()], canvas)?;
+        if hyphenate {
+            font.register_text("-", canvas)?;
+        }
+    }
+
+    let inner_rect = Rect {
+        x: rect.x + padding.left as i32,
+        y: rect.y + padding.top as i32,
+        w: rect
+            .w
+            .saturating_sub(padding.left as u32 + padding.right as u32),
+        h: rect
+            .h
+            .saturating_sub(padding.top as u32 + padding.bottom as u32),
+    };
+    let line_height = spacing.line_height(fonts.regular.glyphs_height());
+
+    let bidi = Bidi::new(text.text());
+    let mut cursor = StyleCursor::new(text);
+    let lines = text
+        .text()
+        .bounded_lines(inner_rect.w, hyphenate, |i, previous, c| {
+            let style = cursor.style_at(i);
+            // no kerning across spans, which may use different fonts
+            let previous = previous.filter(|_| i > 0 && cursor.style_at(i - 1) == style);
+            fonts
+                .font(style)
+                .advance(previous, c, bidi.is_rtl_cluster(i), spacing.letter_spacing)
+        })
+        .collect::<Vec<_>>();
+
+    let mut y_cursor = inner_rect.y;
+    let mut line_rects = Vec::with_capacity(lines.len());
+    for line in lines.iter() {
+        // lines start on the side their paragraph starts
+        let mut x_cursor = inner_rect.x;
+        if bidi.is_rtl(line.start) {
+            x_cursor += inner_rect.w.saturating_sub(line.width) as i32;
+        }
+        let start = x_cursor;
+        let clusters = bidi
+            .visual_order(line)
+            .into_iter()
+            .map(|(i, c)| (cursor.style_at(i), c))
+            .collect::<Vec<_>>();
+        for run in clusters.chunk_by(|a, b| a.0 == b.0) {
+            let style = run[0].0;
+            let run = run.iter().map(|&(_, c)| c);
+            let position = Point::new(x_cursor, y_cursor);
+            x_cursor = draw_run(
+                canvas,
+                fonts,
+                run,
+                style,
+                color,
+                position,
+                spacing.letter_spacing,
+            )?;
+        }
+        line_rects.push(Rect::new(
+            start,
+            y_cursor,
+            (x_cursor - start) as u32,
+            fonts.regular.glyphs_height(),
+        ));
+        y_cursor += line_height as i32;
+    }
+    Ok(TextBounds::new(inner_rect.point(), line_rects))
+}
+
+/// Draws clusters in `style` from `position`, on the baseline of `fonts.regular` in the line at
+/// its `y`, returning where they end.
+fn draw_run<'t>(
+    canvas: &Canvas,
+    fonts: FontFamily,
+    run: impl IntoIterator<Item = &'t str>,
+    style: TextStyle,
+    color: Color,
+    position: Point,
+    letter_spacing: i32,
+) -> Result<i32> {
+    let font = fonts.font(style);
+    let color = style.color.unwrap_or(color);
+    let x = position.x;
+    let y = position.y + fonts.regular.metrics().ascent - font.metrics().ascent;
+    let end = font.draw_clusters(canvas, run, Point::new(x, y), color, letter_spacing)?;
+    if style.underline {
+        let underline = Rect::new(x, y + font.glyphs_height() as i32 - 1, (end - x) as u32, 1);
+        canvas.fill_rect(Some(underline), color)?;
+    }
+    Ok(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{canvas, mock, Mock};
+    use core::cell::RefCell;
+
+    fn spans(rich_text: &RichText) -> Vec<(&str, TextStyle)> {
+        rich_text
+            .spans()
+            .iter()
+            .map(|span| (&rich_text.text()[span.range.clone()], span.style))
+            .collect()
+    }
+
+    #[test]
+    fn parses_nested_tags() {
+        let rich_text = RichText::parse("a [b]bold [color=#ff000080]red[/color][/b] [[x]").unwrap();
+        let bold = TextStyle {
+            bold: true,
+            ..Default::default()
+        };
+        let red = TextStyle {
+            color: Some(Color::new(255, 0, 0, 128)),
+            ..bold
+        };
+        assert_eq!(rich_text.text(), "a bold red [x]");
+        assert_eq!(
+            spans(&rich_text),
+            [
+                ("a ", TextStyle::default()),
+                ("bold ", bold),
+                ("red", red),
+                (" [x]", TextStyle::default())
+            ]
+        );
+    }
+
+    #[test]
+    fn merges_spans_of_the_same_style() {
+        let rich_text = RichText::parse("[u]a[/u][u]b[/u]").unwrap();
+        assert_eq!(rich_text.spans().len(), 1);
+    }
+
+    #[test]
+    fn walks_the_spans_both_ways() {
+        let rich_text = RichText::parse("a[b]bc[/b][i]d[/i]").unwrap();
+        let mut cursor = StyleCursor::new(&rich_text);
+        let styles = [3, 0, 2, 1, 3, 4]
+            .map(|i| cursor.style_at(i))
+            .map(|style| (style.bold, style.italic));
+        assert_eq!(
+            styles,
+            [
+                (false, true),
+                (false, false),
+                (true, false),
+                (true, false),
+                (false, true),
+                (false, false)
+            ]
+        );
+    }
+
+    /// The x, y and width of the glyphs drawn.
+    fn copies(mock: &RefCell<Mock>) -> Vec<(i32, i32, u32)> {
+        let copies = mock.borrow().copies.clone();
+        copies.iter().map(|r| (r.x, r.y, r.w)).collect()
+    }
+
+    #[test]
+    fn draws_spans_in_their_fonts() {
+        let (mock, backend, regular) = mock(16);
+        let bold = Font::new(&backend, "bold.ttf", 32).unwrap();
+        let fonts = FontFamily {
+            bold: Some(&bold),
+            ..FontFamily::new(&regular)
+        };
+        let text = RichText::parse("ab [b]cd[/b] e").unwrap();
+        let options = RichTextOptions {
+            spacing: TextSpacing {
+                letter_spacing: 0,
+                line_height: Some(20),
+            },
+            ..Default::default()
+        };
+        let rect = Rect::new(0, 0, 70, 100);
+        let bounds = draw_rich_text_bounded(
+            &canvas(&backend, 1),
+            fonts,
+            &text,
+            Color::WHITE,
+            rect,
+            &options,
+        )
+        .unwrap();
+        // bold glyphs share the baseline of the regular ones
+        assert_eq!(
+            copies(&mock),
+            [
+                (0, 0, 10),
+                (10, 0, 10),
+                (20, 0, 4),
+                (24, -12, 20),
+                (44, -12, 20),
+                (0, 20, 10)
+            ]
+        );
+        let lines = bounds.lines.iter().map(|l| (l.y, l.w)).collect::<Vec<_>>();
+        assert_eq!(lines, [(0, 64), (20, 10)]);
+    }
+
+    #[test]
+    fn hyphenates_in_the_style_of_the_last_cluster() {
+        let (mock, backend, regular) = mock(16);
+        let bold = Font::new(&backend, "bold.ttf", 32).unwrap();
+        let fonts = FontFamily {
+            bold: Some(&bold),
+            ..FontFamily::new(&regular)
+        };
+        let text = RichText::parse("ab[b]cdef[/b]").unwrap();
+        let options = RichTextOptions {
+            wrap: TextWrap::Hyphenate,
+            ..Default::default()
+        };
+        let rect = Rect::new(0, 0, 35, 100);
+        let bounds = draw_rich_text_bounded(
+            &canvas(&backend, 1),
+            fonts,
+            &text,
+            Color::WHITE,
+            rect,
+            &options,
+        )
+        .unwrap();
+        // the regular hyphen leaves room for "ab", where a bold one would not
+        let first = copies(&mock)
+            .into_iter()
+            .filter(|c| c.1 == 0)
+            .collect::<Vec<_>>();
+        assert_eq!(first, [(0, 0, 10), (10, 0, 10), (20, 0, 10)]);
+        assert_eq!(bounds.lines[0].w, 30);
+    }
+
+    #[test]
+    fn rejects_invalid_markup() {
+        assert!(RichText::parse("[b]a").is_err());
+        assert!(RichText::parse("[b]a[/i]").is_err());
+        assert!(RichText::parse("a[/b]").is_err());
+        assert!(RichText::parse("[color=red]a[/color]").is_err());
+        assert!(RichText::parse("[wave]a[/wave]").is_err());
+        assert!(RichText::parse("[b").is_err());
+    }
+}
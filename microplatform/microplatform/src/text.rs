@@ -1,97 +1,434 @@
-use core::str::CharIndices;
-use core::iter::Peekable;
+use alloc::vec::Vec;
 
+/// Splits text into lines no wider than `max_width`. `get_width` returns the width of the
+/// grapheme cluster at a byte index given the one before it, if any, so that kerning and styled
+/// spans can be included. The width of the hyphen of a split word is asked with the index of the
+/// last cluster before it, in whose style it is drawn.
+///
+/// Lines break at whitespace, which is not part of any line, after hyphens and at `'\n'`. A word
+/// too long for a line of its own is split where the line is full, after a hyphen inserted by the
+/// split if `hyphenate` is set.
 pub trait BoundedLines<'a, F>
 where
     Self: 'a,
-    F: FnMut(char) -> u32,
+    F: FnMut(usize, Option<&str>, &str) -> u32,
 {
-    fn bounded_lines(&self, max_width: u32, get_width: F) -> BoundedLinesIter<'a, F>;
+    fn bounded_lines(
+        &self,
+        max_width: u32,
+        hyphenate: bool,
+        get_width: F,
+    ) -> BoundedLinesIter<'a, F>;
 }
 
 impl<'a, F> BoundedLines<'a, F> for &'a str
 where
-    F: FnMut(char) -> u32,
+    F: FnMut(usize, Option<&str>, &str) -> u32,
 {
-    fn bounded_lines(&self, max_width: u32, get_width: F) -> BoundedLinesIter<'a, F> {
-        let iter = self.char_indices().peekable();
+    fn bounded_lines(
+        &self,
+        max_width: u32,
+        hyphenate: bool,
+        get_width: F,
+    ) -> BoundedLinesIter<'a, F> {
         BoundedLinesIter {
             text: self,
-            iter,
             max_width,
+            hyphenate,
             char_width: get_width,
-            word_start: 0,
             line_start: 0,
-            line_end: 0,
-            line_width: 0,
+            wrapped: false,
             finished: false,
+            advances: Vec::new(),
         }
     }
 }
 
+/// A line of `BoundedLines`, whose width includes the hyphen if it has one.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Line<'a> {
+    pub text: &'a str,
+    /// The byte index of `text` in the whole text.
+    pub start: usize,
+    pub width: u32,
+    pub hyphenated: bool,
+}
+
 pub struct BoundedLinesIter<'a, F>
 where
-    F: FnMut(char) -> u32,
+    F: FnMut(usize, Option<&str>, &str) -> u32,
 {
     text: &'a str,
-    iter: Peekable<CharIndices<'a>>,
     max_width: u32,
+    hyphenate: bool,
     char_width: F,
-    word_start: usize,
     line_start: usize,
-    line_end: usize,
-    line_width: u32,
+    // true if the previous line was wrapped, so that this one starts after whitespace to skip
+    wrapped: bool,
     finished: bool,
+    // the start and width before every cluster of the current line, to split words
+    advances: Vec<(usize, u32)>,
+}
+
+impl<'a, F> BoundedLinesIter<'a, F>
+where
+    F: FnMut(usize, Option<&str>, &str) -> u32,
+{
+    fn line(&mut self, end: usize, width: u32, next_start: usize, hyphenated: bool) -> Line<'a> {
+        let line = Line {
+            text: &self.text[self.line_start..end],
+            start: self.line_start,
+            width,
+            hyphenated,
+        };
+        self.line_start = next_start;
+        line
+    }
+
+    /// Splits the word at the end of the line before the cluster at `index` of `advances`,
+    /// keeping at least one cluster on the line.
+    fn split_word(&mut self, mut index: usize) -> Line<'a> {
+        if !self.hyphenate {
+            let (end, width) = self.advances[index.max(1)];
+            return self.line(end, width, end, false);
+        }
+        // the hyphen is only registered by callers that hyphenate
+        index = index.max(1);
+        let mut hyphen_width = (self.char_width)(self.advances[index - 1].0, None, "-");
+        while index > 1 && self.advances[index].1 + hyphen_width > self.max_width {
+            index -= 1;
+            hyphen_width = (self.char_width)(self.advances[index - 1].0, None, "-");
+        }
+        let (end, width) = self.advances[index];
+        self.line(end, width + hyphen_width, end, true)
+    }
 }
 
 impl<'a, F> Iterator for BoundedLinesIter<'a, F>
 where
-    F: FnMut(char) -> u32,
+    F: FnMut(usize, Option<&str>, &str) -> u32,
 {
-    type Item = (&'a str, u32);
+    type Item = Line<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.finished {
             return None;
         }
+        if self.wrapped {
+            let rest = &self.text[self.line_start..];
+            let trimmed = rest.trim_start_matches(|c: char| c.is_whitespace() && c != '\n');
+            self.line_start += rest.len() - trimmed.len();
+            self.wrapped = false;
+        }
+
+        let start = self.line_start;
+        let mut width = 0;
+        // the end and width of the line without its trailing whitespace
+        let mut content = (start, 0);
+        // the end and width of the line if wrapped at the last opportunity, and the next start
+        let mut wrap = None;
+        let mut previous = None;
+        self.advances.clear();
 
-        loop {
-            match self.iter.next() {
-                Some((i, c)) => {
-                    if self.iter.peek().is_some() && (c != ' ' || i == 0) {
-                        continue;
+        for (offset, c) in graphemes(&self.text[start..]) {
+            let i = start + offset;
+            let next = i + c.len();
+            if c.ends_with('\n') {
+                return Some(self.line(content.0, content.1, next, false));
+            }
+            let char_width = (self.char_width)(i, previous.replace(c), c);
+            if c.starts_with(char::is_whitespace) {
+                wrap = Some((content, next));
+                width += char_width;
+                continue;
+            }
+            if width + char_width > self.max_width && i > start {
+                self.wrapped = true;
+                return Some(match wrap {
+                    Some(((end, line_width), next_start)) => {
+                        self.line(end, line_width, next_start, false)
                     }
-                    let word = &self.text[self.word_start..i];
-                    let word_width: u32 = word.chars().map(&mut self.char_width).sum();
-                    let is_line_break = self.line_width + word_width > self.max_width;
-                    if is_line_break {
-                        let line = &self.text[self.line_start..self.line_end];
-                        let width = self.line_width;
-                        // workaround for bounds that are smaller than a single whitespace...
-                        self.line_start = if word.chars().next().unwrap() == ' ' {
-                            self.word_start + 1
-                        } else {
-                            self.word_start
-                        };
-                        self.line_end = i;
-                        self.line_width = word_width;
-                        self.word_start = i;
-                        return Some((line, width));
-                    } else {
-                        let whitespace_width = (self.char_width)(' ');
-                        self.line_width += word_width + whitespace_width;
-                        self.line_end = i;
-                        self.word_start = i + 1;
+                    None => {
+                        self.advances.push((i, width));
+                        self.split_word(self.advances.len() - 1)
                     }
-                }
-                None => {
-                    let line = &self.text[self.line_start..];
-                    let width = self.line_width;
-                    self.finished = true;
-                    return Some((line, width));
-                }
+                });
+            }
+            self.advances.push((i, width));
+            width += char_width;
+            content = (next, width);
+            if c == "-" {
+                wrap = Some((content, next));
+            }
+        }
+
+        self.finished = true;
+        Some(self.line(content.0, content.1, self.text.len(), false))
+    }
+}
+
+/// Splits text into grapheme clusters, the units text is drawn, measured and wrapped in, with
+/// their byte indexes.
+///
+/// The clusters approximate the Unicode extended grapheme clusters without its tables: combining
+/// marks of common scripts, variation selectors, emoji modifiers and tags extend a cluster,
+/// zero-width joiners join the char after them, regional indicators pair into flags and `"\r\n"`
+/// is one cluster.
+pub fn graphemes(text: &str) -> Graphemes<'_> {
+    Graphemes { text, index: 0 }
+}
+
+pub struct Graphemes<'a> {
+    text: &'a str,
+    index: usize,
+}
+
+impl<'a> Iterator for Graphemes<'a> {
+    type Item = (usize, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.index;
+        let mut chars = self.text[start..].char_indices();
+        let (_, mut previous) = chars.next()?;
+        let mut end = start + previous.len_utf8();
+        // true while a regional indicator waits for the other half of its flag
+        let mut flag = is_regional_indicator(previous);
+        for (offset, c) in chars {
+            let joins = match previous {
+                '\r' => c == '\n',
+                '\n' => false,
+                _ if c == '\r' || c == '\n' => false,
+                ZERO_WIDTH_JOINER => true,
+                _ if is_extend(c) => true,
+                _ => flag && is_regional_indicator(c),
+            };
+            if !joins {
+                break;
             }
+            flag = flag && !is_regional_indicator(c);
+            previous = c;
+            end = start + offset + c.len_utf8();
         }
+        self.index = end;
+        Some((start, &self.text[start..end]))
     }
 }
 
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+
+const EXTEND: &[(char, char)] = &[
+    ('\u{0300}', '\u{036F}'),
+    ('\u{0483}', '\u{0489}'),
+    ('\u{0591}', '\u{05BD}'),
+    ('\u{0610}', '\u{061A}'),
+    ('\u{064B}', '\u{065F}'),
+    ('\u{0900}', '\u{0903}'),
+    ('\u{093A}', '\u{094F}'),
+    ('\u{0E31}', '\u{0E31}'),
+    ('\u{0E34}', '\u{0E3A}'),
+    ('\u{0E47}', '\u{0E4E}'),
+    ('\u{1AB0}', '\u{1AFF}'),
+    ('\u{1DC0}', '\u{1DFF}'),
+    ('\u{200C}', '\u{200D}'),
+    ('\u{20D0}', '\u{20FF}'),
+    ('\u{FE00}', '\u{FE0F}'),
+    ('\u{FE20}', '\u{FE2F}'),
+    ('\u{1F3FB}', '\u{1F3FF}'),
+    ('\u{E0020}', '\u{E007F}'),
+    ('\u{E0100}', '\u{E01EF}'),
+];
+
+fn is_extend(c: char) -> bool {
+    EXTEND
+        .iter()
+        .any(|&(first, last)| (first..=last).contains(&c))
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str, max_width: u32, hyphenate: bool) -> Vec<(&str, u32, bool)> {
+        text.bounded_lines(max_width, hyphenate, |_, _, _| 1)
+            .map(|line| (line.text, line.width, line.hyphenated))
+            .collect()
+    }
+
+    #[test]
+    fn breaks_between_words() {
+        assert_eq!(
+            lines("the quick brown fox", 10, false),
+            [("the quick", 9, false), ("brown fox", 9, false)]
+        );
+    }
+
+    #[test]
+    fn skips_whitespace_at_breaks() {
+        assert_eq!(
+            lines("a   b  ", 2, false),
+            [("a", 1, false), ("b", 1, false)]
+        );
+    }
+
+    #[test]
+    fn breaks_after_hyphens() {
+        assert_eq!(
+            lines("well-known fact", 6, false),
+            [("well-", 5, false), ("known", 5, false), ("fact", 4, false)]
+        );
+    }
+
+    #[test]
+    fn splits_long_words() {
+        assert_eq!(
+            lines("abcdefgh", 3, false),
+            [("abc", 3, false), ("def", 3, false), ("gh", 2, false)]
+        );
+        assert_eq!(
+            lines("a abcdefgh", 3, false),
+            [
+                ("a", 1, false),
+                ("abc", 3, false),
+                ("def", 3, false),
+                ("gh", 2, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn hyphenates_split_words() {
+        assert_eq!(
+            lines("abcdefgh", 3, true),
+            [
+                ("ab", 3, true),
+                ("cd", 3, true),
+                ("ef", 3, true),
+                ("gh", 2, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn measures_the_hyphen_after_the_last_cluster_of_the_line() {
+        let mut hyphens = Vec::new();
+        let lines = "abcdef"
+            .bounded_lines(4, true, |i, _, c| {
+                if c == "-" {
+                    hyphens.push(i);
+                }
+                1
+            })
+            .map(|line| line.text)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, ["abc", "def"]);
+        assert_eq!(hyphens, [3, 2]);
+    }
+
+    #[test]
+    fn only_measures_the_hyphen_when_hyphenating() {
+        let lines = "abcdefgh"
+            .bounded_lines(3, false, |_, _, c| {
+                assert_ne!(c, "-");
+                1
+            })
+            .count();
+        assert_eq!(lines, 3);
+    }
+
+    #[test]
+    fn keeps_a_char_per_line() {
+        assert_eq!(lines("ab", 0, false), [("a", 1, false), ("b", 1, false)]);
+        assert_eq!(lines("ab", 0, true), [("a", 2, true), ("b", 1, false)]);
+    }
+
+    #[test]
+    fn breaks_at_newlines() {
+        assert_eq!(
+            lines("ab  \ncd\n\ne", 10, false),
+            [
+                ("ab", 2, false),
+                ("cd", 2, false),
+                ("", 0, false),
+                ("e", 1, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_text_is_one_empty_line() {
+        assert_eq!(lines("", 10, false), [("", 0, false)]);
+    }
+
+    #[test]
+    fn counts_chars_rather_than_bytes() {
+        assert_eq!(
+            lines("héllo wörld", 5, false),
+            [("héllo", 5, false), ("wörld", 5, false)]
+        );
+    }
+
+    #[test]
+    fn passes_the_previous_char_for_kerning() {
+        let kerned = |_, previous: Option<&str>, c: &str| match (previous, c) {
+            (Some("A"), "V") => 0,
+            _ => 1,
+        };
+        let line = "AVAV".bounded_lines(10, false, kerned).next().unwrap();
+        assert_eq!(line.width, 2);
+    }
+
+    #[test]
+    fn counts_grapheme_clusters() {
+        assert_eq!(
+            lines("e\u{301}e\u{301} 👍🏽👍🏽", 2, false),
+            [("e\u{301}e\u{301}", 2, false), ("👍🏽👍🏽", 2, false)]
+        );
+    }
+
+    fn clusters(text: &str) -> Vec<&str> {
+        graphemes(text).map(|(_, cluster)| cluster).collect()
+    }
+
+    #[test]
+    fn keeps_combining_marks_with_their_base() {
+        assert_eq!(clusters("a\u{301}\u{302}b"), ["a\u{301}\u{302}", "b"]);
+        assert_eq!(clusters("\u{301}a"), ["\u{301}", "a"]);
+    }
+
+    #[test]
+    fn joins_emoji_sequences() {
+        let family = "👨\u{200D}👩\u{200D}👧";
+        assert_eq!(clusters(family), [family]);
+        assert_eq!(clusters("❤\u{FE0F}x"), ["❤\u{FE0F}", "x"]);
+        assert_eq!(clusters("👋🏿👋"), ["👋🏿", "👋"]);
+    }
+
+    #[test]
+    fn pairs_regional_indicators() {
+        assert_eq!(clusters("🇩🇪🇫🇷🇮"), ["🇩🇪", "🇫🇷", "🇮"]);
+    }
+
+    #[test]
+    fn keeps_line_breaks_apart() {
+        assert_eq!(clusters("a\r\n\r\u{301}"), ["a", "\r\n", "\r", "\u{301}"]);
+        assert_eq!(
+            graphemes("é\nb").collect::<Vec<_>>(),
+            [(0, "é"), (2, "\n"), (3, "b")]
+        );
+    }
+
+    #[test]
+    fn passes_byte_indexes() {
+        let mut indexes = Vec::new();
+        let lines = "aé b".bounded_lines(10, false, |i, _, _| {
+            indexes.push(i);
+            1
+        });
+        assert_eq!(lines.map(|line| line.start).collect::<Vec<_>>(), [0]);
+        assert_eq!(indexes, [0, 1, 3, 4]);
+    }
+}
@@ -1,3 +1,5 @@
+use alloc::vec::Vec;
+
 pub type ResourceId = u32;
 
 #[derive(Copy, Clone, Debug)]
@@ -17,17 +19,46 @@ pub struct FontId(pub ResourceId);
 pub struct FontData {
     pub id: FontId,
     pub glyphs_height: u32,
+    pub metrics: FontMetrics,
+}
+
+/// The vertical metrics of a font, in pixels up from the baseline.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FontMetrics {
+    pub ascent: i32,
+    /// Negative for glyphs reaching below the baseline.
+    pub descent: i32,
+    /// The recommended space between lines, besides the glyphs height.
+    pub line_gap: i32,
 }
 
+/// The horizontal alignment of lines. `Start` and `End` follow the direction of the paragraph,
+/// right to left text starting on the right.
 #[derive(Copy, Clone, Debug, Default)]
 pub enum TextAlign {
     #[default]
+    Start,
+    End,
     Left,
     Right,
     Center,
+    /// Widens the gaps between words to fill the width with every line but the last of each
+    /// paragraph, which align like `Start`.
     Justified,
 }
 
+impl TextAlign {
+    /// The offset of a line `width` wide in a rect `max_width` wide.
+    pub(crate) fn offset(self, rtl: bool, max_width: u32, width: u32) -> i32 {
+        let space = max_width.saturating_sub(width) as i32;
+        match (self, rtl) {
+            (Self::Left, _) | (Self::Start | Self::Justified, false) | (Self::End, true) => 0,
+            (Self::Right, _) | (Self::Start | Self::Justified, true) | (Self::End, false) => space,
+            (Self::Center, _) => space / 2,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, Default)]
 pub enum TextCrossAlign {
     #[default]
@@ -36,6 +67,28 @@ pub enum TextCrossAlign {
     End,
 }
 
+/// How `Canvas::draw_text_bounded` splits a word too long for a line of its own. Lines otherwise
+/// break between words and after hyphens.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TextWrap {
+    #[default]
+    Split,
+    /// Ends the first part of a split word with a hyphen.
+    Hyphenate,
+}
+
+/// What `Canvas::draw_text_bounded` does with lines wider than its rect.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TextOverflow {
+    /// Wraps them into several lines, split words following `TextWrap`.
+    #[default]
+    Wrap,
+    /// Leaves out the clusters that don't fit, lines only breaking at newlines.
+    Clip,
+    /// Like `Clip`, ending cut lines with "…".
+    Ellipsis,
+}
+
 #[derive(Copy, Clone, Debug, Default)]
 pub struct TextPadding {
     pub left: u16,
@@ -44,6 +97,56 @@ pub struct TextPadding {
     pub bottom: u16,
 }
 
+/// The spacing of text drawn on a canvas.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TextSpacing {
+    /// The space added between grapheme clusters, besides kerning.
+    pub letter_spacing: i32,
+    /// How far apart lines of bounded text are, the glyphs height if `None`.
+    pub line_height: Option<u32>,
+}
+
+impl TextSpacing {
+    /// How far apart lines of glyphs `glyphs_height` high are.
+    pub(crate) fn line_height(self, glyphs_height: u32) -> u32 {
+        self.line_height.unwrap_or(glyphs_height)
+    }
+}
+
+/// How `Canvas::draw_text_bounded` lays out text in its rect.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TextOptions {
+    pub align: TextAlign,
+    pub cross_align: TextCrossAlign,
+    pub padding: TextPadding,
+    pub wrap: TextWrap,
+    pub overflow: TextOverflow,
+    pub spacing: TextSpacing,
+}
+
+/// The area covered by text drawn by `Canvas::draw_text_bounded`, as high as the glyphs of
+/// every line.
+#[derive(Clone, Debug, Default)]
+pub struct TextBounds {
+    /// The union of `lines`, empty at the top left of the text if there are none.
+    pub rect: Rect,
+    /// The rect of every line, from the top.
+    pub lines: Vec<Rect>,
+}
+
+impl TextBounds {
+    pub(crate) fn new(origin: Point, lines: Vec<Rect>) -> Self {
+        let empty = Rect::new(origin.x, origin.y, 0, 0);
+        let rect = lines.iter().fold(None, |rect: Option<Rect>, line| {
+            Some(rect.map_or(*line, |rect| rect.union(line)))
+        });
+        Self {
+            rect: rect.unwrap_or(empty),
+            lines,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct GlyphMetrics {
     pub min_x: i32,
@@ -140,9 +243,18 @@ impl Rect {
     pub const fn point(&self) -> Point {
         Point::new(self.x, self.y)
     }
+
+    /// The smallest rect containing both rects.
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.w as i32).max(other.x + other.w as i32);
+        let bottom = (self.y + self.h as i32).max(other.y + other.h as i32);
+        Rect::new(x, y, (right - x) as u32, (bottom - y) as u32)
+    }
 }
 
-#[derive(Copy, Clone, Debug, Default)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub struct Color {
     pub r: u8,
     pub g: u8,